@@ -1,5 +1,7 @@
+use crate::{multiformats::decode_content_address, output::split_algorithm_tag};
 use clap::ValueEnum;
-use std::fmt;
+use serde::{Serialize, Serializer};
+use std::{fmt, str::FromStr};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
 pub enum HashAlgorithm {
@@ -11,6 +13,8 @@ pub enum HashAlgorithm {
     #[default]
     #[value(name = "sha256")]
     Sha256,
+    #[value(name = "sha384")]
+    Sha384,
     #[value(name = "sha512")]
     Sha512,
 
@@ -61,6 +65,7 @@ impl fmt::Display for HashAlgorithm {
             HashAlgorithm::Md5 => write!(f, "MD5"),
             HashAlgorithm::Sha1 => write!(f, "SHA-1"),
             HashAlgorithm::Sha256 => write!(f, "SHA-256"),
+            HashAlgorithm::Sha384 => write!(f, "SHA-384"),
             HashAlgorithm::Sha512 => write!(f, "SHA-512"),
             HashAlgorithm::Sha3_224 => write!(f, "SHA3-224"),
             HashAlgorithm::Sha3_256 => write!(f, "SHA3-256"),
@@ -81,6 +86,13 @@ impl fmt::Display for HashAlgorithm {
     }
 }
 
+/// Algorithms serialize as their CLI name (e.g. `"sha3-256"`)
+impl Serialize for HashAlgorithm {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
 impl HashAlgorithm {
     /// Returns true if this algorithm is designed for password hashing (slow)
     pub fn is_password_hash(&self) -> bool {
@@ -101,6 +113,7 @@ impl HashAlgorithm {
             HashAlgorithm::Md5 => "Legacy compatibility (not recommended for security)",
             HashAlgorithm::Sha1 => "Legacy compatibility (not recommended for security)",
             HashAlgorithm::Sha256 => "General purpose, file integrity, digital signatures",
+            HashAlgorithm::Sha384 => "Subresource Integrity, TLS certificates",
             HashAlgorithm::Sha512 => "High security requirements, large data",
             HashAlgorithm::Sha3_224 => "SHA-3 standard, compact 224-bit output",
             HashAlgorithm::Sha3_256 => "SHA-3 standard, modern alternative to SHA-256",
@@ -126,6 +139,7 @@ impl HashAlgorithm {
             HashAlgorithm::Md5 => 16,
             HashAlgorithm::Sha1 => 20,
             HashAlgorithm::Sha256 | HashAlgorithm::Blake2s => 32,
+            HashAlgorithm::Sha384 => 48,
             HashAlgorithm::Sha512 | HashAlgorithm::Blake2b => 64,
             HashAlgorithm::Sha3_224 => 28,
             HashAlgorithm::Sha3_256 => 32,
@@ -151,6 +165,79 @@ impl HashAlgorithm {
         )
     }
 
+    /// Returns the CLI name of this algorithm (e.g. `sha3-256`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha384 => "sha384",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha3_224 => "sha3-224",
+            HashAlgorithm::Sha3_256 => "sha3-256",
+            HashAlgorithm::Sha3_384 => "sha3-384",
+            HashAlgorithm::Sha3_512 => "sha3-512",
+            HashAlgorithm::Shake128 => "shake128",
+            HashAlgorithm::Shake256 => "shake256",
+            HashAlgorithm::Blake2b => "blake2b",
+            HashAlgorithm::Blake2s => "blake2s",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::XxHash32 => "xxh32",
+            HashAlgorithm::XxHash64 => "xxh64",
+            HashAlgorithm::XxHash3 => "xxh3",
+            HashAlgorithm::Bcrypt => "bcrypt",
+            HashAlgorithm::Scrypt => "scrypt",
+            HashAlgorithm::Argon2 => "argon2",
+        }
+    }
+
+    /// Returns the tag used for this algorithm in BSD-style checksum lines
+    /// (`SHA256 (file) = ...`), matching coreutils and xxhsum where they exist
+    pub fn bsd_tag(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha1 => "SHA1",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Sha384 => "SHA384",
+            HashAlgorithm::Sha512 => "SHA512",
+            HashAlgorithm::Sha3_224 => "SHA3-224",
+            HashAlgorithm::Sha3_256 => "SHA3-256",
+            HashAlgorithm::Sha3_384 => "SHA3-384",
+            HashAlgorithm::Sha3_512 => "SHA3-512",
+            HashAlgorithm::Shake128 => "SHAKE128",
+            HashAlgorithm::Shake256 => "SHAKE256",
+            HashAlgorithm::Blake2b => "BLAKE2b",
+            HashAlgorithm::Blake2s => "BLAKE2s",
+            HashAlgorithm::Blake3 => "BLAKE3",
+            HashAlgorithm::XxHash32 => "XXH32",
+            HashAlgorithm::XxHash64 => "XXH64",
+            HashAlgorithm::XxHash3 => "XXH3",
+            HashAlgorithm::Bcrypt => "BCRYPT",
+            HashAlgorithm::Scrypt => "SCRYPT",
+            HashAlgorithm::Argon2 => "ARGON2",
+        }
+    }
+
+    /// Looks up an algorithm by its BSD checksum tag
+    pub fn from_bsd_tag(tag: &str) -> Option<HashAlgorithm> {
+        HashAlgorithm::value_variants()
+            .iter()
+            .copied()
+            .find(|alg| alg.is_fast_hash() && alg.bsd_tag() == tag)
+    }
+
+    /// Returns the algorithms whose default digest is `len` bytes long.
+    ///
+    /// Password hashing algorithms are never returned: their output depends on
+    /// a salt and parameters that cannot be recovered from the digest alone.
+    pub fn candidates_for_length(len: usize) -> Vec<HashAlgorithm> {
+        HashAlgorithm::value_variants()
+            .iter()
+            .copied()
+            .filter(|alg| alg.is_fast_hash() && alg.output_size() == len)
+            .collect()
+    }
+
     /// Returns true if this algorithm is cryptographically secure
     pub fn is_cryptographic(&self) -> bool {
         !matches!(
@@ -159,3 +246,57 @@ impl HashAlgorithm {
         )
     }
 }
+
+/// Algorithm choice for verification: either a specific algorithm or
+/// automatic detection from the expected hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgorithmSelection {
+    Auto,
+    Specific(HashAlgorithm),
+}
+
+impl AlgorithmSelection {
+    /// Reconcile an optional `--algorithm` with the tag of a tagged expected
+    /// digest (`sha256:...`, `sha512-...`), or the algorithm named by a CID or
+    /// multihash.
+    ///
+    /// The tag is used when no algorithm was given, and SHA-256 when there is
+    /// neither. A specific algorithm contradicting the tag is an error.
+    pub fn resolve(selection: Option<Self>, expected: &str) -> anyhow::Result<Self> {
+        let tag = split_algorithm_tag(expected)
+            .0
+            .or_else(|| decode_content_address(expected).map(|result| result.algorithm));
+        match (selection, tag) {
+            (Some(AlgorithmSelection::Specific(algorithm)), Some(tag)) if algorithm != tag => {
+                anyhow::bail!(
+                    "Expected hash is tagged as {} but --algorithm is {}",
+                    tag.name(),
+                    algorithm.name()
+                )
+            }
+            (Some(selection), _) => Ok(selection),
+            (None, Some(tag)) => Ok(AlgorithmSelection::Specific(tag)),
+            (None, None) => Ok(AlgorithmSelection::Specific(HashAlgorithm::Sha256)),
+        }
+    }
+}
+
+impl FromStr for AlgorithmSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(AlgorithmSelection::Auto);
+        }
+        HashAlgorithm::from_str(s, true).map(AlgorithmSelection::Specific)
+    }
+}
+
+impl fmt::Display for AlgorithmSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlgorithmSelection::Auto => write!(f, "auto"),
+            AlgorithmSelection::Specific(algorithm) => write!(f, "{algorithm}"),
+        }
+    }
+}
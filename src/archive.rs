@@ -0,0 +1,416 @@
+// Hashing the members of tar and zip archives without extracting them
+use crate::{
+    algorithms::HashAlgorithm,
+    checksum::{parse_line, CheckEvent, CheckOptions, CheckStatus, CheckSummary, ManifestEntry},
+    core::HashForge,
+    decompress::Compression,
+    output::HashResult,
+};
+use anyhow::{Context, Result};
+use serde::{Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{BufRead, BufReader, Read, Seek},
+    path::Path,
+};
+
+/// Container and compression of an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A tar file, possibly compressed as a whole
+    Tar(Option<Compression>),
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Detect the format from the magic bytes at the start of `path`.
+    ///
+    /// Anything that is neither a zip file nor compressed is read as a plain tar.
+    pub fn detect(path: &Path) -> Result<Self> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open archive: {}", path.display()))?;
+        let mut magic = Vec::with_capacity(6);
+        file.take(6).read_to_end(&mut magic)?;
+
+        Ok(
+            if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+                ArchiveFormat::Zip
+            } else {
+                ArchiveFormat::Tar(Compression::from_magic(&magic))
+            },
+        )
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveFormat::Tar(None) => write!(f, "tar"),
+            ArchiveFormat::Tar(Some(compression)) => write!(f, "tar.{}", compression.extension()),
+            ArchiveFormat::Zip => write!(f, "zip"),
+        }
+    }
+}
+
+impl Serialize for ArchiveFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A regular file stored in an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveMember {
+    /// Path inside the archive, using `/` as separator and without a leading `./`
+    pub path: String,
+    pub size: u64,
+    pub digest: HashResult,
+}
+
+/// Result of checking an archive against a manifest
+#[derive(Debug, Clone)]
+pub struct ArchiveCheck {
+    pub format: ArchiveFormat,
+    pub summary: CheckSummary,
+    /// Members the manifest does not list; they do not affect the result
+    pub unlisted: Vec<String>,
+}
+
+/// A manifest line waiting for its member
+struct Listed {
+    line: usize,
+    entry: ManifestEntry,
+    algorithm: HashAlgorithm,
+    expected: Vec<u8>,
+    status: Option<CheckStatus>,
+}
+
+impl HashForge {
+    /// Hash every regular file in an archive, in archive order.
+    ///
+    /// Members are decompressed and hashed as they are read; nothing is
+    /// written to disk. Directories, links and other special entries are
+    /// skipped.
+    pub fn hash_archive<F>(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        mut on_member: F,
+    ) -> Result<ArchiveFormat>
+    where
+        F: FnMut(ArchiveMember) -> Result<()>,
+    {
+        check_algorithm(algorithm)?;
+        let member_forge = HashForge::new().with_progress(false);
+        self.for_each_member(path, |name, size, reader| {
+            let digest = member_forge.hash_reader(reader, size, algorithm)?;
+            on_member(ArchiveMember {
+                path: name.to_string(),
+                size,
+                digest,
+            })
+        })
+    }
+
+    /// Verify archive members against a checksum manifest, like `check` does
+    /// for files on disk.
+    ///
+    /// GNU-format lines are checked with `algorithm`; BSD tag lines use the
+    /// algorithm they name. Listed paths missing from the archive are
+    /// unreadable unless `ignore_missing` is set. If a tar holds several
+    /// members with the same path, the last one counts, as it would when
+    /// extracted. `on_event` is called for each line in manifest order once
+    /// the whole archive has been read.
+    pub fn check_archive<R, F>(
+        &self,
+        path: &Path,
+        manifest: R,
+        algorithm: HashAlgorithm,
+        options: &CheckOptions,
+        mut on_event: F,
+    ) -> Result<ArchiveCheck>
+    where
+        R: BufRead,
+        F: FnMut(&CheckEvent),
+    {
+        let mut summary = CheckSummary::default();
+        let mut lines: Vec<(usize, Option<Listed>)> = Vec::new();
+        for (index, line) in manifest.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let listed = parse_line(&line).and_then(|(tag, entry)| {
+                let algorithm = tag.unwrap_or(algorithm);
+                let expected = hex::decode(&entry.digest).ok()?;
+                (expected.len() == algorithm.output_size()).then_some(Listed {
+                    line: index + 1,
+                    entry,
+                    algorithm,
+                    expected,
+                    status: None,
+                })
+            });
+            match listed {
+                Some(ref listed) => check_algorithm(listed.algorithm)?,
+                None => summary.improperly_formatted += 1,
+            }
+            lines.push((index + 1, listed));
+        }
+        summary.properly_formatted = lines.iter().filter(|(_, l)| l.is_some()).count();
+
+        let mut by_path: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, (_, listed)) in lines.iter().enumerate() {
+            if let Some(listed) = listed {
+                let path = listed.entry.path.trim_start_matches("./").to_string();
+                by_path.entry(path).or_default().push(index);
+            }
+        }
+
+        let member_forge = HashForge::new().with_progress(false);
+        let mut unlisted = Vec::new();
+        let format = self.for_each_member(path, |name, size, reader| {
+            let Some(indices) = by_path.get(name) else {
+                unlisted.push(name.to_string());
+                return Ok(());
+            };
+            let mut algorithms: Vec<HashAlgorithm> = Vec::new();
+            for &index in indices {
+                let algorithm = lines[index].1.as_ref().map(|l| l.algorithm);
+                if let Some(algorithm) = algorithm.filter(|a| !algorithms.contains(a)) {
+                    algorithms.push(algorithm);
+                }
+            }
+
+            // A member listed under several algorithms is buffered to be hashed with each
+            let digests: Result<Vec<(HashAlgorithm, HashResult)>> = if algorithms.len() == 1 {
+                member_forge
+                    .hash_reader(reader, size, algorithms[0])
+                    .map(|digest| vec![(algorithms[0], digest)])
+            } else {
+                let mut data = Vec::new();
+                reader
+                    .read_to_end(&mut data)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| {
+                        algorithms
+                            .iter()
+                            .map(|&a| Ok((a, member_forge.hash_bytes(&data, a)?)))
+                            .collect()
+                    })
+            };
+
+            for &index in indices {
+                let Some(listed) = lines[index].1.as_mut() else {
+                    continue;
+                };
+                listed.status = Some(match digests {
+                    Ok(ref digests) => {
+                        let (_, digest) = digests
+                            .iter()
+                            .find(|(a, _)| *a == listed.algorithm)
+                            .expect("member hashed with every listed algorithm");
+                        if digest.constant_time_eq(&listed.expected) {
+                            CheckStatus::Ok
+                        } else {
+                            CheckStatus::Failed
+                        }
+                    }
+                    Err(ref e) => CheckStatus::Unreadable {
+                        error: format!("{e:#}"),
+                    },
+                });
+            }
+            Ok(())
+        })?;
+
+        for (line, listed) in lines {
+            let Some(listed) = listed else {
+                on_event(&CheckEvent {
+                    line,
+                    path: None,
+                    status: CheckStatus::ImproperlyFormatted,
+                });
+                continue;
+            };
+            let status = match listed.status {
+                Some(status) => status,
+                None if options.ignore_missing => continue,
+                None => CheckStatus::Unreadable {
+                    error: "not found in archive".to_string(),
+                },
+            };
+            match status {
+                CheckStatus::Ok => summary.verified += 1,
+                CheckStatus::Failed => summary.mismatched += 1,
+                _ => summary.unreadable += 1,
+            }
+            on_event(&CheckEvent {
+                line: listed.line,
+                path: Some(listed.entry.path),
+                status,
+            });
+        }
+
+        Ok(ArchiveCheck {
+            format,
+            summary,
+            unlisted,
+        })
+    }
+
+    /// Call `visit` with the path, size and contents of each regular file in
+    /// the archive at `path`
+    fn for_each_member<F>(&self, path: &Path, mut visit: F) -> Result<ArchiveFormat>
+    where
+        F: FnMut(&str, u64, &mut dyn Read) -> Result<()>,
+    {
+        let format = ArchiveFormat::detect(path)?;
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open archive: {}", path.display()))?;
+        let progress_bar = self.progress_bar(file.metadata()?.len());
+        let file = BufReader::new(file);
+
+        let result = match (format, &progress_bar) {
+            (ArchiveFormat::Zip, Some(pb)) => visit_zip(pb.wrap_read(file), &mut visit),
+            (ArchiveFormat::Zip, None) => visit_zip(file, &mut visit),
+            (ArchiveFormat::Tar(compression), _) => {
+                let reader: Box<dyn Read> = match &progress_bar {
+                    Some(pb) => Box::new(pb.wrap_read(file)),
+                    None => Box::new(file),
+                };
+                match compression {
+                    Some(compression) => compression
+                        .decoder(reader)
+                        .and_then(|reader| visit_tar(reader, &mut visit)),
+                    None => visit_tar(reader, &mut visit),
+                }
+            }
+        };
+        result.with_context(|| format!("Failed to read {format} archive: {}", path.display()))?;
+
+        if let Some(pb) = progress_bar {
+            pb.finish_with_message("Archive hashed");
+        }
+        Ok(format)
+    }
+}
+
+/// Member hashes are compared against manifests, so password hashes, which
+/// salt every digest, cannot be used
+fn check_algorithm(algorithm: HashAlgorithm) -> Result<()> {
+    if algorithm.is_password_hash() {
+        anyhow::bail!(
+            "Archive members need a fast hash; {} is a password hash",
+            algorithm.name()
+        );
+    }
+    Ok(())
+}
+
+fn visit_tar<R, F>(reader: R, visit: &mut F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(&str, u64, &mut dyn Read) -> Result<()>,
+{
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = member_name(&entry.path()?.to_string_lossy());
+        let size = entry.size();
+        visit(&name, size, &mut entry)?;
+    }
+    Ok(())
+}
+
+fn visit_zip<R, F>(reader: R, visit: &mut F) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(&str, u64, &mut dyn Read) -> Result<()>,
+{
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for index in 0..archive.len() {
+        let mut member = archive.by_index(index)?;
+        if member.is_dir() || member.is_symlink() {
+            continue;
+        }
+        let name = member_name(member.name());
+        let size = member.size();
+        visit(&name, size, &mut member)?;
+    }
+    Ok(())
+}
+
+/// Member path in the form written to manifests
+fn member_name(name: &str) -> String {
+    let name = name.replace('\\', "/");
+    name.trim_start_matches("./").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_archive() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let tar_path = dir.join("release.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            fs::File::create(&tar_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for (name, data) in [("./bin/tool", &b"binary"[..]), ("README", b"read me")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        assert_eq!(
+            ArchiveFormat::detect(&tar_path).unwrap(),
+            ArchiveFormat::Tar(Some(Compression::Gzip))
+        );
+
+        let forge = HashForge::new().with_progress(false);
+        let mut members = Vec::new();
+        forge
+            .hash_archive(&tar_path, HashAlgorithm::Sha256, |member| {
+                members.push(member);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].path, "bin/tool");
+        let tool = forge.hash_bytes(b"binary", HashAlgorithm::Sha256).unwrap();
+        assert_eq!(members[0].digest.bytes, tool.bytes);
+
+        let manifest = format!(
+            "{}  bin/tool\n{}  ./README\n{}  missing\n",
+            tool.to_hex(),
+            tool.to_hex(),
+            tool.to_hex()
+        );
+        let mut events = Vec::new();
+        let check = forge
+            .check_archive(
+                &tar_path,
+                manifest.as_bytes(),
+                HashAlgorithm::Sha256,
+                &CheckOptions::default(),
+                |event| events.push(event.clone()),
+            )
+            .unwrap();
+        assert_eq!(check.summary.verified, 1);
+        assert_eq!(check.summary.mismatched, 1);
+        assert_eq!(check.summary.unreadable, 1);
+        assert!(check.unlisted.is_empty());
+        assert_eq!(events.len(), 3);
+    }
+}
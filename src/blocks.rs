@@ -0,0 +1,344 @@
+// Per-block hash lists for finding which parts of a file changed
+use crate::{algorithms::HashAlgorithm, core::HashForge, output::HashResult};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+/// First line of a block list file; the number is the format version
+const HEADER: &str = "hash-forge-blocks 1";
+
+/// Block size used when none is given
+pub const DEFAULT_BLOCK_SIZE: u64 = 1024 * 1024;
+
+/// Digests of consecutive fixed-size blocks of a file.
+///
+/// The last block may be shorter than `block_size`; an empty file has no
+/// blocks. `top` is the digest of the concatenated raw block digests, so two
+/// lists with the same algorithm and block size describe the same contents
+/// exactly when their top digests are equal.
+#[derive(Debug, Clone)]
+pub struct BlockList {
+    pub algorithm: HashAlgorithm,
+    pub block_size: u64,
+    pub file_size: u64,
+    pub blocks: Vec<Vec<u8>>,
+    pub top: HashResult,
+}
+
+impl BlockList {
+    /// Write the list in its text form:
+    ///
+    /// ```text
+    /// hash-forge-blocks 1
+    /// algorithm sha256
+    /// block-size 1048576
+    /// file-size 3145728
+    /// top <hex>
+    /// <hex digest of block 0>
+    /// <hex digest of block 1>
+    /// ...
+    /// ```
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{HEADER}")?;
+        writeln!(writer, "algorithm {}", self.algorithm.name())?;
+        writeln!(writer, "block-size {}", self.block_size)?;
+        writeln!(writer, "file-size {}", self.file_size)?;
+        writeln!(writer, "top {}", self.top.to_hex())?;
+        for block in &self.blocks {
+            writeln!(writer, "{}", hex::encode(block))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a list written by [`BlockList::write`].
+    ///
+    /// The block count must match the file size and the top digest must match
+    /// the blocks, so a truncated or edited list is rejected rather than
+    /// blamed on the file it is compared with.
+    pub fn parse<R: BufRead>(reader: R, path: &Path) -> Result<Self> {
+        let mut lines = reader.lines();
+        let mut next = |what: &str| -> Result<String> {
+            match lines.next() {
+                Some(line) => Ok(line?),
+                None => anyhow::bail!("{}: missing {what}", path.display()),
+            }
+        };
+        if next("header")? != HEADER {
+            anyhow::bail!("{}: not a hash-forge block list", path.display());
+        }
+        let mut field = |name: &str| -> Result<String> {
+            let line = next(name)?;
+            line.strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix(' '))
+                .map(str::to_string)
+                .with_context(|| format!("{}: expected '{name}', found '{line}'", path.display()))
+        };
+
+        let algorithm = HashAlgorithm::from_str(&field("algorithm")?, false)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        let block_size: u64 = field("block-size")?
+            .parse()
+            .with_context(|| format!("{}: invalid block size", path.display()))?;
+        let file_size: u64 = field("file-size")?
+            .parse()
+            .with_context(|| format!("{}: invalid file size", path.display()))?;
+        let top = hex::decode(field("top")?)
+            .with_context(|| format!("{}: invalid top digest", path.display()))?;
+        if block_size == 0 {
+            anyhow::bail!("{}: block size must not be zero", path.display());
+        }
+
+        let mut blocks = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let digest = hex::decode(&line)
+                .ok()
+                .filter(|digest| digest.len() == algorithm.output_size());
+            match digest {
+                Some(digest) => blocks.push(digest),
+                None => anyhow::bail!(
+                    "{}:{}: not a {} digest",
+                    path.display(),
+                    index + 6,
+                    algorithm.name()
+                ),
+            }
+        }
+
+        if blocks.len() as u64 != file_size.div_ceil(block_size) {
+            anyhow::bail!(
+                "{}: {} blocks listed, but a {file_size}-byte file has {}",
+                path.display(),
+                blocks.len(),
+                file_size.div_ceil(block_size)
+            );
+        }
+        let list = Self {
+            algorithm,
+            block_size,
+            file_size,
+            top: top_digest(&blocks, algorithm)?,
+            blocks,
+        };
+        if list.top.bytes != top {
+            anyhow::bail!(
+                "{}: top digest does not match the block digests",
+                path.display()
+            );
+        }
+        Ok(list)
+    }
+}
+
+/// Bytes `start..end` of a file, covering blocks `first_block..=last_block`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ByteRange {
+    pub start: u64,
+    /// Exclusive
+    pub end: u64,
+    pub first_block: u64,
+    pub last_block: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Result of comparing a file with a block list
+#[derive(Debug, Clone)]
+pub struct BlockComparison {
+    /// The file's current block list
+    pub current: BlockList,
+    /// Blocks present in the file, the list or both
+    pub blocks: u64,
+    /// Blocks whose digests differ or that only one side has
+    pub differing: u64,
+    /// Differing bytes, with adjacent blocks merged into one range
+    pub ranges: Vec<ByteRange>,
+}
+
+impl BlockComparison {
+    pub fn is_identical(&self) -> bool {
+        self.differing == 0
+    }
+}
+
+impl HashForge {
+    /// Hash `path` in blocks of `block_size` bytes.
+    ///
+    /// The file is read once, front to back, with a single progress bar for
+    /// the whole file.
+    pub fn block_list(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        block_size: u64,
+    ) -> Result<BlockList> {
+        if algorithm.is_password_hash() {
+            anyhow::bail!(
+                "Block lists need a fast hash; {} is a password hash",
+                algorithm.name()
+            );
+        }
+        if block_size == 0 {
+            anyhow::bail!("Block size must not be zero");
+        }
+
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let file_size = file.metadata()?.len();
+        let progress_bar = self.progress_bar(file_size);
+        let mut reader: Box<dyn Read> = match &progress_bar {
+            Some(pb) => Box::new(pb.wrap_read(BufReader::new(file))),
+            None => Box::new(BufReader::new(file)),
+        };
+
+        let blocks_forge = HashForge::new().with_progress(false);
+        let mut blocks = Vec::new();
+        let mut remaining = file_size;
+        while remaining > 0 {
+            let len = remaining.min(block_size);
+            let mut block = (&mut reader).take(len);
+            let digest = blocks_forge.hash_reader(&mut block, len, algorithm)?;
+            if block.limit() != 0 {
+                anyhow::bail!("{} shrank while being read", path.display());
+            }
+            blocks.push(digest.bytes);
+            remaining -= len;
+        }
+        if reader.read(&mut [0])? != 0 {
+            anyhow::bail!("{} grew while being read", path.display());
+        }
+        if let Some(pb) = progress_bar {
+            pb.finish_with_message("Hash computed");
+        }
+
+        Ok(BlockList {
+            algorithm,
+            block_size,
+            file_size,
+            top: top_digest(&blocks, algorithm)?,
+            blocks,
+        })
+    }
+
+    /// Hash `path` with the algorithm and block size of `list` and find the
+    /// byte ranges where the two differ.
+    ///
+    /// If the file is longer or shorter than the list describes, the bytes
+    /// only one side has count as a differing range.
+    pub fn compare_blocks(&self, path: &Path, list: &BlockList) -> Result<BlockComparison> {
+        let current = self.block_list(path, list.algorithm, list.block_size)?;
+        let blocks = current.blocks.len().max(list.blocks.len()) as u64;
+        let size = current.file_size.max(list.file_size);
+
+        let mut ranges: Vec<ByteRange> = Vec::new();
+        let mut differing = 0;
+        for index in 0..blocks {
+            let i = index as usize;
+            if current.blocks.get(i) == list.blocks.get(i) {
+                continue;
+            }
+            differing += 1;
+            let end = ((index + 1) * list.block_size).min(size);
+            match ranges.last_mut() {
+                Some(range) if range.last_block + 1 == index => {
+                    range.last_block = index;
+                    range.end = end;
+                }
+                _ => ranges.push(ByteRange {
+                    start: index * list.block_size,
+                    end,
+                    first_block: index,
+                    last_block: index,
+                }),
+            }
+        }
+
+        Ok(BlockComparison {
+            current,
+            blocks,
+            differing,
+            ranges,
+        })
+    }
+}
+
+/// Digest of the concatenated raw block digests
+fn top_digest(blocks: &[Vec<u8>], algorithm: HashAlgorithm) -> Result<HashResult> {
+    HashForge::new()
+        .with_progress(false)
+        .hash_bytes(&blocks.concat(), algorithm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_blocks() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let file = dir.join("image");
+        let mut data = vec![0u8; 10 * 1024];
+        fs::write(&file, &data).unwrap();
+
+        let forge = HashForge::new().with_progress(false);
+        let list = forge
+            .block_list(&file, HashAlgorithm::Sha256, 1024)
+            .unwrap();
+        assert_eq!(list.blocks.len(), 10);
+        let mut text = Vec::new();
+        list.write(&mut text).unwrap();
+        let parsed = BlockList::parse(text.as_slice(), Path::new("list")).unwrap();
+        assert_eq!(parsed.top.bytes, list.top.bytes);
+        assert!(forge.compare_blocks(&file, &parsed).unwrap().is_identical());
+
+        // Damage blocks 2 and 3, then append half a block
+        data[2048] = 1;
+        data[4095] = 1;
+        data.extend_from_slice(&[0; 512]);
+        fs::write(&file, &data).unwrap();
+        let comparison = forge.compare_blocks(&file, &parsed).unwrap();
+        assert_eq!(comparison.blocks, 11);
+        assert_eq!(comparison.differing, 3);
+        assert_eq!(
+            comparison.ranges,
+            [
+                ByteRange {
+                    start: 2048,
+                    end: 4096,
+                    first_block: 2,
+                    last_block: 3
+                },
+                ByteRange {
+                    start: 10240,
+                    end: 10752,
+                    first_block: 10,
+                    last_block: 10
+                },
+            ]
+        );
+
+        // A list whose blocks no longer match its top digest is rejected
+        let text = String::from_utf8(text)
+            .unwrap()
+            .replace(&hex::encode(&list.blocks[0]), &hex::encode([0; 32]));
+        assert!(BlockList::parse(text.as_bytes(), Path::new("list")).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+// Persistent digest cache keyed on file metadata
+use crate::{algorithms::HashAlgorithm, output::HashResult};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the on-disk format; files with another version are ignored
+const CACHE_VERSION: u32 = 1;
+
+/// Files modified this recently are not cached: on filesystems with coarse
+/// timestamps a write right after hashing could leave mtime unchanged
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// Cache shared by every [`HashForge`](crate::core::HashForge) it is attached to
+pub type SharedCache = Arc<Mutex<HashCache>>;
+
+/// Metadata a cached digest is valid for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Stamp {
+    device: u64,
+    inode: u64,
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+impl Stamp {
+    /// Stamp of a regular file, or `None` if it must not be cached
+    fn of(metadata: &fs::Metadata) -> Option<Self> {
+        if !metadata.is_file() {
+            return None;
+        }
+        let mtime = metadata.modified().ok()?;
+        let since_epoch = mtime.duration_since(UNIX_EPOCH).ok()?;
+        #[cfg(unix)]
+        let (device, inode) = {
+            use std::os::unix::fs::MetadataExt;
+            (metadata.dev(), metadata.ino())
+        };
+        #[cfg(not(unix))]
+        let (device, inode) = (0, 0);
+
+        Some(Self {
+            device,
+            inode,
+            size: metadata.len(),
+            mtime_secs: since_epoch.as_secs(),
+            mtime_nanos: since_epoch.subsec_nanos(),
+        })
+    }
+
+    fn is_racy(&self) -> bool {
+        let mtime = UNIX_EPOCH + Duration::new(self.mtime_secs, self.mtime_nanos);
+        match SystemTime::now().duration_since(mtime) {
+            Ok(age) => age < RACY_WINDOW,
+            // Timestamps in the future can't be trusted either
+            Err(_) => true,
+        }
+    }
+}
+
+/// One cached digest as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheRecord {
+    path: PathBuf,
+    algorithm: String,
+    #[serde(flatten)]
+    stamp: Stamp,
+    digest: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Vec<CacheRecord>,
+}
+
+/// On-disk map from (canonical path, algorithm) to digest.
+///
+/// An entry is only used while the file's device, inode, size and mtime are
+/// all unchanged. Changes are kept in memory until [`HashCache::save`].
+#[derive(Debug)]
+pub struct HashCache {
+    path: PathBuf,
+    entries: HashMap<(PathBuf, &'static str), (Stamp, Vec<u8>)>,
+    refresh: bool,
+    dirty: bool,
+}
+
+/// Counters reported by [`HashCache::prune`]
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PruneSummary {
+    pub kept: usize,
+    pub removed: usize,
+}
+
+impl HashCache {
+    /// Default cache file location.
+    ///
+    /// `HASH_FORGE_CACHE` overrides it; otherwise the platform cache directory
+    /// is used (`$XDG_CACHE_HOME` or `~/.cache` on Linux, `~/Library/Caches`
+    /// on macOS, `%LOCALAPPDATA%` on Windows).
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("HASH_FORGE_CACHE") {
+            return Some(PathBuf::from(path));
+        }
+        let base = if cfg!(windows) {
+            std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+        } else {
+            std::env::var_os("XDG_CACHE_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        }?;
+        Some(base.join("hash-forge").join("digests.json"))
+    }
+
+    /// Load the cache at `path`; a missing file gives an empty cache
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut cache = Self {
+            path: path.to_path_buf(),
+            entries: HashMap::new(),
+            refresh: false,
+            dirty: false,
+        };
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(cache),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read cache: {}", path.display()))
+            }
+        };
+        let file: CacheFile = serde_json::from_slice(&data)
+            .with_context(|| format!("Corrupt cache file: {}", path.display()))?;
+        if file.version != CACHE_VERSION {
+            return Ok(cache);
+        }
+
+        for record in file.entries {
+            let Ok(algorithm) = HashAlgorithm::from_str(&record.algorithm, false) else {
+                continue;
+            };
+            let Ok(digest) = hex::decode(&record.digest) else {
+                continue;
+            };
+            cache
+                .entries
+                .insert((record.path, algorithm.name()), (record.stamp, digest));
+        }
+        Ok(cache)
+    }
+
+    /// Ignore existing entries and store fresh digests for every file hashed
+    pub fn refreshing(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Share this cache between several hashers
+    pub fn shared(self) -> SharedCache {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Cached digest of `path`, if its metadata still matches
+    pub fn lookup(&self, path: &Path, algorithm: HashAlgorithm) -> Option<HashResult> {
+        if self.refresh {
+            return None;
+        }
+        let key = (fs::canonicalize(path).ok()?, algorithm.name());
+        let (stamp, digest) = self.entries.get(&key)?;
+        let current = Stamp::of(&fs::metadata(path).ok()?)?;
+        (current == *stamp).then(|| HashResult::new(digest.clone(), algorithm))
+    }
+
+    /// Remember the digest of `path`.
+    ///
+    /// `before` is the file's metadata from before it was read. Nothing is
+    /// stored if the file changed while being hashed or was modified too
+    /// recently to be sure it won't change again within the same mtime.
+    pub fn insert(&mut self, path: &Path, before: &fs::Metadata, result: &HashResult) {
+        let Some(stamp) = Stamp::of(before) else {
+            return;
+        };
+        let after = fs::metadata(path).ok().and_then(|m| Stamp::of(&m));
+        if after != Some(stamp) || stamp.is_racy() {
+            return;
+        }
+        let Ok(canonical) = fs::canonicalize(path) else {
+            return;
+        };
+        self.entries.insert(
+            (canonical, result.algorithm.name()),
+            (stamp, result.bytes.clone()),
+        );
+        self.dirty = true;
+    }
+
+    /// Drop entries whose file is gone or no longer matches its metadata
+    pub fn prune(&mut self) -> PruneSummary {
+        let before = self.entries.len();
+        self.entries.retain(|(path, _), (stamp, _)| {
+            fs::metadata(path)
+                .ok()
+                .and_then(|m| Stamp::of(&m))
+                .is_some_and(|current| current == *stamp)
+        });
+        let removed = before - self.entries.len();
+        self.dirty |= removed > 0;
+        PruneSummary {
+            kept: self.entries.len(),
+            removed,
+        }
+    }
+
+    /// Write the cache back if it changed, replacing the file atomically
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|((path, algorithm), (stamp, digest))| CacheRecord {
+                path: path.clone(),
+                algorithm: algorithm.to_string(),
+                stamp: *stamp,
+                digest: hex::encode(digest),
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.path, &a.algorithm).cmp(&(&b.path, &b.algorithm)));
+        let data = serde_json::to_vec(&CacheFile {
+            version: CACHE_VERSION,
+            entries,
+        })?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let temp = self
+            .path
+            .with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&temp, data)
+            .with_context(|| format!("Failed to write cache: {}", temp.display()))?;
+        fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write cache: {}", self.path.display()))?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let file = dir.join("data");
+        fs::write(&file, "hello").unwrap();
+        let old = SystemTime::now() - Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let cache_path = dir.join("cache.json");
+        let mut cache = HashCache::load(&cache_path).unwrap();
+        let result = HashResult::new(vec![1, 2, 3], HashAlgorithm::Sha256);
+        cache.insert(&file, &fs::metadata(&file).unwrap(), &result);
+        cache.save().unwrap();
+
+        let cache = HashCache::load(&cache_path).unwrap();
+        let hit = cache.lookup(&file, HashAlgorithm::Sha256).unwrap();
+        assert_eq!(hit.bytes, result.bytes);
+        assert!(cache.lookup(&file, HashAlgorithm::Sha512).is_none());
+        assert!(cache
+            .refreshing(true)
+            .lookup(&file, HashAlgorithm::Sha256)
+            .is_none());
+
+        // Any change to size or mtime invalidates the entry
+        fs::write(&file, "hello!").unwrap();
+        let mut cache = HashCache::load(&cache_path).unwrap();
+        assert!(cache.lookup(&file, HashAlgorithm::Sha256).is_none());
+        assert_eq!(cache.prune().removed, 1);
+    }
+}
@@ -0,0 +1,455 @@
+// Checksum manifest formats compatible with GNU coreutils and BSD tools
+use crate::{algorithms::HashAlgorithm, core::HashForge};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use std::{fmt, io::BufRead, path::Path};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum ManifestFormat {
+    /// `hash  path` as written by sha256sum (`hash *path` in binary mode)
+    #[default]
+    #[value(name = "gnu")]
+    Gnu,
+    /// `SHA256 (path) = hash` as written by `shasum --tag` and BSD `sha256`
+    #[value(name = "bsd")]
+    Bsd,
+}
+
+impl fmt::Display for ManifestFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestFormat::Gnu => write!(f, "gnu"),
+            ManifestFormat::Bsd => write!(f, "bsd"),
+        }
+    }
+}
+
+impl Serialize for ManifestFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A single line of a checksum manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path as written in the manifest, using `/` as separator
+    pub path: String,
+    /// Hex-encoded digest
+    pub digest: String,
+    /// Whether the file was read in binary mode (`*` marker in GNU format)
+    pub binary: bool,
+}
+
+impl ManifestEntry {
+    pub fn new(path: impl Into<String>, digest: impl Into<String>, binary: bool) -> Self {
+        Self {
+            path: path.into(),
+            digest: digest.into(),
+            binary,
+        }
+    }
+
+    /// Format this entry as a manifest line (without the trailing newline).
+    ///
+    /// Like coreutils, a filename containing a backslash, newline or carriage
+    /// return is escaped and the whole line is prefixed with a backslash.
+    pub fn to_line(&self, algorithm: HashAlgorithm, format: ManifestFormat) -> String {
+        let needs_escape = self.path.contains(['\\', '\n', '\r']);
+        let prefix = if needs_escape { "\\" } else { "" };
+        let path = if needs_escape {
+            escape_filename(&self.path)
+        } else {
+            self.path.clone()
+        };
+
+        match format {
+            ManifestFormat::Gnu => {
+                let mode = if self.binary { '*' } else { ' ' };
+                format!("{prefix}{} {mode}{path}", self.digest)
+            }
+            ManifestFormat::Bsd => {
+                format!("{prefix}{} ({path}) = {}", algorithm.bsd_tag(), self.digest)
+            }
+        }
+    }
+}
+
+/// Parse a manifest line in GNU or BSD tag format.
+///
+/// Returns the algorithm named by a BSD tag (GNU lines carry none) together
+/// with the entry, or `None` if the line is not a properly formatted checksum
+/// line. Leading whitespace, a trailing carriage return and coreutils filename
+/// escapes are handled the same way `sha256sum -c` handles them.
+pub fn parse_line(line: &str) -> Option<(Option<HashAlgorithm>, ManifestEntry)> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let line = line.trim_start_matches([' ', '\t']);
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    let (algorithm, digest, name, binary) = if let Some(parsed) = parse_bsd_line(line) {
+        parsed
+    } else {
+        let (digest, rest) = line.split_once([' ', '\t'])?;
+        let (binary, name) = match rest.strip_prefix('*') {
+            Some(name) => (true, name),
+            None => (false, rest.strip_prefix(' ').unwrap_or(rest)),
+        };
+        (None, digest, name, binary)
+    };
+
+    if name.is_empty() || digest.is_empty() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let path = if escaped {
+        unescape_filename(name)?
+    } else {
+        name.to_string()
+    };
+
+    Some((
+        algorithm,
+        ManifestEntry::new(path, digest.to_ascii_lowercase(), binary),
+    ))
+}
+
+/// Split `TAG (name) = digest` into its parts
+fn parse_bsd_line(line: &str) -> Option<(Option<HashAlgorithm>, &str, &str, bool)> {
+    let (head, digest) = line.rsplit_once(") = ")?;
+    let (tag, name) = head.split_once(" (")?;
+    let algorithm = HashAlgorithm::from_bsd_tag(tag)?;
+    Some((Some(algorithm), digest, name, true))
+}
+
+/// Reverse [`escape_filename`], rejecting unknown escape sequences
+fn unescape_filename(name: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next()? {
+                '\\' => unescaped.push('\\'),
+                'n' => unescaped.push('\n'),
+                'r' => unescaped.push('\r'),
+                _ => return None,
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some(unescaped)
+}
+
+/// Escape a filename the way coreutils does for checksum output
+pub fn escape_filename(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Options for checking manifests, mirroring the flags of `sha256sum -c`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckOptions {
+    /// Don't print OK for each successfully verified file
+    pub quiet: bool,
+    /// Don't print anything; only the exit status reports the result
+    pub status: bool,
+    /// Treat improperly formatted lines as a failure
+    pub strict: bool,
+    /// Warn about each improperly formatted line
+    pub warn: bool,
+    /// Skip files listed in the manifest that don't exist
+    pub ignore_missing: bool,
+}
+
+/// Counters collected while checking one manifest
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct CheckSummary {
+    pub properly_formatted: usize,
+    pub improperly_formatted: usize,
+    pub verified: usize,
+    pub mismatched: usize,
+    pub unreadable: usize,
+}
+
+impl CheckSummary {
+    /// Whether the check succeeded, using the same rules as coreutils
+    pub fn is_success(&self, options: &CheckOptions) -> bool {
+        self.properly_formatted > 0
+            && self.mismatched == 0
+            && self.unreadable == 0
+            && !(options.strict && self.improperly_formatted > 0)
+            && !(options.ignore_missing && self.verified + self.mismatched == 0)
+    }
+}
+
+/// Result of checking one manifest line
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    Unreadable { error: String },
+    ImproperlyFormatted,
+}
+
+/// A checked manifest line, reported as soon as it has been processed
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckEvent {
+    /// 1-based line number in the manifest
+    pub line: usize,
+    /// Listed path (absent for improperly formatted lines)
+    pub path: Option<String>,
+    #[serde(flatten)]
+    pub status: CheckStatus,
+}
+
+impl HashForge {
+    /// Verify every file listed in a checksum manifest.
+    ///
+    /// GNU-format lines are checked with `algorithm`; BSD tag lines use the
+    /// algorithm they name. `on_event` is called for each line as it is
+    /// checked; missing files skipped by `ignore_missing` produce no event.
+    pub fn check_manifest<R, F>(
+        &self,
+        reader: R,
+        algorithm: HashAlgorithm,
+        options: &CheckOptions,
+        mut on_event: F,
+    ) -> Result<CheckSummary>
+    where
+        R: BufRead,
+        F: FnMut(&CheckEvent),
+    {
+        let mut summary = CheckSummary::default();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+
+            let parsed = parse_line(&line).and_then(|(tag, entry)| {
+                let algorithm = tag.unwrap_or(algorithm);
+                (entry.digest.len() == algorithm.output_size() * 2).then_some((algorithm, entry))
+            });
+            let Some((line_algorithm, entry)) = parsed else {
+                summary.improperly_formatted += 1;
+                on_event(&CheckEvent {
+                    line: index + 1,
+                    path: None,
+                    status: CheckStatus::ImproperlyFormatted,
+                });
+                continue;
+            };
+            summary.properly_formatted += 1;
+
+            let path = Path::new(&entry.path);
+            if options.ignore_missing && !path.exists() {
+                continue;
+            }
+
+            let expected = hex::decode(&entry.digest)?;
+            let status = match self.hash_file(path, line_algorithm) {
+                Ok(computed) if computed.constant_time_eq(&expected) => {
+                    summary.verified += 1;
+                    CheckStatus::Ok
+                }
+                Ok(_) => {
+                    summary.mismatched += 1;
+                    CheckStatus::Failed
+                }
+                Err(e) => {
+                    summary.unreadable += 1;
+                    CheckStatus::Unreadable {
+                        error: format!("{e:#}"),
+                    }
+                }
+            };
+            on_event(&CheckEvent {
+                line: index + 1,
+                path: Some(entry.path),
+                status,
+            });
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Print a checked line the way `sha256sum -c` does.
+///
+/// `source` names the manifest and `algorithm` is the one used for GNU lines,
+/// both only appearing in the `--warn` message for malformed lines.
+pub fn print_check_event(
+    event: &CheckEvent,
+    source: &str,
+    algorithm: HashAlgorithm,
+    options: &CheckOptions,
+) {
+    if options.status {
+        return;
+    }
+
+    let display = event
+        .path
+        .as_deref()
+        .map(display_filename)
+        .unwrap_or_default();
+    match event.status {
+        CheckStatus::Ok => {
+            if !options.quiet {
+                println!("{display}: OK");
+            }
+        }
+        CheckStatus::Failed => println!("{display}: FAILED"),
+        CheckStatus::Unreadable { ref error } => {
+            eprintln!("hash-forge: {display}: {error}");
+            println!("{display}: FAILED open or read");
+        }
+        CheckStatus::ImproperlyFormatted => {
+            if options.warn {
+                eprintln!(
+                    "hash-forge: {source}: {}: improperly formatted {} checksum line",
+                    event.line,
+                    algorithm.bsd_tag()
+                );
+            }
+        }
+    }
+}
+
+/// Print the end-of-manifest warnings the way coreutils words them
+pub fn print_check_summary(source: &str, summary: &CheckSummary, options: &CheckOptions) {
+    if options.status {
+        return;
+    }
+    if summary.properly_formatted == 0 {
+        eprintln!("hash-forge: {source}: no properly formatted checksum lines found");
+        return;
+    }
+
+    let plural = |n: usize, one: &str, many: &str| {
+        if n == 1 {
+            format!("{n} {one}")
+        } else {
+            format!("{n} {many}")
+        }
+    };
+    if summary.improperly_formatted > 0 {
+        eprintln!(
+            "hash-forge: WARNING: {} improperly formatted",
+            plural(summary.improperly_formatted, "line is", "lines are")
+        );
+    }
+    if summary.unreadable > 0 {
+        eprintln!(
+            "hash-forge: WARNING: {} could not be read",
+            plural(summary.unreadable, "listed file", "listed files")
+        );
+    }
+    if summary.mismatched > 0 {
+        eprintln!(
+            "hash-forge: WARNING: {} NOT match",
+            plural(
+                summary.mismatched,
+                "computed checksum did",
+                "computed checksums did"
+            )
+        );
+    }
+    if options.ignore_missing && summary.verified + summary.mismatched == 0 {
+        eprintln!("hash-forge: {source}: no file was verified");
+    }
+}
+
+/// Render a filename for check output.
+///
+/// As in `sha256sum -c`, only names containing line breaks are escaped here.
+fn display_filename(name: &str) -> String {
+    if name.contains(['\n', '\r']) {
+        format!("\\{}", escape_filename(name))
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_gnu_lines() {
+        let text = ManifestEntry::new("dir/hello.txt", DIGEST, false);
+        let binary = ManifestEntry::new("hello.bin", DIGEST, true);
+
+        assert_eq!(
+            text.to_line(HashAlgorithm::Sha256, ManifestFormat::Gnu),
+            format!("{DIGEST}  dir/hello.txt")
+        );
+        assert_eq!(
+            binary.to_line(HashAlgorithm::Sha256, ManifestFormat::Gnu),
+            format!("{DIGEST} *hello.bin")
+        );
+    }
+
+    #[test]
+    fn test_bsd_lines() {
+        let entry = ManifestEntry::new("hello.txt", DIGEST, true);
+        assert_eq!(
+            entry.to_line(HashAlgorithm::Sha256, ManifestFormat::Bsd),
+            format!("SHA256 (hello.txt) = {DIGEST}")
+        );
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let (tag, entry) = parse_line(&format!("{DIGEST}  dir/hello.txt")).unwrap();
+        assert_eq!(tag, None);
+        assert_eq!(entry, ManifestEntry::new("dir/hello.txt", DIGEST, false));
+
+        let (_, entry) = parse_line(&format!("  {DIGEST} *hello.bin\r")).unwrap();
+        assert_eq!(entry, ManifestEntry::new("hello.bin", DIGEST, true));
+
+        let (tag, entry) = parse_line(&format!("SHA3-256 (a (1).txt) = {DIGEST}")).unwrap();
+        assert_eq!(tag, Some(HashAlgorithm::Sha3_256));
+        assert_eq!(entry.path, "a (1).txt");
+
+        assert!(parse_line("garbage").is_none());
+        assert!(parse_line(&format!("\\{DIGEST}  bad\\escape")).is_none());
+    }
+
+    #[test]
+    fn test_escape_round_trip() {
+        for format in [ManifestFormat::Gnu, ManifestFormat::Bsd] {
+            let entry = ManifestEntry::new("we\\ird\nname", DIGEST, true);
+            let line = entry.to_line(HashAlgorithm::Sha256, format);
+            assert_eq!(parse_line(&line).unwrap().1, entry);
+        }
+    }
+
+    #[test]
+    fn test_escaped_filenames() {
+        let entry = ManifestEntry::new("a\\b\nc", DIGEST, false);
+        assert_eq!(
+            entry.to_line(HashAlgorithm::Sha256, ManifestFormat::Gnu),
+            format!("\\{DIGEST}  a\\\\b\\nc")
+        );
+        assert_eq!(
+            entry.to_line(HashAlgorithm::Md5, ManifestFormat::Bsd),
+            format!("\\MD5 (a\\\\b\\nc) = {DIGEST}")
+        );
+    }
+}
@@ -0,0 +1,302 @@
+// Content-defined chunking (FastCDC) for deduplication estimates
+use crate::{
+    algorithms::HashAlgorithm,
+    core::HashForge,
+    dupes::FileError,
+    walk::{collect_files, WalkOptions},
+};
+use anyhow::{Context, Result};
+use fastcdc::v2020::{self as cdc, StreamCDC};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// Chunk size limits for [`HashForge::chunk_file`], in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkOptions {
+    pub min_size: u64,
+    /// Target average; cut points are chosen so chunks cluster around it
+    pub avg_size: u64,
+    pub max_size: u64,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            min_size: 16 * 1024,
+            avg_size: 64 * 1024,
+            max_size: 256 * 1024,
+        }
+    }
+}
+
+impl ChunkOptions {
+    /// Check the sizes against the limits FastCDC supports
+    pub fn validate(&self) -> Result<()> {
+        let check = |name: &str, size: u64, min: u32, max: u32| {
+            if size < u64::from(min) || size > u64::from(max) {
+                anyhow::bail!(
+                    "{name} chunk size must be between {min} and {max} bytes, not {size}"
+                );
+            }
+            Ok(())
+        };
+        check("Minimum", self.min_size, cdc::MINIMUM_MIN, cdc::MINIMUM_MAX)?;
+        check("Average", self.avg_size, cdc::AVERAGE_MIN, cdc::AVERAGE_MAX)?;
+        check("Maximum", self.max_size, cdc::MAXIMUM_MIN, cdc::MAXIMUM_MAX)?;
+        if self.min_size > self.avg_size || self.avg_size > self.max_size {
+            anyhow::bail!(
+                "Chunk sizes must satisfy minimum <= average <= maximum, not {} / {} / {}",
+                self.min_size,
+                self.avg_size,
+                self.max_size
+            );
+        }
+        Ok(())
+    }
+}
+
+/// One chunk of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: u64,
+    pub length: u64,
+    pub digest: Vec<u8>,
+}
+
+/// A file split into chunks
+#[derive(Debug, Clone)]
+pub struct ChunkedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Chunks in file order, covering the whole file
+    pub chunks: Vec<Chunk>,
+}
+
+/// Chunks of a set of files and how much of them is duplicated
+#[derive(Debug, Clone, Default)]
+pub struct ChunkAnalysis {
+    pub files: Vec<ChunkedFile>,
+    /// Files that could not be read
+    pub skipped: Vec<FileError>,
+    /// Distinct chunk digests across all files
+    pub unique_chunks: usize,
+    /// Bytes needed to store each distinct chunk once
+    pub unique_bytes: u64,
+}
+
+impl ChunkAnalysis {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    pub fn total_chunks(&self) -> usize {
+        self.files.iter().map(|file| file.chunks.len()).sum()
+    }
+
+    /// Total bytes divided by unique bytes; 1.0 when there is nothing to store
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.unique_bytes == 0 {
+            return 1.0;
+        }
+        self.total_bytes() as f64 / self.unique_bytes as f64
+    }
+
+    /// Fraction of the total bytes that deduplication would not store
+    pub fn savings(&self) -> f64 {
+        1.0 - 1.0 / self.dedup_ratio()
+    }
+}
+
+impl HashForge {
+    /// Split `path` into content-defined chunks and hash each chunk.
+    ///
+    /// Uses FastCDC (2020) with normalization level 1, the chunker's default,
+    /// so boundaries match other tools using the same sizes and gear table.
+    pub fn chunk_file(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        options: &ChunkOptions,
+    ) -> Result<ChunkedFile> {
+        check_algorithm(algorithm)?;
+        options.validate()?;
+
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let size = file.metadata()?.len();
+        let progress_bar = self.progress_bar(size);
+        let reader: Box<dyn Read> = match &progress_bar {
+            Some(pb) => Box::new(pb.wrap_read(BufReader::new(file))),
+            None => Box::new(BufReader::new(file)),
+        };
+
+        // Validated above, so the chunker's own assertions hold
+        let chunker = StreamCDC::new(
+            reader,
+            options.min_size as u32,
+            options.avg_size as u32,
+            options.max_size as u32,
+        );
+        let chunk_forge = HashForge::new().with_progress(false);
+        let mut chunks = Vec::new();
+        for chunk in chunker {
+            let chunk = chunk
+                .map_err(std::io::Error::from)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            chunks.push(Chunk {
+                offset: chunk.offset,
+                length: chunk.length as u64,
+                digest: chunk_forge.hash_bytes(&chunk.data, algorithm)?.bytes,
+            });
+        }
+        if let Some(pb) = progress_bar {
+            pb.finish_with_message("Chunks computed");
+        }
+
+        Ok(ChunkedFile {
+            path: path.to_path_buf(),
+            size: chunks.iter().map(|chunk| chunk.length).sum(),
+            chunks,
+        })
+    }
+
+    /// Chunk every file selected by `paths` and count the distinct chunks.
+    ///
+    /// Directories select files like `batch` does with `walk`. Files that
+    /// cannot be read are recorded in `skipped` and left out of the totals.
+    pub fn analyze_chunks(
+        &self,
+        paths: &[PathBuf],
+        algorithm: HashAlgorithm,
+        walk: &WalkOptions,
+        options: &ChunkOptions,
+    ) -> Result<ChunkAnalysis> {
+        check_algorithm(algorithm)?;
+        options.validate()?;
+
+        let mut selected = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                selected.extend(
+                    collect_files(path, walk)?
+                        .into_iter()
+                        // Link target paths are not file contents
+                        .filter(|entry| entry.link_target.is_none())
+                        .map(|entry| entry.path),
+                );
+            } else {
+                selected.push(path.clone());
+            }
+        }
+
+        let mut analysis = ChunkAnalysis::default();
+        let mut seen = HashSet::new();
+        for path in selected {
+            match self.chunk_file(&path, algorithm, options) {
+                Ok(file) => {
+                    for chunk in &file.chunks {
+                        if seen.insert(chunk.digest.clone()) {
+                            analysis.unique_chunks += 1;
+                            analysis.unique_bytes += chunk.length;
+                        }
+                    }
+                    analysis.files.push(file);
+                }
+                Err(e) => analysis.skipped.push(FileError {
+                    path: path.display().to_string(),
+                    error: format!("{e:#}"),
+                }),
+            }
+        }
+        Ok(analysis)
+    }
+}
+
+/// Chunks are deduplicated by digest alone, so the hash must resist collisions
+fn check_algorithm(algorithm: HashAlgorithm) -> Result<()> {
+    let collision_resistant = algorithm.is_cryptographic()
+        && !algorithm.is_password_hash()
+        && !matches!(algorithm, HashAlgorithm::Md5 | HashAlgorithm::Sha1);
+    if !collision_resistant {
+        anyhow::bail!(
+            "Chunking needs a collision-resistant hash; {} is not one",
+            algorithm.name()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_chunks() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        // Pseudo-random bytes, so that cut points depend on content
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let data: Vec<u8> = (0..1024 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        fs::write(dir.join("a"), &data).unwrap();
+        // A copy with bytes inserted at the front still shares most chunks
+        let mut shifted = b"inserted".to_vec();
+        shifted.extend_from_slice(&data);
+        fs::write(dir.join("b"), &shifted).unwrap();
+
+        let forge = HashForge::new().with_progress(false);
+        let options = ChunkOptions::default();
+        let analysis = forge
+            .analyze_chunks(
+                &[dir.to_path_buf()],
+                HashAlgorithm::Sha256,
+                &WalkOptions::default(),
+                &options,
+            )
+            .unwrap();
+        assert_eq!(analysis.files.len(), 2);
+        for file in &analysis.files {
+            let mut offset = 0;
+            for chunk in &file.chunks {
+                assert_eq!(chunk.offset, offset);
+                assert!(chunk.length <= options.max_size);
+                offset += chunk.length;
+            }
+            assert_eq!(offset, file.size);
+        }
+        assert_eq!(analysis.total_bytes(), 2 * 1024 * 1024 + 8);
+        assert!(analysis.unique_chunks < analysis.total_chunks());
+        assert!(analysis.dedup_ratio() > 1.8);
+
+        let invalid = ChunkOptions {
+            min_size: 128 * 1024,
+            ..options
+        };
+        assert!(invalid.validate().is_err());
+        for algorithm in [
+            HashAlgorithm::XxHash32,
+            HashAlgorithm::Md5,
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Argon2,
+        ] {
+            assert!(forge
+                .analyze_chunks(
+                    &[dir.to_path_buf()],
+                    algorithm,
+                    &WalkOptions::default(),
+                    &options
+                )
+                .is_err());
+        }
+    }
+}
@@ -1,5 +1,14 @@
-use crate::{algorithms::HashAlgorithm, output::OutputFormat};
-use clap::{Parser, Subcommand};
+use crate::{
+    algorithms::{AlgorithmSelection, HashAlgorithm},
+    checksum::ManifestFormat,
+    decompress::Decompress,
+    input::InputEncoding,
+    normalize::Normalization,
+    output::{BatchFormat, OutputFormat, OutputMode},
+    utils::{parse_byte_count, parse_size},
+    walk::WalkOptions,
+};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -8,6 +17,22 @@ use std::path::PathBuf;
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(author = "Francesco <fra2404@users.noreply.github.com>")]
 pub struct Cli {
+    /// Report format: human-readable text or one JSON document per command
+    #[arg(long, global = true, default_value = "human")]
+    pub output: OutputMode,
+
+    /// Reuse digests of files whose metadata is unchanged (file, batch, manifest, tree, dupes)
+    #[arg(long, global = true)]
+    pub cache: bool,
+
+    /// Don't use the digest cache; the default, kept to override an earlier --cache
+    #[arg(long, global = true, overrides_with = "cache")]
+    pub no_cache: bool,
+
+    /// Re-hash every file and replace its cached digest
+    #[arg(long, global = true, requires = "cache")]
+    pub refresh: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -15,120 +40,796 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     /// Hash text input
-    Text {
-        /// Text to hash
-        #[arg(short, long)]
-        input: String,
+    Text(TextArgs),
 
-        /// Hash algorithm to use
-        #[arg(short, long, default_value = "sha256")]
-        algorithm: HashAlgorithm,
+    /// Hash file contents
+    File(FileArgs),
 
-        /// Output format
-        #[arg(short = 'f', long, default_value = "hex")]
-        output_format: OutputFormat,
+    /// Verify hash against expected value
+    Verify(VerifyArgs),
 
-        /// Salt for password hashing algorithms
-        #[arg(short, long)]
-        salt: Option<String>,
+    /// Batch process directory
+    Batch(BatchArgs),
 
-        /// Number of iterations for password hashing
-        #[arg(long)]
-        iterations: Option<u32>,
-    },
+    /// Write a checksum manifest readable by sha256sum -c or shasum -c
+    Manifest(ManifestArgs),
 
-    /// Hash file contents
-    File {
-        /// Path to file
-        #[arg(short, long)]
-        path: PathBuf,
+    /// Verify files listed in checksum manifests (like sha256sum -c)
+    Check(CheckArgs),
 
-        /// Hash algorithm to use
-        #[arg(short, long, default_value = "sha256")]
-        algorithm: HashAlgorithm,
+    /// Compare two manifests, or a manifest and a directory: added, removed, modified and renamed files
+    Diff(DiffArgs),
 
-        /// Output format
-        #[arg(short = 'f', long, default_value = "hex")]
-        output_format: OutputFormat,
+    /// Generate and verify Subresource Integrity (SRI) values
+    Sri {
+        #[command(subcommand)]
+        action: SriAction,
     },
 
-    /// Verify hash against expected value
-    Verify {
-        /// Text to verify (mutually exclusive with --file)
-        #[arg(short = 't', long, group = "input")]
-        text: Option<String>,
+    /// Compute a Merkle digest of a whole directory tree (always recursive)
+    Tree(TreeArgs),
 
-        /// File to verify (mutually exclusive with --text)
-        #[arg(short = 'f', long, group = "input")]
-        file: Option<PathBuf>,
+    /// Find files with identical contents and the space they waste (always recursive)
+    Dupes(DupesArgs),
 
-        /// Expected hash value
-        #[arg(short, long)]
-        expected_hash: String,
+    /// Watch files or directories and hash files again whenever they change
+    Watch(WatchArgs),
 
-        /// Hash algorithm used
-        #[arg(short, long, default_value = "sha256")]
-        algorithm: HashAlgorithm,
+    /// Hash a file in fixed-size blocks, or find which blocks of a file changed
+    Blocks {
+        #[command(subcommand)]
+        action: BlockAction,
     },
 
-    /// Batch process directory
-    Batch {
-        /// Directory to process
-        #[arg(short, long)]
-        directory: PathBuf,
+    /// Split files into content-defined chunks (FastCDC) and estimate deduplication savings
+    Chunks(ChunksArgs),
 
-        /// Hash algorithm to use
-        #[arg(short, long, default_value = "sha256")]
-        algorithm: HashAlgorithm,
+    /// Write the rsync-style signature of a file, for computing deltas against it
+    Signature(SignatureArgs),
+
+    /// Write a delta that turns the file a signature describes into a new file
+    Delta(DeltaArgs),
+
+    /// Apply a delta to the basis file, writing the new file
+    Patch(PatchArgs),
 
-        /// Output format
-        #[arg(short = 'f', long, default_value = "hex")]
-        output_format: OutputFormat,
+    /// Hash the members of a tar (plain, gz, xz, zstd) or zip archive, or verify them, without extracting it
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveAction,
+    },
+
+    /// Inspect and maintain the persistent digest cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
     },
 
     /// Compute HMAC (Keyed-Hash Message Authentication Code)
-    Hmac {
-        /// Text to compute HMAC for (mutually exclusive with --file)
-        #[arg(short = 't', long, group = "input")]
-        text: Option<String>,
+    Hmac(HmacArgs),
+
+    /// Verify HMAC against expected value
+    VerifyHmac(VerifyHmacArgs),
+}
+
+/// Arguments of `text`
+#[derive(Args)]
+pub struct TextArgs {
+    /// Text to hash
+    #[arg(short, long)]
+    pub input: String,
+
+    /// How to read --input: as text, or as hex, base64 or UTF-16LE bytes
+    #[arg(long, value_name = "ENCODING", default_value = "utf8")]
+    pub input_encoding: InputEncoding,
+
+    #[command(flatten)]
+    pub normalization: Normalization,
+
+    /// Hash algorithm to use
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Output format
+    #[arg(short = 'f', long, default_value = "hex")]
+    pub output_format: OutputFormat,
+
+    /// Salt for password hashing algorithms
+    #[arg(short, long)]
+    pub salt: Option<String>,
+
+    /// Number of iterations for password hashing
+    #[arg(long)]
+    pub iterations: Option<u32>,
+
+    /// Prefix the digest with its algorithm (e.g. "sha256:2cf2...")
+    #[arg(short = 'T', long, conflicts_with_all = ["multihash", "cid"])]
+    pub tagged: bool,
+
+    /// Print a multihash (multicodec code, length, digest) in the output format
+    #[arg(long, conflicts_with = "cid")]
+    pub multihash: bool,
+
+    /// Print a CIDv1 for raw content ("bafkrei..." for sha256)
+    #[arg(long)]
+    pub cid: bool,
+
+    /// Print only the digest, without banners or labels
+    #[arg(short, long, visible_alias = "raw")]
+    pub quiet: bool,
+}
+
+/// Arguments of `file`
+#[derive(Args)]
+pub struct FileArgs {
+    /// Path to file
+    #[arg(short, long)]
+    pub path: PathBuf,
+
+    /// Start at this byte offset (decimal, 0x-prefixed hex, or with a K/M/G suffix)
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_count)]
+    pub offset: Option<u64>,
+
+    /// Hash only this many bytes [default: to the end of the file]
+    #[arg(long, value_name = "BYTES", value_parser = parse_byte_count)]
+    pub length: Option<u64>,
+
+    /// Hash the decompressed contents of a gzip, zstd, xz or bzip2 file
+    #[arg(long, value_name = "FORMAT", conflicts_with_all = ["offset", "length"])]
+    pub decompress: Option<Decompress>,
+
+    #[command(flatten)]
+    pub normalization: Normalization,
+
+    /// Hash algorithm to use
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Output format
+    #[arg(short = 'f', long, default_value = "hex")]
+    pub output_format: OutputFormat,
+
+    /// Prefix the digest with its algorithm (e.g. "sha256:2cf2...")
+    #[arg(short = 'T', long, conflicts_with_all = ["multihash", "cid"])]
+    pub tagged: bool,
+
+    /// Print a multihash (multicodec code, length, digest) in the output format
+    #[arg(long, conflicts_with = "cid")]
+    pub multihash: bool,
+
+    /// Print a CIDv1 for raw content ("bafkrei..." for sha256)
+    #[arg(long)]
+    pub cid: bool,
+
+    /// Print only the digest, without banners or labels
+    #[arg(short, long, visible_alias = "raw")]
+    pub quiet: bool,
+}
+
+/// Arguments of `verify`
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Text to verify (mutually exclusive with --file)
+    #[arg(short = 't', long, group = "input")]
+    pub text: Option<String>,
+
+    /// How to read --text: as text, or as hex, base64 or UTF-16LE bytes
+    #[arg(
+        long,
+        value_name = "ENCODING",
+        default_value = "utf8",
+        conflicts_with = "file"
+    )]
+    pub input_encoding: InputEncoding,
+
+    /// File to verify (mutually exclusive with --text)
+    #[arg(short = 'f', long, group = "input")]
+    pub file: Option<PathBuf>,
+
+    /// Start at this byte offset of --file (decimal, 0x-prefixed hex, or with a K/M/G suffix)
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_byte_count,
+        requires = "file",
+        conflicts_with = "text"
+    )]
+    pub offset: Option<u64>,
+
+    /// Use only this many bytes of --file [default: to the end of the file]
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_byte_count,
+        requires = "file",
+        conflicts_with = "text"
+    )]
+    pub length: Option<u64>,
+
+    /// Verify the decompressed contents of a gzip, zstd, xz or bzip2 --file
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        requires = "file",
+        conflicts_with_all = ["text", "offset", "length"]
+    )]
+    pub decompress: Option<Decompress>,
+
+    #[command(flatten)]
+    pub normalization: Normalization,
+
+    /// Expected hash value
+    #[arg(short, long)]
+    pub expected_hash: String,
+
+    /// Hash algorithm used, or "auto" to detect it from the expected hash
+    /// [default: the expected hash's tag (e.g. "sha256:..."), otherwise sha256]
+    #[arg(short, long)]
+    pub algorithm: Option<AlgorithmSelection>,
+
+    /// Print nothing; the exit code shows whether verification passed
+    #[arg(short, long, visible_alias = "raw")]
+    pub quiet: bool,
+}
+
+/// Arguments of `batch`
+#[derive(Args)]
+pub struct BatchArgs {
+    /// Directory to process
+    #[arg(short, long)]
+    pub directory: PathBuf,
+
+    /// Hash algorithm to use
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Output format
+    #[arg(short = 'f', long, default_value = "hex")]
+    pub output_format: OutputFormat,
+
+    /// Record format; csv and ndjson stream one record per file as it completes
+    #[arg(long, default_value = "text")]
+    pub format: BatchFormat,
+
+    /// Write records to a file instead of stdout
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// Arguments of `manifest`
+#[derive(Args)]
+pub struct ManifestArgs {
+    /// Directory to process
+    #[arg(short, long)]
+    pub directory: PathBuf,
+
+    /// Hash algorithm to use
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Manifest line format
+    #[arg(long, default_value = "gnu")]
+    pub format: ManifestFormat,
+
+    /// Mark files as read in binary mode ('*' before the path, GNU format)
+    #[arg(short, long)]
+    pub binary: bool,
+
+    /// Write the manifest to a file instead of stdout
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// Arguments of `check`
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Manifest files to read ("-" for standard input)
+    #[arg(required = true)]
+    pub manifests: Vec<PathBuf>,
+
+    /// Hash algorithm for GNU-format lines (BSD tag lines name their own)
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Don't print OK for each successfully verified file
+    #[arg(long)]
+    pub quiet: bool,
+
+    /// Don't output anything; the exit code shows success
+    #[arg(long)]
+    pub status: bool,
+
+    /// Exit non-zero for improperly formatted checksum lines
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Warn about improperly formatted checksum lines
+    #[arg(short, long)]
+    pub warn: bool,
+
+    /// Don't fail or report status for missing files
+    #[arg(long)]
+    pub ignore_missing: bool,
+}
+
+/// Arguments of `diff`
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Old snapshot: a checksum manifest ("-" for standard input) or a directory
+    pub old: PathBuf,
+
+    /// New snapshot: a checksum manifest or a directory
+    pub new: PathBuf,
+
+    /// Hash algorithm for GNU-format manifests and directories (BSD tag lines name their own)
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Don't output anything; the exit code shows whether the snapshots differ
+    #[arg(long)]
+    pub status: bool,
+
+    #[command(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// Arguments of `tree`
+#[derive(Args)]
+pub struct TreeArgs {
+    /// Directory to process
+    #[arg(short, long)]
+    pub directory: PathBuf,
+
+    /// Hash algorithm to use
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Output format
+    #[arg(short = 'f', long, default_value = "hex")]
+    pub output_format: OutputFormat,
+
+    /// Include permission bits of files and directories in the digest
+    #[arg(long)]
+    pub modes: bool,
+
+    /// Include directories that contain no selected files
+    #[arg(long)]
+    pub empty_dirs: bool,
+
+    /// Print only the digest, without banners or labels
+    #[arg(short, long, visible_alias = "raw")]
+    pub quiet: bool,
 
-        /// File to compute HMAC for (mutually exclusive with --text)
-        #[arg(short = 'f', long, group = "input")]
-        file: Option<PathBuf>,
+    #[command(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// Arguments of `dupes`
+#[derive(Args)]
+pub struct DupesArgs {
+    /// Directory to search
+    #[arg(short, long)]
+    pub directory: PathBuf,
+
+    /// Cryptographic hash confirming that candidates are identical
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Ignore files smaller than this many bytes
+    #[arg(long, value_name = "BYTES", default_value_t = 1)]
+    pub min_size: u64,
+
+    /// Replace each duplicate with a hardlink to the first file of its set
+    #[arg(long)]
+    pub hardlink: bool,
+
+    #[command(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// Arguments of `watch`
+#[derive(Args)]
+pub struct WatchArgs {
+    /// Files or directories to watch
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Hash algorithm to use (a manifest with BSD tag lines names its own)
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Output format
+    #[arg(short = 'f', long, default_value = "hex")]
+    pub output_format: OutputFormat,
+
+    /// Verify changed files against this checksum manifest instead of printing digests
+    #[arg(short, long)]
+    pub manifest: Option<PathBuf>,
+
+    /// Milliseconds a file must stay unchanged before it is hashed
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub debounce: u64,
+
+    #[command(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// Arguments of `chunks`
+#[derive(Args)]
+pub struct ChunksArgs {
+    /// Files or directories to analyze
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Hash algorithm identifying each chunk
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Smallest chunk, in bytes (K, M suffixes are powers of 1024)
+    #[arg(long, value_name = "SIZE", default_value = "16K", value_parser = parse_size)]
+    pub min_size: u64,
+
+    /// Average chunk size the chunker aims for
+    #[arg(long, value_name = "SIZE", default_value = "64K", value_parser = parse_size)]
+    pub avg_size: u64,
+
+    /// Largest chunk
+    #[arg(long, value_name = "SIZE", default_value = "256K", value_parser = parse_size)]
+    pub max_size: u64,
+
+    /// List every chunk (offset, length and digest) of every file
+    #[arg(short, long)]
+    pub list: bool,
+
+    #[command(flatten)]
+    pub walk: WalkOptions,
+}
+
+/// Arguments of `signature`
+#[derive(Args)]
+pub struct SignatureArgs {
+    /// Basis file, as it exists on the receiving side
+    pub file: PathBuf,
+
+    /// Where to write the signature
+    pub signature: PathBuf,
+
+    /// Strong hash checked whenever a weak checksum matches
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Block size [default: square root of the file size, 700 bytes to 128K]
+    #[arg(short, long, value_name = "SIZE", value_parser = parse_size)]
+    pub block_size: Option<u64>,
+}
+
+/// Arguments of `delta`
+#[derive(Args)]
+pub struct DeltaArgs {
+    /// Signature written by `signature`
+    pub signature: PathBuf,
+
+    /// New version of the file
+    pub file: PathBuf,
+
+    /// Where to write the delta
+    pub delta: PathBuf,
+}
+
+/// Arguments of `patch`
+#[derive(Args)]
+pub struct PatchArgs {
+    /// File the signature was computed from
+    pub basis: PathBuf,
+
+    /// Delta written by `delta`
+    pub delta: PathBuf,
+
+    /// Where to write the new file (may be the basis file itself)
+    pub new_file: PathBuf,
+}
+
+/// Arguments of `hmac`
+#[derive(Args)]
+pub struct HmacArgs {
+    /// Text to compute HMAC for (mutually exclusive with --file)
+    #[arg(short = 't', long, group = "input")]
+    pub text: Option<String>,
+
+    /// How to read --text: as text, or as hex, base64 or UTF-16LE bytes
+    #[arg(
+        long,
+        value_name = "ENCODING",
+        default_value = "utf8",
+        conflicts_with = "file"
+    )]
+    pub input_encoding: InputEncoding,
+
+    /// File to compute HMAC for (mutually exclusive with --text)
+    #[arg(short = 'f', long, group = "input")]
+    pub file: Option<PathBuf>,
+
+    /// Start at this byte offset of --file (decimal, 0x-prefixed hex, or with a K/M/G suffix)
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_byte_count,
+        requires = "file",
+        conflicts_with = "text"
+    )]
+    pub offset: Option<u64>,
+
+    /// Use only this many bytes of --file [default: to the end of the file]
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_byte_count,
+        requires = "file",
+        conflicts_with = "text"
+    )]
+    pub length: Option<u64>,
+
+    /// HMAC key
+    #[arg(short, long)]
+    pub key: String,
+
+    /// Hash algorithm to use for HMAC
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
+
+    /// Output format
+    #[arg(short = 'o', long, default_value = "hex")]
+    pub output_format: OutputFormat,
+
+    /// Print only the digest, without banners or labels
+    #[arg(short, long, visible_alias = "raw")]
+    pub quiet: bool,
+}
+
+/// Arguments of `verify-hmac`
+#[derive(Args)]
+pub struct VerifyHmacArgs {
+    /// Text to verify HMAC for (mutually exclusive with --file)
+    #[arg(short = 't', long, group = "input")]
+    pub text: Option<String>,
+
+    /// How to read --text: as text, or as hex, base64 or UTF-16LE bytes
+    #[arg(
+        long,
+        value_name = "ENCODING",
+        default_value = "utf8",
+        conflicts_with = "file"
+    )]
+    pub input_encoding: InputEncoding,
+
+    /// File to verify HMAC for (mutually exclusive with --text)
+    #[arg(short = 'f', long, group = "input")]
+    pub file: Option<PathBuf>,
+
+    /// Start at this byte offset of --file (decimal, 0x-prefixed hex, or with a K/M/G suffix)
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_byte_count,
+        requires = "file",
+        conflicts_with = "text"
+    )]
+    pub offset: Option<u64>,
+
+    /// Use only this many bytes of --file [default: to the end of the file]
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_byte_count,
+        requires = "file",
+        conflicts_with = "text"
+    )]
+    pub length: Option<u64>,
+
+    /// HMAC key
+    #[arg(short, long)]
+    pub key: String,
+
+    /// Expected HMAC value
+    #[arg(short, long)]
+    pub expected_hmac: String,
+
+    /// Hash algorithm used for HMAC
+    #[arg(short, long, default_value = "sha256")]
+    pub algorithm: HashAlgorithm,
 
-        /// HMAC key
+    /// Print nothing; the exit code shows whether verification passed
+    #[arg(short, long, visible_alias = "raw")]
+    pub quiet: bool,
+}
+
+/// Actions of the `sri` command
+#[derive(Subcommand)]
+pub enum SriAction {
+    /// Print the integrity value of each file
+    Generate {
+        /// Files to hash
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// SRI algorithm (sha256, sha384 or sha512); repeat for several hashes
+        #[arg(short, long = "algorithm", default_value = "sha384")]
+        algorithms: Vec<HashAlgorithm>,
+    },
+
+    /// Verify a file against an integrity value
+    Verify {
+        /// File to verify
+        file: PathBuf,
+
+        /// Integrity value, e.g. "sha384-oqVuAfXRKap7fdgcCY5uykM6+R9GqQ8K..."
+        #[arg(short, long)]
+        integrity: String,
+    },
+
+    /// Check (or rewrite) <script> and <link> integrity values in an HTML file
+    Html {
+        /// HTML file to scan
+        html: PathBuf,
+
+        /// Directory that root-relative URLs ("/js/app.js") resolve against
+        /// [default: the HTML file's directory]
+        #[arg(long)]
+        root: Option<PathBuf>,
+
+        /// Rewrite mismatching integrity values in place
         #[arg(short, long)]
-        key: String,
+        write: bool,
+
+        /// Algorithms for rewritten values [default: those already in each value]
+        #[arg(short, long = "algorithm")]
+        algorithms: Vec<HashAlgorithm>,
+    },
+}
 
-        /// Hash algorithm to use for HMAC
+/// Actions of the `blocks` command
+#[derive(Subcommand)]
+pub enum BlockAction {
+    /// Write the list of block digests of a file
+    Create {
+        /// File to hash
+        file: PathBuf,
+
+        /// Hash algorithm to use for each block
         #[arg(short, long, default_value = "sha256")]
         algorithm: HashAlgorithm,
 
-        /// Output format
-        #[arg(short = 'o', long, default_value = "hex")]
-        output_format: OutputFormat,
+        /// Block size in bytes; K, M and G suffixes are powers of 1024
+        #[arg(short, long, value_name = "SIZE", default_value = "1M", value_parser = parse_size)]
+        block_size: u64,
+
+        /// Write the block list to a file instead of stdout
+        #[arg(short, long)]
+        output_file: Option<PathBuf>,
     },
 
-    /// Verify HMAC against expected value
-    VerifyHmac {
-        /// Text to verify HMAC for (mutually exclusive with --file)
-        #[arg(short = 't', long, group = "input")]
-        text: Option<String>,
+    /// Report the byte ranges where a file differs from a saved block list
+    Compare {
+        /// File to check
+        file: PathBuf,
+
+        /// Block list written by `blocks create`
+        list: PathBuf,
+    },
+}
+
+/// Actions of the `archive` command
+#[derive(Subcommand)]
+pub enum ArchiveAction {
+    /// Write a checksum manifest of every file in the archive
+    Manifest {
+        /// Archive to read; the format is detected from its contents
+        archive: PathBuf,
 
-        /// File to verify HMAC for (mutually exclusive with --text)
-        #[arg(short = 'f', long, group = "input")]
-        file: Option<PathBuf>,
+        /// Hash algorithm to use
+        #[arg(short, long, default_value = "sha256")]
+        algorithm: HashAlgorithm,
 
-        /// HMAC key
+        /// Manifest line format
+        #[arg(long, default_value = "gnu")]
+        format: ManifestFormat,
+
+        /// Mark files as read in binary mode ('*' before the path, GNU format)
         #[arg(short, long)]
-        key: String,
+        binary: bool,
 
-        /// Expected HMAC value
+        /// Write the manifest to a file instead of stdout
         #[arg(short, long)]
-        expected_hmac: String,
+        output_file: Option<PathBuf>,
+    },
+
+    /// Verify archive members listed in a checksum manifest (like sha256sum -c)
+    Check {
+        /// Archive to read; the format is detected from its contents
+        archive: PathBuf,
 
-        /// Hash algorithm used for HMAC
+        /// Manifest listing paths inside the archive ("-" for standard input)
+        manifest: PathBuf,
+
+        /// Hash algorithm for GNU-format lines (BSD tag lines name their own)
         #[arg(short, long, default_value = "sha256")]
         algorithm: HashAlgorithm,
+
+        /// Don't print OK for each successfully verified member
+        #[arg(long)]
+        quiet: bool,
+
+        /// Don't output anything; the exit code shows success
+        #[arg(long)]
+        status: bool,
+
+        /// Exit non-zero for improperly formatted checksum lines
+        #[arg(long)]
+        strict: bool,
+
+        /// Warn about improperly formatted checksum lines
+        #[arg(short, long)]
+        warn: bool,
+
+        /// Don't fail or report status for listed paths missing from the archive
+        #[arg(long)]
+        ignore_missing: bool,
     },
 }
+
+/// Actions of the `cache` command
+#[derive(Subcommand)]
+pub enum CacheAction {
+    /// Show where the cache is stored and how many digests it holds
+    Info,
+
+    /// Remove entries for files that were deleted or changed since they were cached
+    Prune,
+
+    /// Remove every entry
+    Clear,
+}
+
+impl Commands {
+    /// Exit status when the command fails with an error.
+    ///
+    /// `diff` follows diff(1) and keeps `1` for "different", so errors are `2`.
+    pub fn error_status(&self) -> i32 {
+        match self {
+            Commands::Diff { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    /// Command name as typed on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Commands::Text { .. } => "text",
+            Commands::File { .. } => "file",
+            Commands::Verify { .. } => "verify",
+            Commands::Batch { .. } => "batch",
+            Commands::Manifest { .. } => "manifest",
+            Commands::Check { .. } => "check",
+            Commands::Diff { .. } => "diff",
+            Commands::Sri { .. } => "sri",
+            Commands::Tree { .. } => "tree",
+            Commands::Dupes { .. } => "dupes",
+            Commands::Watch { .. } => "watch",
+            Commands::Blocks { .. } => "blocks",
+            Commands::Chunks { .. } => "chunks",
+            Commands::Signature { .. } => "signature",
+            Commands::Delta { .. } => "delta",
+            Commands::Patch { .. } => "patch",
+            Commands::Archive { .. } => "archive",
+            Commands::Cache { .. } => "cache",
+            Commands::Hmac { .. } => "hmac",
+            Commands::VerifyHmac { .. } => "verify-hmac",
+        }
+    }
+}
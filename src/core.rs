@@ -1,30 +1,50 @@
 use crate::{
     algorithms::HashAlgorithm,
-    output::{HashResult, OutputFormat},
+    cache::SharedCache,
+    checksum::{ManifestEntry, ManifestFormat},
+    multiformats::decode_content_address,
+    output::{decode_expected_hash, split_algorithm_tag, BatchFormat, HashResult, OutputFormat},
+    report::FileRecord,
     utils::generate_salt,
+    walk::{collect_files, WalkEntry, WalkOptions},
 };
 use anyhow::{Context, Result};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::{
     fs,
-    io::{BufReader, Read},
+    io::{self, BufReader, Read, Write},
     path::Path,
+    sync::{mpsc, Arc},
+    thread,
 };
 
 use blake2::{Blake2b512, Blake2s256, Digest as Blake2Digest};
 use digest::Digest;
 use sha1::{Digest as Sha1Digest, Sha1};
-use sha2::{Digest as Sha2Digest, Sha256, Sha512};
+use sha2::{Digest as Sha2Digest, Sha256, Sha384, Sha512};
 use sha3::{Digest as Sha3Digest, Sha3_224, Sha3_256, Sha3_384, Sha3_512, Shake128, Shake256};
 
 pub struct HashForge {
     progress_enabled: bool,
+    cache: Option<SharedCache>,
+}
+
+/// Outcome of verifying against an expected hash with automatic algorithm detection
+#[derive(Debug, Clone)]
+pub enum DetectionOutcome {
+    /// Exactly one candidate algorithm reproduced the expected hash
+    Matched(HashResult),
+    /// None of the candidate algorithms reproduced the expected hash
+    NoMatch { candidates: Vec<HashAlgorithm> },
+    /// Several candidate algorithms reproduced the expected hash
+    Ambiguous { matches: Vec<HashAlgorithm> },
 }
 
 impl HashForge {
     pub fn new() -> Self {
         Self {
             progress_enabled: true,
+            cache: None,
         }
     }
 
@@ -33,16 +53,33 @@ impl HashForge {
         self
     }
 
+    /// Consult and update a persistent digest cache in [`HashForge::hash_file`]
+    /// (and everything built on it). Hashers have no cache by default.
+    pub fn with_cache(mut self, cache: Option<SharedCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
     /// Hash text input with the specified algorithm
     pub fn hash_text(
         &self,
         text: &str,
         algorithm: HashAlgorithm,
         salt: Option<&str>,
-        _iterations: Option<u32>,
+        iterations: Option<u32>,
     ) -> Result<HashResult> {
-        let bytes = text.as_bytes();
+        self.hash_data(text.as_bytes(), algorithm, salt, iterations)
+    }
 
+    /// Hash in-memory input with the specified algorithm, including password
+    /// hashes
+    pub fn hash_data(
+        &self,
+        bytes: &[u8],
+        algorithm: HashAlgorithm,
+        salt: Option<&str>,
+        _iterations: Option<u32>,
+    ) -> Result<HashResult> {
         match algorithm {
             // Fast hash algorithms
             HashAlgorithm::Md5 => {
@@ -59,6 +96,11 @@ impl HashForge {
                 Sha2Digest::update(&mut hasher, bytes);
                 Ok(HashResult::new(hasher.finalize().to_vec(), algorithm))
             }
+            HashAlgorithm::Sha384 => {
+                let mut hasher = Sha384::new();
+                Sha2Digest::update(&mut hasher, bytes);
+                Ok(HashResult::new(hasher.finalize().to_vec(), algorithm))
+            }
             HashAlgorithm::Sha512 => {
                 let mut hasher = Sha512::new();
                 Sha2Digest::update(&mut hasher, bytes);
@@ -153,7 +195,7 @@ impl HashForge {
                 let copy_len = std::cmp::min(salt_slice.len(), 16);
                 salt_bytes[..copy_len].copy_from_slice(&salt_slice[..copy_len]);
 
-                let hash = bcrypt::hash_with_salt(text, cost, salt_bytes)
+                let hash = bcrypt::hash_with_salt(bytes, cost, salt_bytes)
                     .context("Failed to compute bcrypt hash")?;
                 Ok(HashResult::new(hash.to_string().into_bytes(), algorithm))
             }
@@ -181,26 +223,139 @@ impl HashForge {
         }
     }
 
-    /// Hash file contents with the specified algorithm
+    /// Hash file contents with the specified algorithm.
+    ///
+    /// With a cache attached, a digest cached for the file's current metadata
+    /// is returned without reading it.
     pub fn hash_file(&self, path: &Path, algorithm: HashAlgorithm) -> Result<HashResult> {
         let file = fs::File::open(path)
             .with_context(|| format!("Failed to open file: {}", path.display()))?;
 
-        let file_size = file.metadata()?.len();
-        let mut reader = BufReader::new(file);
+        let metadata = file.metadata()?;
+        let Some(ref cache) = self.cache else {
+            return self.hash_reader(BufReader::new(file), metadata.len(), algorithm);
+        };
 
-        let progress_bar = if self.progress_enabled && file_size > 1024 * 1024 {
-            let pb = ProgressBar::new(file_size);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
-            Some(pb)
-        } else {
-            None
+        let lock = || {
+            cache
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
         };
+        if let Some(cached) = lock().lookup(path, algorithm) {
+            return Ok(cached);
+        }
+        let result = self.hash_reader(BufReader::new(file), metadata.len(), algorithm)?;
+        lock().insert(path, &metadata, &result);
+        Ok(result)
+    }
+
+    /// Hash an in-memory buffer with a fast (non-password) algorithm
+    pub fn hash_bytes(&self, bytes: &[u8], algorithm: HashAlgorithm) -> Result<HashResult> {
+        self.hash_reader(bytes, bytes.len() as u64, algorithm)
+    }
+
+    /// Hash a file with each of `algorithms`, reading it only once.
+    ///
+    /// The digest cache is not used; results are in the order of `algorithms`.
+    pub fn hash_file_many(
+        &self,
+        path: &Path,
+        algorithms: &[HashAlgorithm],
+    ) -> Result<Vec<HashResult>> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let size = file.metadata()?.len();
+        self.hash_reader_many(BufReader::new(file), size, algorithms)
+    }
+
+    /// Hash everything read from `reader` with each of `algorithms`.
+    ///
+    /// The input is read once and handed to one thread per algorithm, so a
+    /// large file costs a single pass however many digests are wanted.
+    /// Results are in the order of `algorithms`.
+    pub fn hash_reader_many<R: Read>(
+        &self,
+        mut reader: R,
+        size: u64,
+        algorithms: &[HashAlgorithm],
+    ) -> Result<Vec<HashResult>> {
+        if let [algorithm] = algorithms {
+            return Ok(vec![self.hash_reader(reader, size, *algorithm)?]);
+        }
+
+        let progress_bar = self.progress_bar(size);
+        let quiet = HashForge::new().with_progress(false);
+        thread::scope(|scope| {
+            let (senders, workers): (Vec<_>, Vec<_>) = algorithms
+                .iter()
+                .map(|&algorithm| {
+                    let (sender, receiver) = mpsc::sync_channel(16);
+                    let quiet = &quiet;
+                    let worker = scope
+                        .spawn(move || quiet.hash_reader(ChunkReader::new(receiver), 0, algorithm));
+                    (sender, worker)
+                })
+                .unzip();
+
+            // Read in the same block size as hash_reader, so every algorithm
+            // sees the blocks it would have read itself
+            let mut buffer = [0; 8192];
+            let mut total_read = 0u64;
+            loop {
+                let bytes_read = reader.read(&mut buffer)?;
+                if bytes_read == 0 {
+                    break;
+                }
+
+                let chunk: Arc<[u8]> = Arc::from(&buffer[..bytes_read]);
+                for sender in &senders {
+                    // A worker only hangs up after failing; its error is returned below
+                    let _ = sender.send(Arc::clone(&chunk));
+                }
+                total_read += bytes_read as u64;
+
+                if let Some(pb) = &progress_bar {
+                    pb.set_position(total_read);
+                }
+            }
+            drop(senders);
+
+            if let Some(pb) = &progress_bar {
+                pb.finish_with_message("Hash computed");
+            }
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("hashing thread panicked"))
+                .collect()
+        })
+    }
+
+    /// Progress bar for reading `size` bytes, if progress is enabled and the
+    /// input is large enough to be worth one
+    pub(crate) fn progress_bar(&self, size: u64) -> Option<ProgressBar> {
+        if !self.progress_enabled || size <= 1024 * 1024 {
+            return None;
+        }
+        let pb = ProgressBar::new(size);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        Some(pb)
+    }
+
+    /// Hash everything read from `reader` with the specified algorithm.
+    ///
+    /// `size` is the expected number of bytes and only drives the progress bar.
+    pub fn hash_reader<R: Read>(
+        &self,
+        mut reader: R,
+        size: u64,
+        algorithm: HashAlgorithm,
+    ) -> Result<HashResult> {
+        let progress_bar = self.progress_bar(size);
 
         match algorithm {
             HashAlgorithm::Md5 => {
@@ -239,6 +394,11 @@ impl HashForge {
                 self.update_hasher_with_progress(&mut hasher, &mut reader, &progress_bar)?;
                 Ok(HashResult::new(hasher.finalize().to_vec(), algorithm))
             }
+            HashAlgorithm::Sha384 => {
+                let mut hasher = Sha384::new();
+                self.update_hasher_with_progress(&mut hasher, &mut reader, &progress_bar)?;
+                Ok(HashResult::new(hasher.finalize().to_vec(), algorithm))
+            }
             HashAlgorithm::Sha512 => {
                 let mut hasher = Sha512::new();
                 self.update_hasher_with_progress(&mut hasher, &mut reader, &progress_bar)?;
@@ -459,10 +619,10 @@ impl HashForge {
     }
 
     /// Helper method to update hashers with progress tracking
-    fn update_hasher_with_progress<D: Digest>(
+    fn update_hasher_with_progress<D: Digest, R: Read>(
         &self,
         hasher: &mut D,
-        reader: &mut BufReader<fs::File>,
+        reader: &mut R,
         progress_bar: &Option<ProgressBar>,
     ) -> Result<()> {
         let mut buffer = [0; 8192];
@@ -489,19 +649,134 @@ impl HashForge {
         Ok(())
     }
 
-    /// Verify hash against expected value
+    /// Verify hash against expected value.
+    ///
+    /// The expected value may carry an algorithm tag (`sha256:...` or SRI
+    /// `sha256-...`), which must name the algorithm `computed` was made with.
     pub fn verify_hash(&self, computed: &HashResult, expected: &str) -> Result<bool> {
-        // Try to decode expected hash as hex first, then base64
-        if let Ok(expected_bytes) = hex::decode(expected) {
-            Ok(computed.constant_time_eq(&expected_bytes))
-        } else {
-            use base64::Engine;
-            if let Ok(expected_bytes) = base64::engine::general_purpose::STANDARD.decode(expected) {
-                Ok(computed.constant_time_eq(&expected_bytes))
-            } else {
-                anyhow::bail!("Expected hash is not valid hex or base64")
+        let (tag, expected) = split_algorithm_tag(expected);
+        let addressed = tag
+            .is_none()
+            .then(|| decode_content_address(expected))
+            .flatten();
+        if let Some(ref addressed) = addressed {
+            if addressed.algorithm != computed.algorithm {
+                anyhow::bail!(
+                    "Expected hash is a {} multihash but was computed with {}",
+                    addressed.algorithm.name(),
+                    computed.algorithm.name()
+                );
+            }
+            return Ok(computed.constant_time_eq(&addressed.bytes));
+        }
+        if let Some(tag) = tag {
+            if tag != computed.algorithm {
+                anyhow::bail!(
+                    "Expected hash is tagged as {} but was computed with {}",
+                    tag.name(),
+                    computed.algorithm.name()
+                );
             }
         }
+
+        // The expected hash may be in any supported text encoding; it matches
+        // if any of its interpretations does
+        let decoded = decode_expected_hash(expected);
+        if decoded.is_empty() {
+            anyhow::bail!("Expected hash is not valid hex, base64, base32 or base58")
+        }
+        Ok(decoded
+            .iter()
+            .any(|expected_bytes| computed.constant_time_eq(expected_bytes)))
+    }
+
+    /// Verify against an expected hash without knowing the algorithm.
+    ///
+    /// Candidates are narrowed by an `algorithm:` prefix if present, otherwise
+    /// by the decoded length of the expected hash. `compute` is called once,
+    /// with every distinct candidate, and returns the digests of the input
+    /// being verified in the same order, so the input can be read in one pass.
+    pub fn verify_auto<F>(&self, expected: &str, compute: F) -> Result<DetectionOutcome>
+    where
+        F: FnOnce(&[HashAlgorithm]) -> Result<Vec<HashResult>>,
+    {
+        let (mut tag, encoded) = split_algorithm_tag(expected);
+        let mut decoded = decode_expected_hash(encoded);
+        if tag.is_none() {
+            if let Some(addressed) = decode_content_address(encoded) {
+                tag = Some(addressed.algorithm);
+                decoded = vec![addressed.bytes];
+            }
+        }
+        if decoded.is_empty() {
+            anyhow::bail!("Expected hash is not valid hex, base64, base32 or base58");
+        }
+
+        let candidates = match tag {
+            Some(algorithm) => vec![algorithm],
+            None => {
+                let mut candidates = Vec::new();
+                for bytes in &decoded {
+                    for algorithm in HashAlgorithm::candidates_for_length(bytes.len()) {
+                        if !candidates.contains(&algorithm) {
+                            candidates.push(algorithm);
+                        }
+                    }
+                }
+                candidates
+            }
+        };
+        if candidates.is_empty() {
+            anyhow::bail!(
+                "No supported algorithm produces a digest matching the expected hash length"
+            );
+        }
+
+        let mut matches: Vec<_> = compute(&candidates)?
+            .into_iter()
+            .filter(|computed| decoded.iter().any(|bytes| computed.constant_time_eq(bytes)))
+            .collect();
+
+        Ok(match matches.len() {
+            0 => DetectionOutcome::NoMatch { candidates },
+            1 => DetectionOutcome::Matched(matches.remove(0)),
+            _ => DetectionOutcome::Ambiguous {
+                matches: matches.iter().map(|m| m.algorithm).collect(),
+            },
+        })
+    }
+
+    /// Hash a file found by a directory walk.
+    ///
+    /// Entries collected with the `hash-target-path` symlink policy hash the
+    /// link target path instead of file contents.
+    pub fn hash_entry(&self, entry: &WalkEntry, algorithm: HashAlgorithm) -> Result<HashResult> {
+        match entry.link_target {
+            Some(ref target) => self.hash_bytes(target.as_os_str().as_encoded_bytes(), algorithm),
+            None => self.hash_file(&entry.path, algorithm),
+        }
+    }
+
+    /// Hash every file selected in `directory`.
+    ///
+    /// `on_file` is called with each entry and its result in sorted path
+    /// order, as soon as the file has been hashed; an error returned from the
+    /// callback stops the walk.
+    pub fn hash_directory<F>(
+        &self,
+        directory: &Path,
+        algorithm: HashAlgorithm,
+        walk: &WalkOptions,
+        mut on_file: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&WalkEntry, Result<HashResult>) -> Result<()>,
+    {
+        for entry in collect_files(directory, walk)? {
+            let result = self.hash_entry(&entry, algorithm);
+            on_file(&entry, result)?;
+        }
+        Ok(())
     }
 
     /// Batch process directory
@@ -510,39 +785,140 @@ impl HashForge {
         directory: &Path,
         algorithm: HashAlgorithm,
         output_format: OutputFormat,
+        walk: &WalkOptions,
     ) -> Result<()> {
-        let entries = fs::read_dir(directory)
-            .with_context(|| format!("Failed to read directory: {}", directory.display()))?;
-
         println!("📁 Processing files in: {}", directory.display());
         println!("Algorithm: {algorithm}");
         println!("Output format: {output_format}");
         println!();
 
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
+        self.hash_directory(directory, algorithm, walk, |entry, result| {
+            match result {
+                Ok(result) => {
+                    println!("{} {}", result.encode(output_format), entry.display_path());
+                }
+                Err(e) => {
+                    eprintln!("❌ Error processing {}: {e}", entry.path.display());
+                }
+            }
+            Ok(())
+        })
+    }
 
-            if path.is_file() {
-                match self.hash_file(&path, algorithm) {
-                    Ok(result) => {
-                        let formatted = match output_format {
-                            OutputFormat::Hex => result.to_hex(),
-                            OutputFormat::Base64 => result.to_base64(),
-                        };
-                        println!(
-                            "{} {}",
-                            formatted,
-                            path.file_name().unwrap().to_string_lossy()
-                        );
-                    }
-                    Err(e) => {
-                        eprintln!("❌ Error processing {}: {e}", path.display());
+    /// Stream one record per file to `writer` as each file is hashed.
+    ///
+    /// Every record is flushed immediately so consumers can ingest results
+    /// while a large directory is still being processed. Returns the number
+    /// of files that could not be hashed; they are still written as records
+    /// with the `error` column set.
+    pub fn write_batch_records<W: Write>(
+        &self,
+        directory: &Path,
+        algorithm: HashAlgorithm,
+        encoding: OutputFormat,
+        walk: &WalkOptions,
+        format: BatchFormat,
+        writer: W,
+    ) -> Result<usize> {
+        let mut failures = 0;
+        let mut writer = RecordWriter::new(format, writer)?;
+
+        self.hash_directory(directory, algorithm, walk, |entry, result| {
+            if result.is_err() {
+                failures += 1;
+            }
+            writer.write(&FileRecord::new(entry, algorithm, &result, encoding))
+        })?;
+
+        Ok(failures)
+    }
+
+    /// Write a checksum manifest for the files selected in `directory`.
+    ///
+    /// Paths are written relative to `directory` in sorted order. Files that
+    /// cannot be hashed are reported on stderr and left out of the manifest,
+    /// as coreutils does; every file is returned as a record either way.
+    pub fn write_manifest<W: Write>(
+        &self,
+        directory: &Path,
+        algorithm: HashAlgorithm,
+        format: ManifestFormat,
+        binary: bool,
+        walk: &WalkOptions,
+        writer: &mut W,
+    ) -> Result<Vec<FileRecord>> {
+        let mut records = Vec::new();
+
+        self.hash_directory(directory, algorithm, walk, |entry, result| {
+            match result {
+                Ok(ref hash) => {
+                    let line = ManifestEntry::new(entry.display_path(), hash.to_hex(), binary);
+                    writeln!(writer, "{}", line.to_line(algorithm, format))?;
+                }
+                Err(ref e) => {
+                    eprintln!("❌ Error processing {}: {e}", entry.path.display());
+                }
+            }
+            records.push(FileRecord::new(
+                entry,
+                algorithm,
+                &result,
+                OutputFormat::Hex,
+            ));
+            Ok(())
+        })?;
+
+        writer.flush()?;
+        Ok(records)
+    }
+}
+
+/// Serializes [`FileRecord`]s in one of the batch record formats
+enum RecordWriter<W: Write> {
+    Text(W),
+    Csv(Box<csv::Writer<W>>),
+    Ndjson(W),
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn new(format: BatchFormat, writer: W) -> Result<Self> {
+        Ok(match format {
+            BatchFormat::Text => RecordWriter::Text(writer),
+            BatchFormat::Ndjson => RecordWriter::Ndjson(writer),
+            BatchFormat::Csv => {
+                let mut csv = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(writer);
+                // Written up front so an empty directory still yields a valid CSV file
+                csv.write_record(["path", "size", "mtime", "algorithm", "digest", "error"])?;
+                csv.flush()?;
+                RecordWriter::Csv(Box::new(csv))
+            }
+        })
+    }
+
+    fn write(&mut self, record: &FileRecord) -> Result<()> {
+        match self {
+            RecordWriter::Text(writer) => {
+                match (&record.digest, &record.error) {
+                    (Some(digest), _) => writeln!(writer, "{digest} {}", record.path)?,
+                    (None, Some(error)) => {
+                        eprintln!("❌ Error processing {}: {error}", record.path)
                     }
+                    (None, None) => {}
                 }
+                writer.flush()?;
+            }
+            RecordWriter::Csv(writer) => {
+                writer.serialize(record)?;
+                writer.flush()?;
+            }
+            RecordWriter::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writeln!(writer)?;
+                writer.flush()?;
             }
         }
-
         Ok(())
     }
 }
@@ -552,3 +928,143 @@ impl Default for HashForge {
         Self::new()
     }
 }
+
+/// Reads the chunks sent by [`HashForge::hash_reader_many`] until the sender hangs up
+struct ChunkReader {
+    receiver: mpsc::Receiver<Arc<[u8]>>,
+    chunk: Arc<[u8]>,
+    position: usize,
+}
+
+impl ChunkReader {
+    fn new(receiver: mpsc::Receiver<Arc<[u8]>>) -> Self {
+        Self {
+            receiver,
+            chunk: Arc::from(Vec::new()),
+            position: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Err(mpsc::RecvError) => return Ok(0),
+            }
+        }
+        let count = buf.len().min(self.chunk.len() - self.position);
+        buf[..count].copy_from_slice(&self.chunk[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_reader_many() {
+        let forge = HashForge::new().with_progress(false);
+        let data = b"hash forge ".repeat(5000);
+        let algorithms = [
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::XxHash32,
+        ];
+
+        let results = forge
+            .hash_reader_many(data.as_slice(), 0, &algorithms)
+            .unwrap();
+        for (result, algorithm) in results.iter().zip(algorithms) {
+            assert_eq!(result.algorithm, algorithm);
+            assert_eq!(
+                result.bytes,
+                forge.hash_bytes(&data, algorithm).unwrap().bytes
+            );
+        }
+    }
+
+    /// Hash `data` for every candidate, recording which were asked for
+    fn compute<'a>(
+        forge: &'a HashForge,
+        data: &'a [u8],
+        asked: &'a mut Vec<Vec<HashAlgorithm>>,
+    ) -> impl FnOnce(&[HashAlgorithm]) -> Result<Vec<HashResult>> + 'a {
+        move |algorithms| {
+            asked.push(algorithms.to_vec());
+            forge.hash_reader_many(data, 0, algorithms)
+        }
+    }
+
+    #[test]
+    fn test_verify_auto() {
+        let forge = HashForge::new().with_progress(false);
+        let data = b"hello world";
+        let sha256 = forge.hash_bytes(data, HashAlgorithm::Sha256).unwrap();
+        let mut asked = Vec::new();
+
+        // 32-byte digests could come from several algorithms; only one matches
+        let outcome = forge
+            .verify_auto(&sha256.to_hex(), compute(&forge, data, &mut asked))
+            .unwrap();
+        assert!(
+            matches!(outcome, DetectionOutcome::Matched(ref m) if m.algorithm == HashAlgorithm::Sha256)
+        );
+        assert_eq!(asked.len(), 1);
+        let candidates = &asked[0];
+        assert!(candidates.len() > 1);
+        assert!(candidates
+            .iter()
+            .enumerate()
+            .all(|(i, alg)| !candidates[..i].contains(alg)));
+
+        let other = forge.hash_bytes(b"goodbye", HashAlgorithm::Sha256).unwrap();
+        let outcome = forge
+            .verify_auto(&other.to_hex(), compute(&forge, data, &mut asked))
+            .unwrap();
+        assert!(
+            matches!(outcome, DetectionOutcome::NoMatch { ref candidates } if candidates.contains(&HashAlgorithm::Sha256))
+        );
+
+        // A tag leaves a single candidate
+        let tagged = format!("sha256:{}", sha256.to_hex());
+        forge
+            .verify_auto(&tagged, compute(&forge, data, &mut asked))
+            .unwrap();
+        assert_eq!(asked.last().unwrap(), &[HashAlgorithm::Sha256]);
+    }
+
+    #[test]
+    fn test_verify_auto_ambiguous() {
+        let forge = HashForge::new().with_progress(false);
+        // Pretend two algorithms produce the same digest
+        let digest = forge.hash_bytes(b"data", HashAlgorithm::Sha256).unwrap();
+        let outcome = forge
+            .verify_auto(&digest.to_hex(), |algorithms| {
+                Ok(algorithms
+                    .iter()
+                    .map(|&algorithm| {
+                        let bytes = match algorithm.output_size() {
+                            32 => digest.bytes.clone(),
+                            size => vec![0; size],
+                        };
+                        HashResult::new(bytes, algorithm)
+                    })
+                    .collect())
+            })
+            .unwrap();
+        let DetectionOutcome::Ambiguous { matches } = outcome else {
+            panic!("expected an ambiguous outcome, got {outcome:?}");
+        };
+        assert_eq!(
+            matches,
+            HashAlgorithm::candidates_for_length(digest.bytes.len())
+        );
+    }
+}
@@ -0,0 +1,214 @@
+// Transparent decompression of gzip, zstd, xz and bzip2 input before hashing
+use crate::{algorithms::HashAlgorithm, core::HashForge, output::HashResult};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use std::{
+    fmt, fs,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+/// A compression format recognized by its magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+impl Compression {
+    /// The format whose magic bytes start `header`, if any
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if header.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// Detect the format from the first bytes of `path`
+    pub fn detect(path: &Path) -> Result<Option<Self>> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let mut header = Vec::with_capacity(6);
+        file.take(6).read_to_end(&mut header)?;
+        Ok(Self::from_magic(&header))
+    }
+
+    /// Usual file name extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bz2",
+        }
+    }
+
+    /// Wrap `reader` in a decoder for this format.
+    ///
+    /// Concatenated streams, as written by `cat a.gz b.gz` or parallel
+    /// compressors, are decoded as one.
+    pub fn decoder<'a, R: Read + 'a>(&self, reader: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+            Compression::Xz => write!(f, "xz"),
+            Compression::Bzip2 => write!(f, "bzip2"),
+        }
+    }
+}
+
+impl Serialize for Compression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Value of `--decompress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Decompress {
+    /// Detect the format from the file's magic bytes; hash other files as they are
+    Auto,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+impl Decompress {
+    /// The compression to undo for `path`, or `None` if `auto` finds the
+    /// file uncompressed
+    pub fn resolve(&self, path: &Path) -> Result<Option<Compression>> {
+        Ok(match self {
+            Decompress::Auto => Compression::detect(path)?,
+            Decompress::Gzip => Some(Compression::Gzip),
+            Decompress::Zstd => Some(Compression::Zstd),
+            Decompress::Xz => Some(Compression::Xz),
+            Decompress::Bzip2 => Some(Compression::Bzip2),
+        })
+    }
+}
+
+impl HashForge {
+    /// Hash the decompressed contents of `path`.
+    ///
+    /// The digest cache is not used: it holds digests of the files as stored.
+    pub fn hash_file_decompressed(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        compression: Compression,
+    ) -> Result<HashResult> {
+        Ok(self
+            .hash_file_decompressed_many(path, &[algorithm], compression)?
+            .remove(0))
+    }
+
+    /// Like [`HashForge::hash_file_decompressed`], with each of `algorithms`
+    /// in a single pass
+    pub fn hash_file_decompressed_many(
+        &self,
+        path: &Path,
+        algorithms: &[HashAlgorithm],
+        compression: Compression,
+    ) -> Result<Vec<HashResult>> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let progress_bar = self.progress_bar(file.metadata()?.len());
+        let reader: Box<dyn Read> = match &progress_bar {
+            Some(pb) => Box::new(pb.wrap_read(BufReader::new(file))),
+            None => Box::new(BufReader::new(file)),
+        };
+
+        // The bar tracks the compressed bytes, so the decoder is hashed with
+        // no size of its own and gets no second bar
+        let decoder = compression.decoder(reader)?;
+        let results = self
+            .hash_reader_many(decoder, 0, algorithms)
+            .with_context(|| {
+                format!(
+                    "Failed to decompress {compression} data: {}",
+                    path.display()
+                )
+            })?;
+        if let Some(pb) = progress_bar {
+            pb.finish_with_message("Hash computed");
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_hash_file_decompressed() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let data = b"2026-10-19 12:00:00 service started\n".repeat(100);
+        let forge = HashForge::new().with_progress(false);
+        let expected = forge.hash_bytes(&data, HashAlgorithm::Sha256).unwrap();
+
+        let compressed: [(Compression, Vec<u8>); 4] = [
+            (Compression::Gzip, {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&data).unwrap();
+                encoder.finish().unwrap()
+            }),
+            (
+                Compression::Zstd,
+                zstd::encode_all(data.as_slice(), 0).unwrap(),
+            ),
+            (Compression::Xz, {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(&data).unwrap();
+                encoder.finish().unwrap()
+            }),
+            (Compression::Bzip2, {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(&data).unwrap();
+                encoder.finish().unwrap()
+            }),
+        ];
+        for (compression, bytes) in compressed {
+            let path = dir.join(format!("log.{}", compression.extension()));
+            fs::write(&path, bytes).unwrap();
+            assert_eq!(Decompress::Auto.resolve(&path).unwrap(), Some(compression));
+            let result = forge
+                .hash_file_decompressed(&path, HashAlgorithm::Sha256, compression)
+                .unwrap();
+            assert_eq!(result.bytes, expected.bytes);
+        }
+
+        let plain = dir.join("log");
+        fs::write(&plain, &data).unwrap();
+        assert_eq!(Decompress::Auto.resolve(&plain).unwrap(), None);
+        assert!(forge
+            .hash_file_decompressed(&plain, HashAlgorithm::Sha256, Compression::Gzip)
+            .is_err());
+    }
+}
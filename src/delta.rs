@@ -0,0 +1,647 @@
+// rsync-style signatures, deltas and patching
+//
+// The binary formats are specified in docs-dev/DELTA_FORMAT.md; keep the two
+// in sync.
+use crate::{algorithms::HashAlgorithm, core::HashForge, output::HashResult};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const SIGNATURE_MAGIC: &[u8; 4] = b"HFSG";
+const DELTA_MAGIC: &[u8; 4] = b"HFDL";
+const FORMAT_VERSION: u8 = 1;
+
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_LITERAL: u8 = 2;
+
+/// Longest run of new bytes written as a single literal command
+const MAX_LITERAL: usize = 1024 * 1024;
+
+/// Smallest block size accepted for a signature
+pub const MIN_BLOCK_SIZE: u32 = 64;
+/// Largest block size accepted for a signature
+pub const MAX_BLOCK_SIZE: u32 = 16 * 1024 * 1024;
+
+/// rsync's weak checksum over a window of bytes, updatable one byte at a time
+#[derive(Debug, Clone, Copy)]
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Self {
+        let mut rolling = Self {
+            a: 0,
+            b: 0,
+            len: window.len() as u32,
+        };
+        for &byte in window {
+            rolling.a = rolling.a.wrapping_add(u32::from(byte));
+            rolling.b = rolling.b.wrapping_add(rolling.a);
+        }
+        rolling
+    }
+
+    /// Slide the window one byte: drop `out` from the front, append `next`
+    fn roll(&mut self, out: u8, next: u8) {
+        self.a = self
+            .a
+            .wrapping_sub(u32::from(out))
+            .wrapping_add(u32::from(next));
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(u32::from(out)))
+            .wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+/// Checksums of one block of the basis file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: Vec<u8>,
+}
+
+/// Checksums of every block of a basis file, from which a delta can be
+/// computed without access to the file itself
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub algorithm: HashAlgorithm,
+    pub block_size: u32,
+    pub file_size: u64,
+    /// One entry per block; the last block may be short
+    pub blocks: Vec<BlockSignature>,
+}
+
+impl Signature {
+    /// Block size used when none is given: the square root of the file size,
+    /// rounded up to a multiple of 8 and kept between 700 bytes and 128 KiB,
+    /// as rsync does
+    pub fn default_block_size(file_size: u64) -> u32 {
+        let root = (file_size as f64).sqrt() as u64;
+        (root.div_ceil(8) * 8).clamp(700, 128 * 1024) as u32
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        write_header(
+            &mut writer,
+            SIGNATURE_MAGIC,
+            self.algorithm,
+            self.block_size,
+        )?;
+        writer.write_all(&self.file_size.to_be_bytes())?;
+        for block in &self.blocks {
+            writer.write_all(&block.weak.to_be_bytes())?;
+            writer.write_all(&block.strong)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(mut reader: R, path: &Path) -> Result<Self> {
+        let context = || format!("Invalid signature file: {}", path.display());
+        let (algorithm, block_size) =
+            read_header(&mut reader, SIGNATURE_MAGIC).with_context(context)?;
+        let file_size = read_u64(&mut reader).with_context(context)?;
+
+        let count = file_size.div_ceil(u64::from(block_size));
+        let mut blocks = Vec::with_capacity(count.min(1 << 20) as usize);
+        for _ in 0..count {
+            let weak = read_u32(&mut reader).with_context(context)?;
+            let mut strong = vec![0; algorithm.output_size()];
+            reader.read_exact(&mut strong).with_context(context)?;
+            blocks.push(BlockSignature { weak, strong });
+        }
+        if reader.read(&mut [0])? != 0 {
+            anyhow::bail!("{}: trailing data", context());
+        }
+
+        Ok(Self {
+            algorithm,
+            block_size,
+            file_size,
+            blocks,
+        })
+    }
+
+    /// Length of block `index`
+    fn block_len(&self, index: u64) -> u64 {
+        let start = index * u64::from(self.block_size);
+        u64::from(self.block_size).min(self.file_size - start)
+    }
+}
+
+/// What a delta is made of
+#[derive(Debug, Clone, Default)]
+pub struct DeltaSummary {
+    /// Size of the new file
+    pub target_size: u64,
+    /// Bytes the patch copies from the basis file
+    pub copied_bytes: u64,
+    /// Bytes carried in the delta itself
+    pub literal_bytes: u64,
+    pub copy_commands: usize,
+    pub literal_commands: usize,
+}
+
+/// Writes delta commands, merging copies of consecutive blocks
+struct DeltaWriter<'a, W: Write> {
+    writer: W,
+    signature: &'a Signature,
+    /// Copy not yet written: first block and number of blocks
+    pending: Option<(u64, u32)>,
+    summary: DeltaSummary,
+}
+
+impl<W: Write> DeltaWriter<'_, W> {
+    /// Block that would extend the pending copy
+    fn next_block(&self) -> Option<u64> {
+        self.pending.map(|(start, count)| start + u64::from(count))
+    }
+
+    fn copy(&mut self, block: u64) -> Result<()> {
+        match self.pending {
+            Some((start, count)) if start + u64::from(count) == block && count < u32::MAX => {
+                self.pending = Some((start, count + 1));
+            }
+            _ => {
+                self.flush_copy()?;
+                self.pending = Some((block, 1));
+            }
+        }
+        self.summary.copied_bytes += self.signature.block_len(block);
+        Ok(())
+    }
+
+    fn literal(&mut self, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.flush_copy()?;
+        for chunk in data.chunks(MAX_LITERAL) {
+            self.writer.write_all(&[OP_LITERAL])?;
+            self.writer.write_all(&(chunk.len() as u32).to_be_bytes())?;
+            self.writer.write_all(chunk)?;
+            self.summary.literal_commands += 1;
+        }
+        self.summary.literal_bytes += data.len() as u64;
+        Ok(())
+    }
+
+    fn flush_copy(&mut self) -> Result<()> {
+        if let Some((start, count)) = self.pending.take() {
+            self.writer.write_all(&[OP_COPY])?;
+            self.writer.write_all(&start.to_be_bytes())?;
+            self.writer.write_all(&count.to_be_bytes())?;
+            self.summary.copy_commands += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<DeltaSummary> {
+        self.flush_copy()?;
+        self.writer.write_all(&[OP_END])?;
+        self.writer.flush()?;
+        Ok(self.summary)
+    }
+}
+
+impl HashForge {
+    /// Compute the signature of `path`: a weak rolling checksum and a strong
+    /// `algorithm` digest of each block of `block_size` bytes
+    /// ([`Signature::default_block_size`] if `None`).
+    pub fn signature(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        block_size: Option<u32>,
+    ) -> Result<Signature> {
+        check_algorithm(algorithm)?;
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let file_size = file.metadata()?.len();
+        let block_size = block_size.unwrap_or_else(|| Signature::default_block_size(file_size));
+        check_block_size(block_size)?;
+
+        let progress_bar = self.progress_bar(file_size);
+        let mut reader: Box<dyn Read> = match &progress_bar {
+            Some(pb) => Box::new(pb.wrap_read(BufReader::new(file))),
+            None => Box::new(BufReader::new(file)),
+        };
+        let block_forge = HashForge::new().with_progress(false);
+        let mut blocks = Vec::new();
+        let mut buffer = Vec::with_capacity(block_size as usize);
+        let mut remaining = file_size;
+        while remaining > 0 {
+            let len = remaining.min(u64::from(block_size));
+            buffer.clear();
+            (&mut reader).take(len).read_to_end(&mut buffer)?;
+            if buffer.len() as u64 != len {
+                anyhow::bail!("{} shrank while being read", path.display());
+            }
+            blocks.push(BlockSignature {
+                weak: Rolling::new(&buffer).digest(),
+                strong: block_forge.hash_bytes(&buffer, algorithm)?.bytes,
+            });
+            remaining -= len;
+        }
+        if let Some(pb) = progress_bar {
+            pb.finish_with_message("Signature computed");
+        }
+
+        Ok(Signature {
+            algorithm,
+            block_size,
+            file_size,
+            blocks,
+        })
+    }
+
+    /// Write a delta that turns the file `signature` was computed from into
+    /// `new_file`.
+    ///
+    /// Every offset of `new_file` is checked against the weak checksums, and
+    /// a block is only reused when its strong digest matches too. The delta
+    /// records the size and digest of `new_file`, so a patch applied to the
+    /// wrong basis file is detected. `new_file` is read twice: once for that
+    /// digest and once to match blocks.
+    pub fn delta<W: Write>(
+        &self,
+        signature: &Signature,
+        new_file: &Path,
+        writer: W,
+    ) -> Result<DeltaSummary> {
+        let algorithm = signature.algorithm;
+        let target = self.hash_file(new_file, algorithm)?;
+        let file = fs::File::open(new_file)
+            .with_context(|| format!("Failed to open file: {}", new_file.display()))?;
+        let target_size = file.metadata()?.len();
+
+        let mut writer = BufWriter::new(writer);
+        write_header(&mut writer, DELTA_MAGIC, algorithm, signature.block_size)?;
+        writer.write_all(&signature.file_size.to_be_bytes())?;
+        writer.write_all(&target_size.to_be_bytes())?;
+        writer.write_all(&target.bytes)?;
+        let mut out = DeltaWriter {
+            writer,
+            signature,
+            pending: None,
+            summary: DeltaSummary {
+                target_size,
+                ..DeltaSummary::default()
+            },
+        };
+
+        // Full blocks by weak checksum; a short last block can only match at
+        // the very end of the new file
+        let block_size = signature.block_size as usize;
+        let mut full: HashMap<u32, Vec<u64>> = HashMap::new();
+        let mut tail = None;
+        for (index, block) in signature.blocks.iter().enumerate() {
+            let index = index as u64;
+            if signature.block_len(index) == block_size as u64 {
+                full.entry(block.weak).or_default().push(index);
+            } else {
+                tail = Some((index, signature.block_len(index) as usize));
+            }
+        }
+
+        let progress_bar = self.progress_bar(target_size);
+        let mut reader: Box<dyn Read> = match &progress_bar {
+            Some(pb) => Box::new(pb.wrap_read(BufReader::new(file))),
+            None => Box::new(BufReader::new(file)),
+        };
+        let block_forge = HashForge::new().with_progress(false);
+        let strong_match = |window: &[u8], candidates: &[u64], preferred: Option<u64>| {
+            let strong = block_forge.hash_bytes(window, algorithm)?.bytes;
+            let matches = |index: &u64| signature.blocks[*index as usize].strong == strong;
+            Ok::<_, anyhow::Error>(
+                preferred
+                    .filter(|index| candidates.contains(index) && matches(index))
+                    .or_else(|| candidates.iter().copied().find(matches)),
+            )
+        };
+
+        // `buffer[literal..pos]` is not yet written; `buffer[pos..]` is the
+        // window being matched and what follows it
+        let mut buffer = Vec::new();
+        let mut pos = 0;
+        let mut literal = 0;
+        let mut eof = false;
+        let mut rolling: Option<Rolling> = None;
+        loop {
+            // Keep one byte beyond the window, to roll it forward
+            if !eof && buffer.len() < pos + block_size + 1 {
+                out.literal(&buffer[literal..pos])?;
+                buffer.drain(..pos);
+                pos = 0;
+                literal = 0;
+                while !eof && buffer.len() < block_size + 1 {
+                    let want = (block_size + 1).max(64 * 1024) as u64;
+                    eof = (&mut reader).take(want).read_to_end(&mut buffer)? == 0;
+                }
+            }
+
+            let window = &buffer[pos..buffer.len().min(pos + block_size)];
+            if window.is_empty() {
+                break;
+            }
+            let matched = if window.len() == block_size {
+                let weak = rolling.get_or_insert_with(|| Rolling::new(window)).digest();
+                match full.get(&weak) {
+                    Some(candidates) => strong_match(window, candidates, out.next_block())?,
+                    None => None,
+                }
+            } else {
+                // Only at the end of the file: the rest can match nothing but
+                // a short last block of the same length
+                match tail {
+                    Some((_, len)) if len < window.len() => {
+                        pos = buffer.len() - len;
+                        rolling = None;
+                        continue;
+                    }
+                    Some((index, len))
+                        if len == window.len()
+                            && Rolling::new(window).digest()
+                                == signature.blocks[index as usize].weak =>
+                    {
+                        strong_match(window, &[index], None)?
+                    }
+                    _ => None,
+                }
+            };
+
+            if let Some(index) = matched {
+                out.literal(&buffer[literal..pos])?;
+                out.copy(index)?;
+                pos += window.len();
+                literal = pos;
+                rolling = None;
+                continue;
+            }
+            if window.len() < block_size {
+                pos = buffer.len();
+                continue;
+            }
+
+            match (&mut rolling, buffer.get(pos + block_size)) {
+                (Some(weak), Some(&next)) => weak.roll(buffer[pos], next),
+                _ => rolling = None,
+            }
+            pos += 1;
+            if pos - literal >= MAX_LITERAL {
+                out.literal(&buffer[literal..pos])?;
+                literal = pos;
+            }
+        }
+        out.literal(&buffer[literal..pos])?;
+        if let Some(pb) = progress_bar {
+            pb.finish_with_message("Delta computed");
+        }
+
+        let summary = out.finish()?;
+        if summary.copied_bytes + summary.literal_bytes != target_size {
+            anyhow::bail!("{} changed while being read", new_file.display());
+        }
+        Ok(summary)
+    }
+
+    /// Apply `delta` to `basis`, writing the result to `output`.
+    ///
+    /// The result is written to a temporary file next to `output` and only
+    /// renamed into place once its size and digest match those recorded in
+    /// the delta, so `output` may be `basis` itself. Returns the digest of
+    /// the result.
+    pub fn patch(&self, basis: &Path, delta: &Path, output: &Path) -> Result<HashResult> {
+        let context = || format!("Invalid delta file: {}", delta.display());
+        let mut reader = BufReader::new(
+            fs::File::open(delta)
+                .with_context(|| format!("Failed to open delta: {}", delta.display()))?,
+        );
+        let (algorithm, block_size) =
+            read_header(&mut reader, DELTA_MAGIC).with_context(context)?;
+        let basis_size = read_u64(&mut reader).with_context(context)?;
+        let target_size = read_u64(&mut reader).with_context(context)?;
+        let mut target = vec![0; algorithm.output_size()];
+        reader.read_exact(&mut target).with_context(context)?;
+
+        let mut basis_file = fs::File::open(basis)
+            .with_context(|| format!("Failed to open file: {}", basis.display()))?;
+        let actual_size = basis_file.metadata()?.len();
+        if actual_size != basis_size {
+            anyhow::bail!(
+                "{} is {actual_size} bytes, but the delta was made for a {basis_size}-byte file",
+                basis.display()
+            );
+        }
+
+        let name = output
+            .file_name()
+            .context("Output has no file name")?
+            .to_string_lossy();
+        let temp = output.with_file_name(format!(".{name}.hash-forge-patch{}", std::process::id()));
+        let result = (|| {
+            let mut writer = BufWriter::new(
+                fs::File::create(&temp)
+                    .with_context(|| format!("Failed to create file: {}", temp.display()))?,
+            );
+            loop {
+                let mut op = [0];
+                reader.read_exact(&mut op).with_context(context)?;
+                match op[0] {
+                    OP_END => break,
+                    OP_COPY => {
+                        let start = read_u64(&mut reader).with_context(context)?;
+                        let count = read_u32(&mut reader).with_context(context)?;
+                        let offset = start
+                            .checked_mul(u64::from(block_size))
+                            .filter(|offset| *offset < basis_size && count > 0)
+                            .with_context(|| format!("{}: copy outside the basis", context()))?;
+                        let len =
+                            (u64::from(count) * u64::from(block_size)).min(basis_size - offset);
+                        basis_file.seek(SeekFrom::Start(offset))?;
+                        let copied = std::io::copy(&mut (&mut basis_file).take(len), &mut writer)?;
+                        if copied != len {
+                            anyhow::bail!("{} shrank while being read", basis.display());
+                        }
+                    }
+                    OP_LITERAL => {
+                        let len = u64::from(read_u32(&mut reader).with_context(context)?);
+                        let copied = std::io::copy(&mut (&mut reader).take(len), &mut writer)?;
+                        if copied != len {
+                            anyhow::bail!("{}: truncated literal", context());
+                        }
+                    }
+                    other => anyhow::bail!("{}: unknown command {other}", context()),
+                }
+            }
+            writer.flush()?;
+            drop(writer);
+
+            let file = fs::File::open(&temp)?;
+            let size = file.metadata()?.len();
+            let result = self.hash_reader(BufReader::new(file), size, algorithm)?;
+            if size != target_size || result.bytes != target {
+                anyhow::bail!(
+                    "Patched file does not match the delta; was it made for {}?",
+                    basis.display()
+                );
+            }
+            fs::rename(&temp, output)
+                .with_context(|| format!("Failed to write {}", output.display()))?;
+            Ok(result)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+}
+
+fn check_algorithm(algorithm: HashAlgorithm) -> Result<()> {
+    if !algorithm.is_cryptographic() || algorithm.is_password_hash() {
+        anyhow::bail!(
+            "Signatures need a cryptographic hash; {} is not one",
+            algorithm.name()
+        );
+    }
+    Ok(())
+}
+
+fn check_block_size(block_size: u32) -> Result<()> {
+    if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+        anyhow::bail!(
+            "Block size must be between {MIN_BLOCK_SIZE} and {MAX_BLOCK_SIZE} bytes, not {block_size}"
+        );
+    }
+    Ok(())
+}
+
+/// Magic, format version, algorithm name and block size
+fn write_header<W: Write>(
+    writer: &mut W,
+    magic: &[u8; 4],
+    algorithm: HashAlgorithm,
+    block_size: u32,
+) -> Result<()> {
+    let name = algorithm.name().as_bytes();
+    writer.write_all(magic)?;
+    writer.write_all(&[FORMAT_VERSION, name.len() as u8])?;
+    writer.write_all(name)?;
+    writer.write_all(&block_size.to_be_bytes())?;
+    Ok(())
+}
+
+fn read_header<R: Read>(reader: &mut R, magic: &[u8; 4]) -> Result<(HashAlgorithm, u32)> {
+    let mut found = [0; 4];
+    reader.read_exact(&mut found)?;
+    if &found != magic {
+        anyhow::bail!("wrong magic number");
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    if version[0] != FORMAT_VERSION {
+        anyhow::bail!("unsupported format version {}", version[0]);
+    }
+    let mut name = vec![0; usize::from(version[1])];
+    reader.read_exact(&mut name)?;
+    let name = String::from_utf8(name).context("algorithm name is not UTF-8")?;
+    let algorithm = HashAlgorithm::from_str(&name, false).map_err(anyhow::Error::msg)?;
+    check_algorithm(algorithm)?;
+    let block_size = read_u32(reader)?;
+    check_block_size(block_size)?;
+    Ok((algorithm, block_size))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_checksum() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut rolling = Rolling::new(&data[..100]);
+        for start in 1..=900 {
+            rolling.roll(data[start - 1], data[start + 99]);
+            assert_eq!(
+                rolling.digest(),
+                Rolling::new(&data[start..start + 100]).digest()
+            );
+        }
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let old: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        // Insert, overwrite and drop a few bytes
+        let mut new = old[..30_000].to_vec();
+        new.extend_from_slice(b"inserted bytes");
+        new.extend_from_slice(&old[30_000..60_000]);
+        new.extend_from_slice(&[0; 100]);
+        new.extend_from_slice(&old[60_100..99_000]);
+        fs::write(dir.join("old"), &old).unwrap();
+        fs::write(dir.join("new"), &new).unwrap();
+
+        let forge = HashForge::new().with_progress(false);
+        let signature = forge
+            .signature(&dir.join("old"), HashAlgorithm::Sha256, Some(1024))
+            .unwrap();
+        let mut encoded = Vec::new();
+        signature.write(&mut encoded).unwrap();
+        let signature = Signature::read(encoded.as_slice(), Path::new("sig")).unwrap();
+        assert_eq!(signature.blocks.len(), 98);
+
+        let mut delta = Vec::new();
+        let summary = forge
+            .delta(&signature, &dir.join("new"), &mut delta)
+            .unwrap();
+        assert_eq!(summary.target_size, new.len() as u64);
+        assert!(summary.literal_bytes < 3 * 1024);
+        fs::write(dir.join("delta"), &delta).unwrap();
+
+        forge
+            .patch(&dir.join("old"), &dir.join("delta"), &dir.join("patched"))
+            .unwrap();
+        assert_eq!(fs::read(dir.join("patched")).unwrap(), new);
+        // The delta only applies to the file it was made for
+        assert!(forge
+            .patch(&dir.join("new"), &dir.join("delta"), &dir.join("wrong"))
+            .is_err());
+        assert!(!dir.join("wrong").exists());
+    }
+}
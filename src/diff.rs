@@ -0,0 +1,299 @@
+// Comparison of checksum manifests and live directories
+use crate::{algorithms::HashAlgorithm, checksum::parse_line, core::HashForge, walk::WalkOptions};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+/// Where a snapshot came from
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SnapshotSource {
+    Manifest { path: PathBuf },
+    Directory { path: PathBuf },
+}
+
+/// Hex digests of a set of files, keyed by `/`-separated relative path
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub source: SnapshotSource,
+    pub algorithm: HashAlgorithm,
+    pub files: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    /// Read a GNU or BSD format checksum manifest.
+    ///
+    /// GNU lines use `algorithm`; BSD tag lines name their own, and all lines
+    /// must agree. Unlike `check`, a malformed line is an error: a snapshot
+    /// that silently lost entries would report them as added or removed.
+    pub fn from_manifest<R: BufRead>(
+        reader: R,
+        path: &Path,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self> {
+        let mut manifest_algorithm = None;
+        let mut files = BTreeMap::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let (tag, entry) = parse_line(&line).with_context(|| {
+                format!(
+                    "{}:{}: improperly formatted checksum line",
+                    path.display(),
+                    index + 1
+                )
+            })?;
+
+            let line_algorithm = tag.unwrap_or(algorithm);
+            match manifest_algorithm {
+                None => manifest_algorithm = Some(line_algorithm),
+                Some(previous) if previous != line_algorithm => anyhow::bail!(
+                    "{}:{}: {} line in a {} manifest",
+                    path.display(),
+                    index + 1,
+                    line_algorithm.name(),
+                    previous.name()
+                ),
+                Some(_) => {}
+            }
+            if entry.digest.len() != line_algorithm.output_size() * 2 {
+                anyhow::bail!(
+                    "{}:{}: digest is not a {} digest",
+                    path.display(),
+                    index + 1,
+                    line_algorithm.name()
+                );
+            }
+            files.insert(normalize_path(&entry.path), entry.digest);
+        }
+
+        Ok(Self {
+            source: SnapshotSource::Manifest {
+                path: path.to_path_buf(),
+            },
+            algorithm: manifest_algorithm.unwrap_or(algorithm),
+            files,
+        })
+    }
+}
+
+impl HashForge {
+    /// Hash every file selected in `directory` into a snapshot.
+    ///
+    /// Paths are relative to `directory`, as in a manifest written by
+    /// `manifest -d`. A file that cannot be read fails the snapshot.
+    pub fn snapshot_directory(
+        &self,
+        directory: &Path,
+        algorithm: HashAlgorithm,
+        walk: &WalkOptions,
+    ) -> Result<Snapshot> {
+        let mut files = BTreeMap::new();
+        self.hash_directory(directory, algorithm, walk, |entry, result| {
+            let result =
+                result.with_context(|| format!("Failed to hash {}", entry.path.display()))?;
+            files.insert(entry.display_path(), result.to_hex());
+            Ok(())
+        })?;
+
+        Ok(Snapshot {
+            source: SnapshotSource::Directory {
+                path: directory.to_path_buf(),
+            },
+            algorithm,
+            files,
+        })
+    }
+}
+
+/// A file whose contents changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModifiedFile {
+    pub path: String,
+    pub old_digest: String,
+    pub new_digest: String,
+}
+
+/// A file that moved without changing contents
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenamedFile {
+    pub from: String,
+    pub to: String,
+    pub digest: String,
+}
+
+/// Differences between two snapshots, each list sorted by path (renames by
+/// their new path)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<ModifiedFile>,
+    pub renamed: Vec<RenamedFile>,
+    pub unchanged: usize,
+}
+
+impl SnapshotDiff {
+    pub fn is_identical(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.renamed.is_empty()
+    }
+}
+
+/// Compare two snapshots hashed with the same algorithm.
+///
+/// A path present in only one snapshot is a rename when the other snapshot
+/// has a path, also present only there, with the same digest. When several
+/// files share a digest, removed and added paths are paired in sorted order
+/// and the rest are reported as added or removed.
+pub fn diff_snapshots(old: &Snapshot, new: &Snapshot) -> Result<SnapshotDiff> {
+    if old.algorithm != new.algorithm {
+        anyhow::bail!(
+            "Cannot compare a {} snapshot with a {} snapshot",
+            old.algorithm.name(),
+            new.algorithm.name()
+        );
+    }
+
+    let mut diff = SnapshotDiff::default();
+    let mut removed: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (path, digest) in &old.files {
+        match new.files.get(path) {
+            Some(new_digest) if new_digest == digest => diff.unchanged += 1,
+            Some(new_digest) => diff.modified.push(ModifiedFile {
+                path: path.clone(),
+                old_digest: digest.clone(),
+                new_digest: new_digest.clone(),
+            }),
+            None => removed
+                .entry(digest.as_str())
+                .or_default()
+                .push(path.as_str()),
+        }
+    }
+
+    let mut renamed_from = BTreeSet::new();
+    for (path, digest) in &new.files {
+        if old.files.contains_key(path) {
+            continue;
+        }
+        let source = removed.get_mut(digest.as_str()).and_then(|paths| {
+            let from = paths.first().copied()?;
+            paths.remove(0);
+            Some(from)
+        });
+        match source {
+            Some(from) => {
+                renamed_from.insert(from);
+                diff.renamed.push(RenamedFile {
+                    from: from.to_string(),
+                    to: path.clone(),
+                    digest: digest.clone(),
+                });
+            }
+            None => diff.added.push(path.clone()),
+        }
+    }
+
+    diff.removed = old
+        .files
+        .keys()
+        .filter(|path| !new.files.contains_key(*path) && !renamed_from.contains(path.as_str()))
+        .cloned()
+        .collect();
+    Ok(diff)
+}
+
+/// Drop a leading `./`, so manifests written as `sha256sum ./*` line up
+/// with directory walks
+fn normalize_path(path: &str) -> String {
+    let mut path = path;
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(files: &[(&str, &str)]) -> Snapshot {
+        Snapshot {
+            source: SnapshotSource::Directory {
+                path: PathBuf::from("."),
+            },
+            algorithm: HashAlgorithm::Sha256,
+            files: files
+                .iter()
+                .map(|(path, digest)| (path.to_string(), digest.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let old = snapshot(&[
+            ("a", "01"),
+            ("b", "02"),
+            ("c", "03"),
+            ("d", "04"),
+            ("e", "04"),
+        ]);
+        let new = snapshot(&[
+            ("a", "01"),
+            ("b", "ff"),
+            ("moved/c", "03"),
+            ("f", "04"),
+            ("g", "05"),
+        ]);
+        let diff = diff_snapshots(&old, &new).unwrap();
+
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].path, "b");
+        let renames: Vec<_> = diff
+            .renamed
+            .iter()
+            .map(|r| (r.from.as_str(), r.to.as_str()))
+            .collect();
+        assert_eq!(renames, [("d", "f"), ("c", "moved/c")]);
+        assert_eq!(diff.removed, ["e"]);
+        assert_eq!(diff.added, ["g"]);
+        assert!(!diff.is_identical());
+        assert!(diff_snapshots(&old, &old).unwrap().is_identical());
+    }
+
+    #[test]
+    fn test_manifest_snapshot() {
+        let digest = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let manifest = format!("{digest}  ./hello.txt\nSHA256 (dir/x) = {digest}\n");
+        let snapshot = Snapshot::from_manifest(
+            manifest.as_bytes(),
+            Path::new("SUMS"),
+            HashAlgorithm::Sha256,
+        )
+        .unwrap();
+        assert_eq!(
+            snapshot.files.keys().collect::<Vec<_>>(),
+            ["dir/x", "hello.txt"]
+        );
+
+        let mixed = format!("{digest}  a\nMD5 (b) = d41d8cd98f00b204e9800998ecf8427e\n");
+        assert!(Snapshot::from_manifest(
+            mixed.as_bytes(),
+            Path::new("SUMS"),
+            HashAlgorithm::Sha256
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,388 @@
+// Duplicate file detection and hardlink deduplication
+use crate::{
+    algorithms::HashAlgorithm,
+    core::HashForge,
+    output::HashResult,
+    walk::{collect_files, WalkEntry, WalkOptions},
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Bytes read from each end of a file for the partial hash
+const PARTIAL_BLOCK: u64 = 64 * 1024;
+
+/// Options for [`HashForge::find_duplicates`]
+#[derive(Debug, Clone, Copy)]
+pub struct DupeOptions {
+    /// Ignore files smaller than this many bytes
+    pub min_size: u64,
+}
+
+impl Default for DupeOptions {
+    fn default() -> Self {
+        // Empty files are all "identical" but waste nothing
+        Self { min_size: 1 }
+    }
+}
+
+/// Files with identical contents
+#[derive(Debug, Clone)]
+pub struct DuplicateSet {
+    /// Size of each file in bytes
+    pub size: u64,
+    pub digest: HashResult,
+    /// Files in sorted path order; the first is the one to keep
+    pub files: Vec<WalkEntry>,
+}
+
+impl DuplicateSet {
+    /// Bytes that would be freed by keeping a single copy
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+/// A file that could not be examined or replaced
+#[derive(Debug, Clone, Serialize)]
+pub struct FileError {
+    pub path: String,
+    pub error: String,
+}
+
+/// Result of a duplicate search
+#[derive(Debug, Clone, Default)]
+pub struct Duplicates {
+    /// Sets sorted by wasted space, largest first
+    pub sets: Vec<DuplicateSet>,
+    /// Files considered (after hardlinks to the same inode are merged)
+    pub scanned: usize,
+    /// Files fully hashed after the size and partial-hash passes
+    pub hashed: usize,
+    /// Files that could not be read, and so may hide duplicates
+    pub skipped: Vec<FileError>,
+}
+
+impl Duplicates {
+    pub fn wasted(&self) -> u64 {
+        self.sets.iter().map(DuplicateSet::wasted).sum()
+    }
+}
+
+impl HashForge {
+    /// Find files with identical contents under `directory`.
+    ///
+    /// Candidates are narrowed in three passes so that most files are never
+    /// read in full: files are grouped by size, then by an xxh3 hash of their
+    /// first and last 64 KiB, and only then by a full `algorithm` digest.
+    /// Paths that are already hardlinks to the same file count once. The walk
+    /// is always recursive unless `walk.max_depth` limits it.
+    pub fn find_duplicates(
+        &self,
+        directory: &Path,
+        algorithm: HashAlgorithm,
+        walk: &WalkOptions,
+        options: &DupeOptions,
+    ) -> Result<Duplicates> {
+        if !algorithm.is_cryptographic() || algorithm.is_password_hash() {
+            anyhow::bail!(
+                "Duplicate detection needs a cryptographic hash; {} is not one",
+                algorithm.name()
+            );
+        }
+
+        let walk = WalkOptions {
+            recursive: true,
+            ..walk.clone()
+        };
+        let mut duplicates = Duplicates::default();
+        let mut by_size: BTreeMap<u64, Vec<WalkEntry>> = BTreeMap::new();
+        let mut inodes = HashSet::new();
+        for entry in collect_files(directory, &walk)? {
+            // Link target paths are not file contents
+            if entry.link_target.is_some() {
+                continue;
+            }
+            let metadata = match fs::metadata(&entry.path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    duplicates.skipped.push(FileError {
+                        path: entry.display_path(),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            if metadata.len() < options.min_size {
+                continue;
+            }
+            if let Some(id) = file_id(&metadata) {
+                if !inodes.insert(id) {
+                    continue;
+                }
+            }
+            duplicates.scanned += 1;
+            by_size.entry(metadata.len()).or_default().push(entry);
+        }
+
+        for (size, entries) in by_size {
+            if entries.len() < 2 {
+                continue;
+            }
+            let partial = group_by(&mut duplicates.skipped, entries, |entry| {
+                partial_hash(&entry.path, size)
+            });
+            for candidates in partial.into_values() {
+                duplicates.hashed += candidates.len();
+                let full = group_by(&mut duplicates.skipped, candidates, |entry| {
+                    self.hash_file(&entry.path, algorithm)
+                        .map(|result| result.bytes)
+                });
+                for (digest, files) in full {
+                    duplicates.sets.push(DuplicateSet {
+                        size,
+                        digest: HashResult::new(digest, algorithm),
+                        files,
+                    });
+                }
+            }
+        }
+
+        duplicates.sets.sort_by(|a, b| {
+            b.wasted()
+                .cmp(&a.wasted())
+                .then(a.files[0].relative.cmp(&b.files[0].relative))
+        });
+        Ok(duplicates)
+    }
+
+    /// Replace every file of `set` but the first with a hardlink to the first.
+    ///
+    /// Each duplicate is hashed again just before it is replaced, so a file
+    /// that changed since the scan is left alone. The link is created under a
+    /// temporary name and renamed over the duplicate, so the path never goes
+    /// missing. Returns the outcome for each replaced path.
+    pub fn hardlink_duplicates(&self, set: &DuplicateSet) -> Vec<(WalkEntry, Result<()>)> {
+        let Some((keep, others)) = set.files.split_first() else {
+            return Vec::new();
+        };
+        others
+            .iter()
+            .map(|duplicate| {
+                (
+                    duplicate.clone(),
+                    self.hardlink(keep, duplicate, &set.digest),
+                )
+            })
+            .collect()
+    }
+
+    fn hardlink(&self, keep: &WalkEntry, duplicate: &WalkEntry, digest: &HashResult) -> Result<()> {
+        // Read the bytes again rather than trusting a cached digest
+        for entry in [keep, duplicate] {
+            let file = fs::File::open(&entry.path)
+                .with_context(|| format!("Failed to open file: {}", entry.path.display()))?;
+            let size = file.metadata()?.len();
+            let current = self.hash_reader(BufReader::new(file), size, digest.algorithm)?;
+            if current.bytes != digest.bytes {
+                anyhow::bail!("{} changed since it was scanned", entry.path.display());
+            }
+        }
+
+        replace_with_link(&keep.path, &duplicate.path, |from, to| fs::rename(from, to))
+    }
+}
+
+/// Hardlink `keep` under a temporary name next to `target`, then move the
+/// link over `target` with `rename`, removing it again if that fails
+fn replace_with_link(
+    keep: &Path,
+    target: &Path,
+    rename: impl FnOnce(&Path, &Path) -> io::Result<()>,
+) -> Result<()> {
+    let name = target
+        .file_name()
+        .context("Duplicate has no file name")?
+        .to_string_lossy();
+    let temp = target.with_file_name(format!(".{name}.hash-forge-link{}", std::process::id()));
+    fs::hard_link(keep, &temp)
+        .with_context(|| format!("Failed to link {} to {}", temp.display(), keep.display()))?;
+    rename(&temp, target).map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        anyhow::Error::new(e).context(format!("Failed to replace {}", target.display()))
+    })
+}
+
+/// Split `entries` by `key`, keeping only groups of two or more. Entries whose
+/// key cannot be computed are recorded in `skipped`.
+fn group_by<F>(
+    skipped: &mut Vec<FileError>,
+    entries: Vec<WalkEntry>,
+    mut key: F,
+) -> BTreeMap<Vec<u8>, Vec<WalkEntry>>
+where
+    F: FnMut(&WalkEntry) -> Result<Vec<u8>>,
+{
+    let mut groups: BTreeMap<Vec<u8>, Vec<WalkEntry>> = BTreeMap::new();
+    for entry in entries {
+        match key(&entry) {
+            Ok(key) => groups.entry(key).or_default().push(entry),
+            Err(e) => skipped.push(FileError {
+                path: entry.display_path(),
+                error: format!("{e:#}"),
+            }),
+        }
+    }
+    groups.retain(|_, files| files.len() > 1);
+    groups
+}
+
+/// xxh3 of the first and last [`PARTIAL_BLOCK`] bytes of a `size`-byte file
+fn partial_hash(path: &Path, size: u64) -> Result<Vec<u8>> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let mut buffer = Vec::with_capacity(2 * PARTIAL_BLOCK as usize);
+    (&mut file).take(PARTIAL_BLOCK).read_to_end(&mut buffer)?;
+    if size > PARTIAL_BLOCK {
+        let tail = PARTIAL_BLOCK.min(size - PARTIAL_BLOCK);
+        file.seek(SeekFrom::Start(size - tail))?;
+        file.take(tail).read_to_end(&mut buffer)?;
+    }
+    Ok(xxhash_rust::xxh3::xxh3_64(&buffer).to_be_bytes().to_vec())
+}
+
+/// Identity of the file behind a path, so hardlinks are only counted once
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some((metadata.dev(), metadata.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_duplicates() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        // Same size and same first/last block, different middle
+        let mut a = vec![0u8; 3 * PARTIAL_BLOCK as usize];
+        let b = a.clone();
+        a[PARTIAL_BLOCK as usize + 1] = 1;
+        fs::write(root.join("a"), &a).unwrap();
+        fs::write(root.join("b"), &b).unwrap();
+        fs::write(root.join("sub/b"), &b).unwrap();
+        fs::write(root.join("empty1"), "").unwrap();
+        fs::write(root.join("empty2"), "").unwrap();
+
+        let forge = HashForge::new();
+        let found = forge
+            .find_duplicates(
+                root,
+                HashAlgorithm::Sha256,
+                &WalkOptions::default(),
+                &DupeOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(found.scanned, 3);
+        assert_eq!(found.hashed, 3);
+        assert_eq!(found.sets.len(), 1);
+        let paths: Vec<_> = found.sets[0]
+            .files
+            .iter()
+            .map(|f| f.display_path())
+            .collect();
+        assert_eq!(paths, ["b", "sub/b"]);
+        assert_eq!(found.wasted(), b.len() as u64);
+    }
+
+    /// Names in `dir`, sorted
+    fn listing(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn scan(forge: &HashForge, root: &Path) -> DuplicateSet {
+        let mut found = forge
+            .find_duplicates(
+                root,
+                HashAlgorithm::Sha256,
+                &WalkOptions::default(),
+                &DupeOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(found.sets.len(), 1);
+        found.sets.remove(0)
+    }
+
+    #[test]
+    fn test_hardlink_duplicates() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::write(root.join("a"), "same").unwrap();
+        fs::write(root.join("b"), "same").unwrap();
+        let forge = HashForge::new().with_progress(false);
+        let set = scan(&forge, root);
+
+        let results = forge.hardlink_duplicates(&set);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.display_path(), "b");
+        results[0].1.as_ref().unwrap();
+        let id = |name| file_id(&fs::metadata(root.join(name)).unwrap());
+        assert_eq!(id("a"), id("b"));
+        assert_eq!(fs::read_to_string(root.join("b")).unwrap(), "same");
+        assert_eq!(listing(root), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_hardlink_refuses_changed_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::write(root.join("a"), "same").unwrap();
+        fs::write(root.join("b"), "same").unwrap();
+        let forge = HashForge::new().with_progress(false);
+        let set = scan(&forge, root);
+
+        fs::write(root.join("b"), "diff").unwrap();
+        let results = forge.hardlink_duplicates(&set);
+        let error = results[0].1.as_ref().unwrap_err();
+        assert!(error.to_string().contains("changed since it was scanned"));
+        assert_eq!(fs::read_to_string(root.join("b")).unwrap(), "diff");
+        assert_eq!(listing(root), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_replace_with_link_rename_failure() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::write(root.join("a"), "same").unwrap();
+        fs::write(root.join("b"), "same").unwrap();
+
+        let result = replace_with_link(&root.join("a"), &root.join("b"), |from, _| {
+            assert!(from.exists());
+            Err(io::Error::other("rename failed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(listing(root), ["a", "b"]);
+        let id = |name| file_id(&fs::metadata(root.join(name)).unwrap());
+        assert_ne!(id("a"), id("b"));
+    }
+}
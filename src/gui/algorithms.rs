@@ -1,15 +1,17 @@
 // Algorithm filtering and categorization logic
 use super::app_state::{AlgorithmCategory, HashForgeApp};
 use crate::algorithms::HashAlgorithm;
+use crate::input::InputEncoding;
 use crate::output::OutputFormat;
 
 // All available algorithms including Phase 1 additions
-pub const ALL_ALGORITHMS: [HashAlgorithm; 19] = [
+pub const ALL_ALGORITHMS: [HashAlgorithm; 20] = [
     // Fast hash algorithms
     HashAlgorithm::Blake3,
     HashAlgorithm::Blake2b,
     HashAlgorithm::Blake2s,
     HashAlgorithm::Sha256,
+    HashAlgorithm::Sha384,
     HashAlgorithm::Sha512,
     HashAlgorithm::Sha3_224,
     HashAlgorithm::Sha3_256,
@@ -36,7 +38,23 @@ pub const ALL_CATEGORIES: [AlgorithmCategory; 5] = [
     AlgorithmCategory::Legacy,
 ];
 
-pub const AVAILABLE_OUTPUT_FORMATS: [OutputFormat; 2] = [OutputFormat::Hex, OutputFormat::Base64];
+/// Text encodings offered in the GUI; raw bytes cannot be shown in a text field
+pub const AVAILABLE_OUTPUT_FORMATS: [OutputFormat; 6] = [
+    OutputFormat::Hex,
+    OutputFormat::HexUpper,
+    OutputFormat::Base64,
+    OutputFormat::Base64Url,
+    OutputFormat::Base32,
+    OutputFormat::Base58,
+];
+
+/// Ways to read the text input, offered next to it
+pub const INPUT_ENCODINGS: [InputEncoding; 4] = [
+    InputEncoding::Utf8,
+    InputEncoding::Hex,
+    InputEncoding::Base64,
+    InputEncoding::Utf16Le,
+];
 
 impl HashForgeApp {
     /// Get filtered algorithms based on current category
@@ -1,11 +1,14 @@
 // GUI Application State and Core Logic
-use crate::{algorithms::HashAlgorithm, core::HashForge, output::OutputFormat};
+use crate::{
+    algorithms::HashAlgorithm, core::HashForge, input::InputEncoding, output::OutputFormat,
+};
 use std::path::PathBuf;
 
 #[derive(Default)]
 pub struct HashForgeApp {
     // Input options
     pub input_text: String,
+    pub input_encoding: InputEncoding,
     pub selected_file: Option<PathBuf>,
     pub input_mode: InputMode,
 
@@ -101,6 +104,7 @@ impl HashForgeApp {
             self.selected_algorithm,
             HashAlgorithm::Sha1
                 | HashAlgorithm::Sha256
+                | HashAlgorithm::Sha384
                 | HashAlgorithm::Sha512
                 | HashAlgorithm::Sha3_224
                 | HashAlgorithm::Sha3_256
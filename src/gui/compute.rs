@@ -30,12 +30,12 @@ impl HashForgeApp {
                         None
                     };
 
-                    self.forge.hash_text(
-                        &self.input_text,
-                        self.selected_algorithm,
-                        salt,
-                        iterations,
-                    )
+                    self.input_encoding
+                        .decode(&self.input_text)
+                        .and_then(|data| {
+                            self.forge
+                                .hash_data(&data, self.selected_algorithm, salt, iterations)
+                        })
                 }
                 InputMode::File => {
                     if let Some(ref path) = self.selected_file {
@@ -57,10 +57,7 @@ impl HashForgeApp {
 
         match result {
             Ok(hash) => {
-                self.hash_result = Some(match self.output_format {
-                    crate::output::OutputFormat::Hex => hash.to_hex(),
-                    crate::output::OutputFormat::Base64 => hash.to_base64(),
-                });
+                self.hash_result = Some(hash.encode(self.output_format));
 
                 // Handle verification
                 if self.hmac_mode && !self.hmac_expected.is_empty() {
@@ -84,7 +81,7 @@ impl HashForgeApp {
         }
 
         let data = match self.input_mode {
-            InputMode::Text => self.input_text.as_bytes(),
+            InputMode::Text => self.input_encoding.decode(&self.input_text)?,
             InputMode::File => {
                 if self.selected_file.is_some() {
                     return Err(anyhow::anyhow!("File HMAC not yet implemented in GUI"));
@@ -95,7 +92,7 @@ impl HashForgeApp {
         };
 
         crate::hmac_core::HmacProcessor::compute_hmac(
-            data,
+            &data,
             self.hmac_key.as_bytes(),
             self.selected_algorithm,
         )
@@ -10,4 +10,12 @@ pub use app_state::{AlgorithmCategory, HashForgeApp, InputMode};
 
 // Output formats constant
 use crate::output::OutputFormat;
-pub const AVAILABLE_OUTPUT_FORMATS: [OutputFormat; 2] = [OutputFormat::Hex, OutputFormat::Base64];
+/// Text encodings offered in the GUI; raw bytes cannot be shown in a text field
+pub const AVAILABLE_OUTPUT_FORMATS: [OutputFormat; 6] = [
+    OutputFormat::Hex,
+    OutputFormat::HexUpper,
+    OutputFormat::Base64,
+    OutputFormat::Base64Url,
+    OutputFormat::Base32,
+    OutputFormat::Base58,
+];
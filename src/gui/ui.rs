@@ -1,7 +1,7 @@
 // GUI rendering and UI components
-use super::algorithms::{ALL_CATEGORIES, AVAILABLE_OUTPUT_FORMATS};
+use super::algorithms::{ALL_CATEGORIES, AVAILABLE_OUTPUT_FORMATS, INPUT_ENCODINGS};
 use super::app_state::{HashForgeApp, InputMode};
-use crate::algorithms::HashAlgorithm;
+use crate::{algorithms::HashAlgorithm, input::InputEncoding};
 
 #[cfg(feature = "gui")]
 impl eframe::App for HashForgeApp {
@@ -97,14 +97,37 @@ impl HashForgeApp {
 
             match self.input_mode {
                 InputMode::Text => {
+                    let mut encoding_changed = false;
+                    ui.horizontal(|ui| {
+                        ui.label("Encoding:");
+                        egui::ComboBox::from_id_source("input_encoding")
+                            .selected_text(format!("{}", self.input_encoding))
+                            .show_ui(ui, |ui| {
+                                for &encoding in &INPUT_ENCODINGS {
+                                    encoding_changed |= ui
+                                        .selectable_value(
+                                            &mut self.input_encoding,
+                                            encoding,
+                                            format!("{encoding}"),
+                                        )
+                                        .clicked();
+                                }
+                            });
+                    });
+
+                    let hint = match self.input_encoding {
+                        InputEncoding::Hex => "Enter hex bytes to hash...",
+                        InputEncoding::Base64 => "Enter base64 bytes to hash...",
+                        _ => "Enter text to hash...",
+                    };
                     let response = ui.add(
                         egui::TextEdit::multiline(&mut self.input_text)
                             .desired_rows(4)
-                            .hint_text("Enter text to hash...")
+                            .hint_text(hint)
                             .font(egui::TextStyle::Monospace),
                     );
 
-                    if response.changed() && self.auto_compute {
+                    if (response.changed() || encoding_changed) && self.auto_compute {
                         self.clear_results();
                         self.compute_hash();
                     }
@@ -325,7 +348,7 @@ impl HashForgeApp {
 
             let response = ui.add(
                 egui::TextEdit::singleline(&mut self.expected_hash)
-                    .hint_text("Enter expected hash (hex or base64)...")
+                    .hint_text("Enter expected hash (hex, base64, base32 or base58)...")
                     .font(egui::TextStyle::Monospace),
             );
 
@@ -353,7 +376,7 @@ impl HashForgeApp {
 
             let response = ui.add(
                 egui::TextEdit::singleline(&mut self.hmac_expected)
-                    .hint_text("Enter expected HMAC (hex or base64)...")
+                    .hint_text("Enter expected HMAC (hex, base64, base32 or base58)...")
                     .font(egui::TextStyle::Monospace),
             );
 
@@ -1,10 +1,11 @@
 use crate::algorithms::HashAlgorithm;
 use crate::output::HashResult;
-use anyhow::{Context, Result};
-use hmac::{Hmac, Mac};
+use anyhow::Result;
+use hmac::{digest::KeyInit, Hmac, Mac};
 use sha1::Sha1;
-use sha2::{Sha256, Sha512};
+use sha2::{Sha256, Sha384, Sha512};
 use sha3::{Sha3_224, Sha3_256, Sha3_384, Sha3_512};
+use std::io::Read;
 
 /// HMAC implementation for supported hash algorithms
 pub struct HmacProcessor;
@@ -12,55 +13,31 @@ pub struct HmacProcessor;
 impl HmacProcessor {
     /// Compute HMAC with the specified algorithm and key
     pub fn compute_hmac(data: &[u8], key: &[u8], algorithm: HashAlgorithm) -> Result<HashResult> {
-        match algorithm {
-            HashAlgorithm::Sha1 => {
-                let mut mac = Hmac::<Sha1>::new_from_slice(key)
-                    .context("Invalid key length for HMAC-SHA1")?;
-                mac.update(data);
-                let result = mac.finalize().into_bytes();
-                Ok(HashResult::new(result.to_vec(), algorithm))
-            }
-            HashAlgorithm::Sha256 => {
-                let mut mac = Hmac::<Sha256>::new_from_slice(key)
-                    .context("Invalid key length for HMAC-SHA256")?;
-                mac.update(data);
-                let result = mac.finalize().into_bytes();
-                Ok(HashResult::new(result.to_vec(), algorithm))
-            }
-            HashAlgorithm::Sha512 => {
-                let mut mac = Hmac::<Sha512>::new_from_slice(key)
-                    .context("Invalid key length for HMAC-SHA512")?;
-                mac.update(data);
-                let result = mac.finalize().into_bytes();
-                Ok(HashResult::new(result.to_vec(), algorithm))
-            }
+        Self::compute_hmac_reader(data, key, algorithm)
+    }
+
+    /// Compute HMAC over everything read from `reader`, without buffering it
+    pub fn compute_hmac_reader<R: Read>(
+        reader: R,
+        key: &[u8],
+        algorithm: HashAlgorithm,
+    ) -> Result<HashResult> {
+        let bytes = match algorithm {
+            HashAlgorithm::Sha1 => mac_reader::<Hmac<Sha1>, _>(reader, key, "HMAC-SHA1")?,
+            HashAlgorithm::Sha256 => mac_reader::<Hmac<Sha256>, _>(reader, key, "HMAC-SHA256")?,
+            HashAlgorithm::Sha384 => mac_reader::<Hmac<Sha384>, _>(reader, key, "HMAC-SHA384")?,
+            HashAlgorithm::Sha512 => mac_reader::<Hmac<Sha512>, _>(reader, key, "HMAC-SHA512")?,
             HashAlgorithm::Sha3_224 => {
-                let mut mac = Hmac::<Sha3_224>::new_from_slice(key)
-                    .context("Invalid key length for HMAC-SHA3-224")?;
-                mac.update(data);
-                let result = mac.finalize().into_bytes();
-                Ok(HashResult::new(result.to_vec(), algorithm))
+                mac_reader::<Hmac<Sha3_224>, _>(reader, key, "HMAC-SHA3-224")?
             }
             HashAlgorithm::Sha3_256 => {
-                let mut mac = Hmac::<Sha3_256>::new_from_slice(key)
-                    .context("Invalid key length for HMAC-SHA3-256")?;
-                mac.update(data);
-                let result = mac.finalize().into_bytes();
-                Ok(HashResult::new(result.to_vec(), algorithm))
+                mac_reader::<Hmac<Sha3_256>, _>(reader, key, "HMAC-SHA3-256")?
             }
             HashAlgorithm::Sha3_384 => {
-                let mut mac = Hmac::<Sha3_384>::new_from_slice(key)
-                    .context("Invalid key length for HMAC-SHA3-384")?;
-                mac.update(data);
-                let result = mac.finalize().into_bytes();
-                Ok(HashResult::new(result.to_vec(), algorithm))
+                mac_reader::<Hmac<Sha3_384>, _>(reader, key, "HMAC-SHA3-384")?
             }
             HashAlgorithm::Sha3_512 => {
-                let mut mac = Hmac::<Sha3_512>::new_from_slice(key)
-                    .context("Invalid key length for HMAC-SHA3-512")?;
-                mac.update(data);
-                let result = mac.finalize().into_bytes();
-                Ok(HashResult::new(result.to_vec(), algorithm))
+                mac_reader::<Hmac<Sha3_512>, _>(reader, key, "HMAC-SHA3-512")?
             }
             HashAlgorithm::Blake2b => {
                 anyhow::bail!("HMAC-BLAKE2b not supported in current implementation")
@@ -69,7 +46,8 @@ impl HmacProcessor {
                 anyhow::bail!("HMAC-BLAKE2s not supported in current implementation")
             }
             _ => anyhow::bail!("HMAC not supported for algorithm: {}", algorithm),
-        }
+        };
+        Ok(HashResult::new(bytes, algorithm))
     }
 
     /// Verify HMAC against expected value
@@ -80,9 +58,13 @@ impl HmacProcessor {
         algorithm: HashAlgorithm,
     ) -> Result<bool> {
         let computed = Self::compute_hmac(data, key, algorithm)?;
+        Ok(Self::matches(&computed, expected_hmac))
+    }
 
+    /// Whether a computed HMAC equals `expected_hmac`
+    pub fn matches(computed: &HashResult, expected_hmac: &[u8]) -> bool {
         // Constant-time comparison to prevent timing attacks
-        Ok(constant_time_eq(&computed.bytes, expected_hmac))
+        constant_time_eq(&computed.bytes, expected_hmac)
     }
 
     /// Check if algorithm supports HMAC
@@ -91,6 +73,7 @@ impl HmacProcessor {
             algorithm,
             HashAlgorithm::Sha1
                 | HashAlgorithm::Sha256
+                | HashAlgorithm::Sha384
                 | HashAlgorithm::Sha512
                 | HashAlgorithm::Sha3_224
                 | HashAlgorithm::Sha3_256
@@ -100,6 +83,25 @@ impl HmacProcessor {
     }
 }
 
+/// Feed `reader` through a MAC keyed with `key`
+fn mac_reader<M, R>(mut reader: R, key: &[u8], name: &str) -> Result<Vec<u8>>
+where
+    M: Mac + KeyInit,
+    R: Read,
+{
+    let mut mac = <M as Mac>::new_from_slice(key)
+        .map_err(|_| anyhow::anyhow!("Invalid key length for {name}"))?;
+    let mut buffer = [0; 8192];
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        mac.update(&buffer[..bytes_read]);
+    }
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
 /// Constant-time equality check to prevent timing attacks
 fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
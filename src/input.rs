@@ -0,0 +1,112 @@
+// Decoding text arguments into the bytes that get hashed
+use anyhow::{Context, Result};
+use base64::{
+    alphabet,
+    engine::{
+        general_purpose::{GeneralPurpose, GeneralPurposeConfig},
+        DecodePaddingMode,
+    },
+    Engine,
+};
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+/// Padding is optional: test vectors and tokens are written both ways
+const PADDING_OPTIONAL: GeneralPurposeConfig =
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+
+/// How a text argument is turned into bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum InputEncoding {
+    /// The UTF-8 bytes of the text, as typed
+    #[default]
+    #[value(name = "utf8")]
+    Utf8,
+    /// Hexadecimal; whitespace, ':' separators and a 0x prefix are ignored
+    #[value(name = "hex")]
+    Hex,
+    /// Standard or URL-safe base64, with or without padding; whitespace is ignored
+    #[value(name = "base64")]
+    Base64,
+    /// UTF-16 code units of the text, little-endian, without a byte order mark
+    #[value(name = "utf16le")]
+    Utf16Le,
+}
+
+impl InputEncoding {
+    /// The bytes `text` stands for in this encoding
+    pub fn decode(self, text: &str) -> Result<Vec<u8>> {
+        match self {
+            InputEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            InputEncoding::Hex => {
+                let text = text.trim();
+                let text = text
+                    .strip_prefix("0x")
+                    .or_else(|| text.strip_prefix("0X"))
+                    .unwrap_or(text);
+                let digits: String = text
+                    .chars()
+                    .filter(|c| !c.is_whitespace() && *c != ':')
+                    .collect();
+                hex::decode(&digits).context("Invalid hex input")
+            }
+            InputEncoding::Base64 => {
+                let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+                let alphabet = if text.contains(['-', '_']) {
+                    &alphabet::URL_SAFE
+                } else {
+                    &alphabet::STANDARD
+                };
+                GeneralPurpose::new(alphabet, PADDING_OPTIONAL)
+                    .decode(&text)
+                    .context("Invalid base64 input")
+            }
+            InputEncoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+        }
+    }
+}
+
+impl fmt::Display for InputEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputEncoding::Utf8 => write!(f, "utf8"),
+            InputEncoding::Hex => write!(f, "hex"),
+            InputEncoding::Base64 => write!(f, "base64"),
+            InputEncoding::Utf16Le => write!(f, "utf16le"),
+        }
+    }
+}
+
+impl Serialize for InputEncoding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_input() {
+        assert_eq!(InputEncoding::Utf8.decode("hé").unwrap(), "hé".as_bytes());
+        assert_eq!(
+            InputEncoding::Hex.decode(" 0xDEAD beef").unwrap(),
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(
+            InputEncoding::Hex.decode("de:ad:be:ef").unwrap(),
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+        assert!(InputEncoding::Hex.decode("abc").is_err());
+        assert_eq!(InputEncoding::Base64.decode("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(InputEncoding::Base64.decode("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(InputEncoding::Base64.decode("-_8").unwrap(), [0xfb, 0xff]);
+        assert!(InputEncoding::Base64.decode("not base64!").is_err());
+        assert_eq!(
+            InputEncoding::Utf16Le.decode("A€").unwrap(),
+            [0x41, 0x00, 0xac, 0x20]
+        );
+    }
+}
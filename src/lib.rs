@@ -1,10 +1,28 @@
 pub mod algorithms;
+pub mod archive;
+pub mod blocks;
+pub mod cache;
+pub mod checksum;
+pub mod chunks;
 pub mod cli;
 pub mod core;
+pub mod decompress;
+pub mod delta;
+pub mod diff;
+pub mod dupes;
 pub mod gui;
 pub mod hmac_core;
+pub mod input;
+pub mod multiformats;
+pub mod normalize;
 pub mod output;
+pub mod range;
+pub mod report;
+pub mod sri;
+pub mod tree;
 pub mod utils;
+pub mod walk;
+pub mod watch;
 
 pub use algorithms::HashAlgorithm;
 pub use core::HashForge;
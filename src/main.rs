@@ -1,185 +1,1945 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
 use hash_forge::{
-    cli::{Cli, Commands},
-    core::HashForge,
+    algorithms::{AlgorithmSelection, HashAlgorithm},
+    blocks::BlockList,
+    cache::{HashCache, SharedCache},
+    checksum::{print_check_event, print_check_summary, CheckOptions, ManifestEntry},
+    chunks::ChunkOptions,
+    cli::{
+        ArchiveAction, BatchArgs, BlockAction, CacheAction, CheckArgs, ChunksArgs, Cli, Commands,
+        DeltaArgs, DiffArgs, DupesArgs, FileArgs, HmacArgs, ManifestArgs, PatchArgs, SignatureArgs,
+        SriAction, TextArgs, TreeArgs, VerifyArgs, VerifyHmacArgs, WatchArgs,
+    },
+    core::{DetectionOutcome, HashForge},
+    delta::Signature,
+    diff::{diff_snapshots, Snapshot},
+    dupes::{DupeOptions, FileError},
     hmac_core::HmacProcessor,
-    output::OutputFormat,
+    input::InputEncoding,
+    normalize::Normalization,
+    output::{BatchFormat, DigestForm, HashResult, OutputFormat, OutputMode},
+    range::{open_range, FileRange},
+    report::{
+        millis, print_json, ArchiveCheckReport, ArchiveManifestReport, ArchiveMemberRecord,
+        BatchReport, BlockCompareReport, BlocksReport, CacheReport, CheckReport, CheckedManifest,
+        ChunkedFileRecord, ChunksReport, DeltaReport, DiffReport, DupesReport, DuplicateRecord,
+        ErrorReport, FileRecord, HashReport, InputDescriptor, ManifestReport, PatchReport,
+        SignatureReport, SriHtmlReport, SriRecord, SriReport, TreeReport, Verdict, VerifyReport,
+        WatchRecord, SCHEMA_VERSION,
+    },
+    sri::{format_integrity, AssetStatus, Integrity},
+    tree::TreeOptions,
+    utils::{format_file_size, format_timestamp},
+    walk::WalkOptions,
+    watch::{WatchChange, WatchOptions},
+};
+use std::{
+    cell::OnceCell,
+    io::{BufRead, IsTerminal, Write},
+    time::Instant,
 };
 
-fn main() -> Result<()> {
+/// Print banners and labels to stdout on a terminal, or to stderr when stdout
+/// is redirected so that only results end up in pipes and files
+macro_rules! banner {
+    ($($arg:tt)*) => {
+        if std::io::stdout().is_terminal() {
+            println!($($arg)*);
+        } else {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+fn main() {
     let cli = Cli::parse();
+    let output = cli.output;
+    let command = cli.command.name();
+    let error_status = cli.command.error_status();
 
-    match cli.command {
-        Commands::Text {
-            input,
-            algorithm,
+    let cache = CliCache::new(cli.cache, cli.refresh);
+    let result = run(cli.command, output, &cache);
+    cache.save();
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            if output == OutputMode::Json {
+                let _ = print_json(&ErrorReport::new(command, &e));
+            } else {
+                eprintln!("Error: {e:?}");
+            }
+            std::process::exit(error_status);
+        }
+    }
+}
+
+/// Run a command, returning whether it succeeded (exit status 0 or 1)
+fn run(command: Commands, output: OutputMode, cache: &CliCache) -> Result<bool> {
+    let json = output == OutputMode::Json;
+
+    match command {
+        Commands::Text(args) => run_text(args, json),
+        Commands::File(args) => run_file(args, json, cache),
+        Commands::Verify(args) => run_verify(args, json),
+        Commands::Batch(args) => run_batch(args, json, cache),
+        Commands::Manifest(args) => run_manifest(args, json, cache),
+        Commands::Check(args) => run_check(args, json),
+        Commands::Diff(args) => run_diff(args, json),
+        Commands::Sri { action } => run_sri(action, json),
+        Commands::Tree(args) => run_tree(args, json, cache),
+        Commands::Dupes(args) => run_dupes(args, json, cache),
+        Commands::Watch(args) => run_watch(args, json),
+        Commands::Blocks { action } => run_blocks(action, json),
+        Commands::Chunks(args) => run_chunks(args, json),
+        Commands::Signature(args) => run_signature(args, json),
+        Commands::Delta(args) => run_delta(args, json),
+        Commands::Patch(args) => run_patch(args, json),
+        Commands::Archive { action } => run_archive(action, json),
+        Commands::Cache { action } => run_cache(action, json),
+        Commands::Hmac(args) => run_hmac(args, json),
+        Commands::VerifyHmac(args) => run_verify_hmac(args, json),
+    }
+}
+
+/// Run `text`
+fn run_text(args: TextArgs, json: bool) -> Result<bool> {
+    let TextArgs {
+        input,
+        input_encoding,
+        normalization,
+        algorithm,
+        output_format,
+        salt,
+        iterations,
+        tagged,
+        multihash,
+        cid,
+        quiet,
+    } = args;
+    let form = digest_form(tagged, multihash, cid);
+    let forge = HashForge::new();
+    let start = Instant::now();
+    let data = input_encoding.decode(&input)?;
+    let size = data.len();
+    let data = normalize(&normalization, data)?;
+    let result = forge.hash_data(&data, algorithm, salt.as_deref(), iterations)?;
+
+    if json {
+        let mut report = HashReport::new(
+            "text",
+            InputDescriptor::encoded_text(&input, input_encoding, size),
+            &result,
             output_format,
-            salt,
-            iterations,
-        } => {
-            let forge = HashForge::new();
-            let result = forge.hash_text(&input, algorithm, salt.as_deref(), iterations)?;
+            start.elapsed(),
+        );
+        report.salt = salt;
+        report.iterations = iterations;
+        report.normalization = normalization.is_enabled().then_some(normalization);
+        report.digest = result.render(form, output_format)?;
+        print_json(&report)?;
+        return Ok(true);
+    }
 
-            let formatted = match output_format {
-                OutputFormat::Hex => result.to_hex(),
-                OutputFormat::Base64 => result.to_base64(),
-            };
+    if quiet {
+        print_digest(None, &result, output_format, form)?;
+        return Ok(true);
+    }
+
+    banner!("🔧 Hash Forge - Text Hashing");
+    banner!("Input: {input}");
+    if input_encoding != InputEncoding::Utf8 {
+        banner!("Input encoding: {input_encoding} ({size} bytes)");
+    }
+    if normalization.is_enabled() {
+        banner!("Normalization: {normalization}");
+    }
+    banner!("Algorithm: {algorithm}");
+    if let Some(salt) = salt {
+        banner!("Salt: {salt}");
+    }
+    if let Some(iter) = iterations {
+        banner!("Iterations: {iter}");
+    }
+    print_digest(Some("Hash"), &result, output_format, form)?;
+    Ok(true)
+}
+
+/// Run `file`
+fn run_file(args: FileArgs, json: bool, cache: &CliCache) -> Result<bool> {
+    let FileArgs {
+        path,
+        offset,
+        length,
+        decompress,
+        normalization,
+        algorithm,
+        output_format,
+        tagged,
+        multihash,
+        cid,
+        quiet,
+    } = args;
+    let form = digest_form(tagged, multihash, cid);
+    let forge = HashForge::new()
+        .with_progress(!json && !quiet)
+        .with_cache(cache.get());
+    let range = FileRange::from_options(offset, length);
+    check_normalization(&normalization, &range)?;
+    let compression = decompress.map(|d| d.resolve(&path)).transpose()?.flatten();
+    let hash = || match (compression, range) {
+        _ if normalization.is_enabled() => {
+            forge.hash_file_normalized(&path, algorithm, compression, &normalization)
+        }
+        (Some(compression), _) => forge.hash_file_decompressed(&path, algorithm, compression),
+        (None, Some(ref range)) => forge.hash_file_range(&path, algorithm, range),
+        (None, None) => forge.hash_file(&path, algorithm),
+    };
+
+    if json {
+        let start = Instant::now();
+        let result = hash()?;
+        let input = InputDescriptor::file_range(&path, range).with_decompressed(compression);
+        let mut report = HashReport::new("file", input, &result, output_format, start.elapsed());
+        report.normalization = normalization.is_enabled().then_some(normalization);
+        report.digest = result.render(form, output_format)?;
+        print_json(&report)?;
+        return Ok(true);
+    }
+
+    if quiet {
+        let result = hash()?;
+        print_digest(None, &result, output_format, form)?;
+        return Ok(true);
+    }
+
+    banner!("🔧 Hash Forge - File Hashing");
+    banner!("File: {}", path.display());
+    if let Some(ref range) = range {
+        banner!("Range: {}", describe_range(range));
+    }
+    if let Some(compression) = compression {
+        banner!("Decompressing: {compression}");
+    }
+    if normalization.is_enabled() {
+        banner!("Normalization: {normalization}");
+    }
+    banner!("Algorithm: {algorithm}");
+
+    let result = hash()?;
+    print_digest(Some("Hash"), &result, output_format, form)?;
+    Ok(true)
+}
+
+/// Run `verify`
+fn run_verify(args: VerifyArgs, json: bool) -> Result<bool> {
+    let VerifyArgs {
+        text,
+        input_encoding,
+        file,
+        offset,
+        length,
+        decompress,
+        normalization,
+        expected_hash,
+        algorithm,
+        quiet,
+    } = args;
+    let forge = HashForge::new().with_progress(!json && !quiet);
+    let start = Instant::now();
+    let range = FileRange::from_options(offset, length);
+    check_normalization(&normalization, &range)?;
+    let compression = match (decompress, &file) {
+        (Some(decompress), Some(file_path)) => decompress.resolve(file_path)?,
+        _ => None,
+    };
+
+    let data = text
+        .as_deref()
+        .map(|text_value| input_encoding.decode(text_value))
+        .transpose()?;
+
+    let input = if let (Some(text_value), Some(data)) = (&text, &data) {
+        InputDescriptor::encoded_text(text_value, input_encoding, data.len())
+    } else if let Some(ref file_path) = file {
+        InputDescriptor::file_range(file_path, range).with_decompressed(compression)
+    } else {
+        anyhow::bail!("Either --text or --file must be specified for verification");
+    };
+    let data = data
+        .map(|data| normalize(&normalization, data))
+        .transpose()?;
+    let compute = |algorithms: &[HashAlgorithm]| {
+        if let Some(ref data) = data {
+            algorithms
+                .iter()
+                .map(|&algorithm| forge.hash_data(data, algorithm, None, None))
+                .collect()
+        } else if let Some(ref file_path) = file {
+            match (compression, range) {
+                _ if normalization.is_enabled() => forge.hash_file_normalized_many(
+                    file_path,
+                    algorithms,
+                    compression,
+                    &normalization,
+                ),
+                (Some(compression), _) => {
+                    forge.hash_file_decompressed_many(file_path, algorithms, compression)
+                }
+                (None, Some(ref range)) => forge.hash_file_range_many(file_path, algorithms, range),
+                (None, None) => forge.hash_file_many(file_path, algorithms),
+            }
+        } else {
+            unreachable!("input presence checked above")
+        }
+    };
 
-            println!("🔧 Hash Forge - Text Hashing");
-            println!("Input: {input}");
-            println!("Algorithm: {algorithm}");
-            if let Some(salt) = salt {
-                println!("Salt: {salt}");
+    let mut report = VerifyReport {
+        schema_version: SCHEMA_VERSION,
+        command: "verify",
+        input,
+        algorithm: None,
+        detected: false,
+        candidates: Vec::new(),
+        expected: expected_hash.clone(),
+        computed: None,
+        verdict: Verdict::Fail,
+        normalization: normalization.is_enabled().then_some(normalization),
+        elapsed_ms: 0.0,
+    };
+
+    let algorithm = match AlgorithmSelection::resolve(algorithm, &expected_hash)? {
+        AlgorithmSelection::Specific(algorithm) => algorithm,
+        AlgorithmSelection::Auto => {
+            let outcome = forge.verify_auto(&expected_hash, compute)?;
+            report.detected = true;
+            report.elapsed_ms = millis(start.elapsed());
+
+            match outcome {
+                DetectionOutcome::Matched(computed_hash) => {
+                    report.algorithm = Some(computed_hash.algorithm);
+                    report.computed = Some(computed_hash.to_hex());
+                    report.verdict = Verdict::Pass;
+                }
+                DetectionOutcome::NoMatch { candidates } => {
+                    report.candidates = candidates;
+                }
+                DetectionOutcome::Ambiguous { matches } => {
+                    report.candidates = matches;
+                    report.verdict = Verdict::Ambiguous;
+                }
             }
-            if let Some(iter) = iterations {
-                println!("Iterations: {iter}");
+
+            if json {
+                print_json(&report)?;
+                return Ok(report.verdict == Verdict::Pass);
             }
-            println!("Hash ({output_format}): {formatted}");
+            if quiet {
+                return Ok(report.verdict == Verdict::Pass);
+            }
+
+            banner!("🔧 Hash Forge - Hash Verification");
+            if normalization.is_enabled() {
+                banner!("Normalization: {normalization}");
+            }
+            match report.verdict {
+                Verdict::Pass => {
+                    if let Some(algorithm) = report.algorithm {
+                        banner!("Algorithm: {algorithm} (detected)");
+                    }
+                    banner!("Expected: {expected_hash}");
+                    if let Some(ref computed) = report.computed {
+                        banner!("Computed: {computed}");
+                    }
+                    println!("✅ Hash verification PASSED");
+                }
+                Verdict::Fail => {
+                    banner!(
+                        "Algorithm: auto (tried {})",
+                        join_algorithms(&report.candidates)
+                    );
+                    banner!("Expected: {expected_hash}");
+                    println!("❌ Hash verification FAILED");
+                }
+                Verdict::Ambiguous => {
+                    banner!("Expected: {expected_hash}");
+                    println!(
+                        "⚠️ Ambiguous: the expected hash matches {}",
+                        join_algorithms(&report.candidates)
+                    );
+                    println!("Specify --algorithm to verify unambiguously");
+                }
+            }
+            return Ok(report.verdict == Verdict::Pass);
         }
+    };
 
-        Commands::File {
-            path,
+    let computed_hash = compute(&[algorithm])?.remove(0);
+    let is_valid = forge.verify_hash(&computed_hash, &expected_hash)?;
+
+    if json {
+        report.algorithm = Some(algorithm);
+        report.computed = Some(computed_hash.to_hex());
+        report.verdict = if is_valid {
+            Verdict::Pass
+        } else {
+            Verdict::Fail
+        };
+        report.elapsed_ms = millis(start.elapsed());
+        print_json(&report)?;
+        return Ok(is_valid);
+    }
+    if quiet {
+        return Ok(is_valid);
+    }
+
+    banner!("🔧 Hash Forge - Hash Verification");
+    if normalization.is_enabled() {
+        banner!("Normalization: {normalization}");
+    }
+    banner!("Algorithm: {algorithm}");
+    banner!("Expected: {expected_hash}");
+    banner!("Computed: {}", computed_hash.to_hex());
+
+    if is_valid {
+        println!("✅ Hash verification PASSED");
+    } else {
+        println!("❌ Hash verification FAILED");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Run `batch`
+fn run_batch(args: BatchArgs, json: bool, cache: &CliCache) -> Result<bool> {
+    let BatchArgs {
+        directory,
+        algorithm,
+        output_format,
+        format,
+        output_file,
+        walk,
+    } = args;
+    let forge = HashForge::new()
+        .with_progress(!json)
+        .with_cache(cache.get());
+
+    if format != BatchFormat::Text || output_file.is_some() {
+        let forge = forge.with_progress(false);
+        let failures = if let Some(ref path) = output_file {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create file: {}", path.display()))?;
+            let writer = std::io::BufWriter::new(file);
+            forge.write_batch_records(
+                &directory,
+                algorithm,
+                output_format,
+                &walk,
+                format,
+                writer,
+            )?
+        } else {
+            let stdout = std::io::stdout().lock();
+            forge.write_batch_records(
+                &directory,
+                algorithm,
+                output_format,
+                &walk,
+                format,
+                stdout,
+            )?
+        };
+        return Ok(failures == 0);
+    }
+
+    if json {
+        let start = Instant::now();
+        let mut files = Vec::new();
+        forge.hash_directory(&directory, algorithm, &walk, |entry, result| {
+            files.push(FileRecord::new(entry, algorithm, &result, output_format));
+            Ok(())
+        })?;
+        print_json(&BatchReport {
+            schema_version: SCHEMA_VERSION,
+            command: "batch",
+            directory,
             algorithm,
-            output_format,
-        } => {
-            let forge = HashForge::new();
+            encoding: output_format.text_form(),
+            files,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(true);
+    }
 
-            println!("🔧 Hash Forge - File Hashing");
-            println!("File: {}", path.display());
-            println!("Algorithm: {algorithm}");
+    println!("🔧 Hash Forge - Batch Processing");
+    println!("Directory: {}", directory.display());
+    println!("Algorithm: {algorithm}");
 
-            let result = forge.hash_file(&path, algorithm)?;
-            let formatted = match output_format {
-                OutputFormat::Hex => result.to_hex(),
-                OutputFormat::Base64 => result.to_base64(),
-            };
+    forge.batch_process_directory(&directory, algorithm, output_format, &walk)?;
+    Ok(true)
+}
 
-            println!("Hash ({output_format}): {formatted}");
-        }
+/// Run `manifest`
+fn run_manifest(args: ManifestArgs, json: bool, cache: &CliCache) -> Result<bool> {
+    let ManifestArgs {
+        directory,
+        algorithm,
+        format,
+        binary,
+        output_file,
+        walk,
+    } = args;
+    let forge = HashForge::new()
+        .with_progress(false)
+        .with_cache(cache.get());
+    let start = Instant::now();
 
-        Commands::Verify {
-            text,
-            file,
-            expected_hash,
+    let files = if let Some(ref path) = output_file {
+        // Buffer the manifest so a file created inside `directory` is not hashed half-written
+        let mut buffer = Vec::new();
+        let files =
+            forge.write_manifest(&directory, algorithm, format, binary, &walk, &mut buffer)?;
+        std::fs::write(path, buffer)
+            .with_context(|| format!("Failed to write manifest: {}", path.display()))?;
+        files
+    } else if json {
+        let mut sink = std::io::sink();
+        forge.write_manifest(&directory, algorithm, format, binary, &walk, &mut sink)?
+    } else {
+        let mut stdout = std::io::stdout().lock();
+        forge.write_manifest(&directory, algorithm, format, binary, &walk, &mut stdout)?
+    };
+
+    let success = files.iter().all(|file| file.error.is_none());
+    if json {
+        print_json(&ManifestReport {
+            schema_version: SCHEMA_VERSION,
+            command: "manifest",
+            directory,
             algorithm,
-        } => {
-            let forge = HashForge::new();
+            format,
+            output_file,
+            files,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+    }
+    Ok(success)
+}
 
-            let computed_hash = if let Some(text_value) = text {
-                forge.hash_text(&text_value, algorithm, None, None)?
-            } else if let Some(file_path) = file {
-                forge.hash_file(&file_path, algorithm)?
-            } else {
-                anyhow::bail!("Either --text or --file must be specified for verification");
-            };
+/// Run `check`
+fn run_check(args: CheckArgs, json: bool) -> Result<bool> {
+    let CheckArgs {
+        manifests,
+        algorithm,
+        quiet,
+        status,
+        strict,
+        warn,
+        ignore_missing,
+    } = args;
+    let forge = HashForge::new().with_progress(false);
+    let options = CheckOptions {
+        quiet,
+        status,
+        strict,
+        warn,
+        ignore_missing,
+    };
+    let start = Instant::now();
+
+    let mut checked = Vec::new();
+    for manifest in &manifests {
+        // Like sha256sum -c, report a manifest that can't be opened and go on
+        let (reader, source) = match open_manifest(manifest) {
+            Ok(opened) => opened,
+            Err(e) => {
+                let source = manifest.display().to_string();
+                let error = open_error_message(&e);
+                if !json {
+                    eprintln!("hash-forge: {source}: {error}");
+                }
+                checked.push(CheckedManifest {
+                    source,
+                    results: Vec::new(),
+                    summary: Default::default(),
+                    ok: false,
+                    error: Some(error),
+                });
+                continue;
+            }
+        };
 
-            let is_valid = forge.verify_hash(&computed_hash, &expected_hash)?;
+        let mut results = Vec::new();
+        let summary = forge.check_manifest(reader, algorithm, &options, |event| {
+            if json {
+                results.push(event.clone());
+            } else {
+                print_check_event(event, &source, algorithm, &options);
+            }
+        })?;
+        if !json {
+            print_check_summary(&source, &summary, &options);
+        }
 
-            println!("🔧 Hash Forge - Hash Verification");
-            println!("Algorithm: {algorithm}");
-            println!("Expected: {expected_hash}");
-            println!("Computed: {}", computed_hash.to_hex());
+        checked.push(CheckedManifest {
+            source,
+            results,
+            ok: summary.is_success(&options),
+            summary,
+            error: None,
+        });
+    }
 
-            if is_valid {
-                println!("✅ Hash verification PASSED");
+    let success = checked.iter().all(|manifest| manifest.ok);
+    if json {
+        print_json(&CheckReport {
+            schema_version: SCHEMA_VERSION,
+            command: "check",
+            manifests: checked,
+            verdict: if success {
+                Verdict::Pass
             } else {
-                println!("❌ Hash verification FAILED");
-                std::process::exit(1);
+                Verdict::Fail
+            },
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+    }
+    Ok(success)
+}
+
+/// Run `diff`
+fn run_diff(args: DiffArgs, json: bool) -> Result<bool> {
+    let DiffArgs {
+        old,
+        new,
+        algorithm,
+        status,
+        walk,
+    } = args;
+    let forge = HashForge::new().with_progress(false);
+    let start = Instant::now();
+
+    // Directories are hashed with the algorithm of the manifest they are compared to
+    let is_dir = |path: &std::path::Path| path.as_os_str() != "-" && path.is_dir();
+    let (old_snapshot, new_snapshot) = if is_dir(&old) && !is_dir(&new) {
+        let new_snapshot = load_snapshot(&forge, &new, algorithm, &walk)?;
+        let old_snapshot = load_snapshot(&forge, &old, new_snapshot.algorithm, &walk)?;
+        (old_snapshot, new_snapshot)
+    } else {
+        let old_snapshot = load_snapshot(&forge, &old, algorithm, &walk)?;
+        let new_algorithm = if is_dir(&new) {
+            old_snapshot.algorithm
+        } else {
+            algorithm
+        };
+        let new_snapshot = load_snapshot(&forge, &new, new_algorithm, &walk)?;
+        (old_snapshot, new_snapshot)
+    };
+    let diff = diff_snapshots(&old_snapshot, &new_snapshot)?;
+    let identical = diff.is_identical();
+
+    if json {
+        print_json(&DiffReport {
+            schema_version: SCHEMA_VERSION,
+            command: "diff",
+            old: old_snapshot.source,
+            new: new_snapshot.source,
+            algorithm: old_snapshot.algorithm,
+            diff,
+            identical,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(identical);
+    }
+    if status {
+        return Ok(identical);
+    }
+
+    for path in &diff.added {
+        println!("added:    {path}");
+    }
+    for path in &diff.removed {
+        println!("removed:  {path}");
+    }
+    for file in &diff.modified {
+        println!("modified: {}", file.path);
+    }
+    for file in &diff.renamed {
+        println!("renamed:  {} -> {}", file.from, file.to);
+    }
+    banner!(
+        "{} added, {} removed, {} modified, {} renamed, {} unchanged",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len(),
+        diff.renamed.len(),
+        diff.unchanged
+    );
+    Ok(identical)
+}
+
+/// Run `tree`
+fn run_tree(args: TreeArgs, json: bool, cache: &CliCache) -> Result<bool> {
+    let TreeArgs {
+        directory,
+        algorithm,
+        output_format,
+        modes,
+        empty_dirs,
+        quiet,
+        walk,
+    } = args;
+    let forge = HashForge::new()
+        .with_progress(false)
+        .with_cache(cache.get());
+    let options = TreeOptions { modes, empty_dirs };
+    let start = Instant::now();
+    let tree = forge.tree_digest(&directory, algorithm, &walk, &options)?;
+
+    if json {
+        print_json(&TreeReport {
+            schema_version: SCHEMA_VERSION,
+            command: "tree",
+            directory,
+            algorithm,
+            encoding: output_format.text_form(),
+            digest: tree.digest.encode(output_format),
+            modes,
+            empty_dirs,
+            symlinks: walk.symlinks,
+            files: tree.files,
+            directories: tree.directories,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(true);
+    }
+
+    if quiet {
+        print_digest(None, &tree.digest, output_format, DigestForm::Plain)?;
+        return Ok(true);
+    }
+
+    banner!("🔧 Hash Forge - Tree Digest");
+    banner!("Directory: {}", directory.display());
+    banner!("Algorithm: {algorithm}");
+    banner!(
+        "Covered: {} files, {} directories",
+        tree.files,
+        tree.directories
+    );
+    print_digest(
+        Some("Tree digest"),
+        &tree.digest,
+        output_format,
+        DigestForm::Plain,
+    )?;
+    Ok(true)
+}
+
+/// Run `dupes`
+fn run_dupes(args: DupesArgs, json: bool, cache: &CliCache) -> Result<bool> {
+    let DupesArgs {
+        directory,
+        algorithm,
+        min_size,
+        hardlink,
+        walk,
+    } = args;
+    let forge = HashForge::new()
+        .with_progress(false)
+        .with_cache(cache.get());
+    let start = Instant::now();
+    let options = DupeOptions { min_size };
+    let found = forge.find_duplicates(&directory, algorithm, &walk, &options)?;
+
+    if !json {
+        banner!("🔧 Hash Forge - Duplicate Files");
+        banner!("Directory: {}", directory.display());
+        banner!("Algorithm: {algorithm}");
+        banner!();
+        for skipped in &found.skipped {
+            eprintln!("hash-forge: {}: {}", skipped.path, skipped.error);
+        }
+    }
+
+    let mut link_errors = Vec::new();
+    let mut freed = 0;
+    for set in &found.sets {
+        if !json {
+            println!(
+                "{} × {} ({} wasted)",
+                format_file_size(set.size),
+                set.files.len(),
+                format_file_size(set.wasted())
+            );
+            for file in &set.files {
+                println!("  {}", file.display_path());
             }
         }
+        if hardlink {
+            for (entry, outcome) in forge.hardlink_duplicates(set) {
+                match outcome {
+                    Ok(()) => freed += set.size,
+                    Err(e) => {
+                        if !json {
+                            eprintln!("hash-forge: {}: {e:#}", entry.display_path());
+                        }
+                        link_errors.push(FileError {
+                            path: entry.display_path(),
+                            error: format!("{e:#}"),
+                        });
+                    }
+                }
+            }
+        }
+        if !json {
+            println!();
+        }
+    }
 
-        Commands::Batch {
+    let success = found.skipped.is_empty() && link_errors.is_empty();
+    if json {
+        print_json(&DupesReport {
+            schema_version: SCHEMA_VERSION,
+            command: "dupes",
             directory,
             algorithm,
-            output_format,
-        } => {
-            let forge = HashForge::new();
+            scanned: found.scanned,
+            hashed: found.hashed,
+            wasted: found.wasted(),
+            sets: found.sets.iter().map(DuplicateRecord::new).collect(),
+            skipped: found.skipped,
+            hardlinked: hardlink,
+            link_errors,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(success);
+    }
 
-            println!("🔧 Hash Forge - Batch Processing");
-            println!("Directory: {}", directory.display());
-            println!("Algorithm: {algorithm}");
+    let redundant: usize = found.sets.iter().map(|set| set.files.len() - 1).sum();
+    banner!(
+        "{} duplicate sets, {redundant} redundant files, {} wasted",
+        found.sets.len(),
+        format_file_size(found.wasted())
+    );
+    banner!(
+        "Scanned {} files, fully hashed {}",
+        found.scanned,
+        found.hashed
+    );
+    if hardlink {
+        banner!("Freed {} with hardlinks", format_file_size(freed));
+    }
+    Ok(success)
+}
 
-            forge.batch_process_directory(&directory, algorithm, output_format)?;
+/// Run `watch`
+fn run_watch(args: WatchArgs, json: bool) -> Result<bool> {
+    let WatchArgs {
+        paths,
+        algorithm,
+        output_format,
+        manifest,
+        debounce,
+        walk,
+    } = args;
+    let forge = HashForge::new().with_progress(false);
+    let manifest = match manifest {
+        Some(ref path) => {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open manifest: {}", path.display()))?;
+            Some(Snapshot::from_manifest(
+                std::io::BufReader::new(file),
+                path,
+                algorithm,
+            )?)
         }
+        None => None,
+    };
+    let algorithm = manifest.as_ref().map_or(algorithm, |m| m.algorithm);
+    let options = WatchOptions {
+        debounce: std::time::Duration::from_millis(debounce),
+    };
 
-        Commands::Hmac {
-            text,
-            file,
-            key,
+    if !json {
+        banner!("🔧 Hash Forge - Watch");
+        banner!(
+            "Watching: {}",
+            paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        banner!("Algorithm: {algorithm}");
+        banner!("Press Ctrl+C to stop");
+        banner!();
+    }
+
+    forge.watch(&paths, algorithm, &walk, &options, |event| {
+        // Manifest paths are relative to the watched directory, or as given
+        let expected = manifest.as_ref().map(|m| {
+            m.files
+                .get(&event.relative)
+                .or_else(|| m.files.get(&event.path.display().to_string()))
+        });
+        let verdict = match (&event.change, expected) {
+            (_, None) => None,
+            (WatchChange::Hashed(_), Some(None)) => Some("unlisted"),
+            (WatchChange::Hashed(result), Some(Some(digest))) => {
+                Some(if result.to_hex() == *digest {
+                    "ok"
+                } else {
+                    "failed"
+                })
+            }
+            (WatchChange::Removed, Some(Some(_))) => Some("missing"),
+            (WatchChange::Removed, Some(None)) => None,
+            (WatchChange::Failed(_), Some(Some(_))) => Some("unreadable"),
+            (WatchChange::Failed(_), Some(None)) => Some("unlisted"),
+        };
+        let path = event.path.display().to_string();
+
+        if json {
+            let (name, digest, error) = match &event.change {
+                WatchChange::Hashed(result) => ("hashed", Some(result.encode(output_format)), None),
+                WatchChange::Removed => ("removed", None, None),
+                WatchChange::Failed(error) => ("failed", None, Some(error.clone())),
+            };
+            println!(
+                "{}",
+                serde_json::to_string(&WatchRecord {
+                    time: format_timestamp(std::time::SystemTime::now()),
+                    path,
+                    event: name,
+                    initial: event.initial,
+                    algorithm,
+                    digest,
+                    verdict,
+                    error,
+                })?
+            );
+            return Ok(());
+        }
+
+        match (&event.change, verdict) {
+            (_, Some("ok")) => println!("{path}: OK"),
+            (_, Some("failed")) => println!("{path}: FAILED"),
+            (_, Some("missing")) => println!("{path}: MISSING"),
+            (_, Some("unreadable")) => println!("{path}: FAILED open or read"),
+            (_, Some(_)) => println!("{path}: NOT IN MANIFEST"),
+            (WatchChange::Hashed(result), None) => {
+                println!("{}  {path}", result.encode(output_format))
+            }
+            (WatchChange::Removed, None) => eprintln!("hash-forge: {path}: removed"),
+            (WatchChange::Failed(_), None) => {}
+        }
+        if let WatchChange::Failed(error) = &event.change {
+            eprintln!("hash-forge: {error}");
+        }
+        Ok(())
+    })?;
+    Ok(true)
+}
+
+/// Run `chunks`
+fn run_chunks(args: ChunksArgs, json: bool) -> Result<bool> {
+    let ChunksArgs {
+        paths,
+        algorithm,
+        min_size,
+        avg_size,
+        max_size,
+        list,
+        walk,
+    } = args;
+    let forge = HashForge::new().with_progress(!json && paths.len() == 1);
+    let options = ChunkOptions {
+        min_size,
+        avg_size,
+        max_size,
+    };
+    let start = Instant::now();
+    let analysis = forge.analyze_chunks(&paths, algorithm, &walk, &options)?;
+    let success = analysis.skipped.is_empty();
+
+    if json {
+        print_json(&ChunksReport {
+            schema_version: SCHEMA_VERSION,
+            command: "chunks",
             algorithm,
-            output_format,
-        } => {
-            if !HmacProcessor::supports_hmac(algorithm) {
-                anyhow::bail!("HMAC is not supported for algorithm: {}", algorithm);
+            min_size,
+            avg_size,
+            max_size,
+            files: analysis
+                .files
+                .iter()
+                .map(|file| ChunkedFileRecord::new(file, list))
+                .collect(),
+            total_bytes: analysis.total_bytes(),
+            total_chunks: analysis.total_chunks(),
+            unique_bytes: analysis.unique_bytes,
+            unique_chunks: analysis.unique_chunks,
+            dedup_ratio: analysis.dedup_ratio(),
+            savings: analysis.savings(),
+            skipped: analysis.skipped,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(success);
+    }
+
+    banner!("🔧 Hash Forge - Chunk Analysis");
+    banner!("Algorithm: {algorithm}");
+    banner!(
+        "Chunk sizes: {} min, {} average, {} max",
+        format_file_size(min_size),
+        format_file_size(avg_size),
+        format_file_size(max_size)
+    );
+    banner!();
+    for skipped in &analysis.skipped {
+        eprintln!("hash-forge: {}: {}", skipped.path, skipped.error);
+    }
+    for file in &analysis.files {
+        println!(
+            "{}: {} chunks, {}",
+            file.path.display(),
+            file.chunks.len(),
+            format_file_size(file.size)
+        );
+        if list {
+            for chunk in &file.chunks {
+                println!(
+                    "  {:>12} {:>8} {}",
+                    chunk.offset,
+                    chunk.length,
+                    hex::encode(&chunk.digest)
+                );
             }
+        }
+    }
+
+    let total_chunks = analysis.total_chunks();
+    banner!();
+    banner!(
+        "Files: {}, {}",
+        analysis.files.len(),
+        format_file_size(analysis.total_bytes())
+    );
+    banner!(
+        "Chunks: {total_chunks}, {} unique (average {})",
+        analysis.unique_chunks,
+        format_file_size(analysis.total_bytes() / total_chunks.max(1) as u64)
+    );
+    banner!(
+        "Unique data: {} of {}",
+        format_file_size(analysis.unique_bytes),
+        format_file_size(analysis.total_bytes())
+    );
+    banner!(
+        "Dedup ratio: {:.2}x ({:.1}% saved)",
+        analysis.dedup_ratio(),
+        analysis.savings() * 100.0
+    );
+    Ok(success)
+}
+
+/// Run `signature`
+fn run_signature(args: SignatureArgs, json: bool) -> Result<bool> {
+    let SignatureArgs {
+        file,
+        signature,
+        algorithm,
+        block_size,
+    } = args;
+    let forge = HashForge::new().with_progress(!json);
+    let block_size = block_size
+        .map(u32::try_from)
+        .transpose()
+        .context("Block size is too large")?;
+    let start = Instant::now();
+    let result = forge.signature(&file, algorithm, block_size)?;
+    let out = std::fs::File::create(&signature)
+        .with_context(|| format!("Failed to create file: {}", signature.display()))?;
+    result.write(std::io::BufWriter::new(out))?;
+    let signature_size = std::fs::metadata(&signature)?.len();
+
+    if json {
+        print_json(&SignatureReport {
+            schema_version: SCHEMA_VERSION,
+            command: "signature",
+            file,
+            signature,
+            algorithm,
+            block_size: result.block_size,
+            file_size: result.file_size,
+            blocks: result.blocks.len(),
+            signature_size,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(true);
+    }
+
+    banner!("🔧 Hash Forge - Signature");
+    banner!(
+        "File: {} ({})",
+        file.display(),
+        format_file_size(result.file_size)
+    );
+    banner!("Algorithm: {algorithm}");
+    banner!(
+        "Blocks: {} of {} bytes",
+        result.blocks.len(),
+        result.block_size
+    );
+    banner!(
+        "Signature: {} ({})",
+        signature.display(),
+        format_file_size(signature_size)
+    );
+    Ok(true)
+}
+
+/// Run `delta`
+fn run_delta(args: DeltaArgs, json: bool) -> Result<bool> {
+    let DeltaArgs {
+        signature,
+        file,
+        delta,
+    } = args;
+    let forge = HashForge::new().with_progress(!json);
+    let start = Instant::now();
+    let reader = std::fs::File::open(&signature)
+        .with_context(|| format!("Failed to open signature: {}", signature.display()))?;
+    let basis = Signature::read(std::io::BufReader::new(reader), &signature)?;
+    let out = std::fs::File::create(&delta)
+        .with_context(|| format!("Failed to create file: {}", delta.display()))?;
+    let summary = forge.delta(&basis, &file, out)?;
+    let delta_size = std::fs::metadata(&delta)?.len();
+
+    if json {
+        print_json(&DeltaReport {
+            schema_version: SCHEMA_VERSION,
+            command: "delta",
+            signature,
+            file,
+            delta,
+            algorithm: basis.algorithm,
+            block_size: basis.block_size,
+            file_size: summary.target_size,
+            copied_bytes: summary.copied_bytes,
+            literal_bytes: summary.literal_bytes,
+            copy_commands: summary.copy_commands,
+            literal_commands: summary.literal_commands,
+            delta_size,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(true);
+    }
+
+    banner!("🔧 Hash Forge - Delta");
+    banner!(
+        "File: {} ({})",
+        file.display(),
+        format_file_size(summary.target_size)
+    );
+    banner!(
+        "Reused from basis: {} in {} copies",
+        format_file_size(summary.copied_bytes),
+        summary.copy_commands
+    );
+    banner!("New data: {}", format_file_size(summary.literal_bytes));
+    banner!(
+        "Delta: {} ({})",
+        delta.display(),
+        format_file_size(delta_size)
+    );
+    Ok(true)
+}
+
+/// Run `patch`
+fn run_patch(args: PatchArgs, json: bool) -> Result<bool> {
+    let PatchArgs {
+        basis,
+        delta,
+        new_file,
+    } = args;
+    let forge = HashForge::new().with_progress(!json);
+    let start = Instant::now();
+    let digest = forge.patch(&basis, &delta, &new_file)?;
+    let size = std::fs::metadata(&new_file)?.len();
+
+    if json {
+        print_json(&PatchReport {
+            schema_version: SCHEMA_VERSION,
+            command: "patch",
+            basis,
+            delta,
+            output: new_file,
+            algorithm: digest.algorithm,
+            size,
+            digest: digest.to_hex(),
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(true);
+    }
+
+    banner!("🔧 Hash Forge - Patch");
+    banner!(
+        "Output: {} ({})",
+        new_file.display(),
+        format_file_size(size)
+    );
+    banner!("Algorithm: {}", digest.algorithm);
+    banner!("Digest: {}", digest.to_hex());
+    println!("✅ Result matches the delta's digest");
+    Ok(true)
+}
+
+/// Run `hmac`
+fn run_hmac(args: HmacArgs, json: bool) -> Result<bool> {
+    let HmacArgs {
+        text,
+        input_encoding,
+        file,
+        offset,
+        length,
+        key,
+        algorithm,
+        output_format,
+        quiet,
+    } = args;
+    if !HmacProcessor::supports_hmac(algorithm) {
+        anyhow::bail!("HMAC is not supported for algorithm: {}", algorithm);
+    }
+
+    let start = Instant::now();
+    let range = FileRange::from_options(offset, length);
+    let (result, input) = if let Some(ref text_value) = text {
+        let data = input_encoding.decode(text_value)?;
+        (
+            HmacProcessor::compute_hmac(&data, key.as_bytes(), algorithm)?,
+            InputDescriptor::encoded_text(text_value, input_encoding, data.len()),
+        )
+    } else if let Some(ref file_path) = file {
+        let (reader, _) = open_range(file_path, &range.unwrap_or_default())?;
+        (
+            HmacProcessor::compute_hmac_reader(reader, key.as_bytes(), algorithm)?,
+            InputDescriptor::file_range(file_path, range),
+        )
+    } else {
+        anyhow::bail!("Either --text or --file must be specified for HMAC");
+    };
+
+    if json {
+        let report = HashReport::new("hmac", input, &result, output_format, start.elapsed());
+        print_json(&report)?;
+        return Ok(true);
+    }
+
+    if quiet {
+        print_digest(None, &result, output_format, DigestForm::Plain)?;
+        return Ok(true);
+    }
+
+    banner!("🔧 Hash Forge - HMAC Computation");
+    banner!("Algorithm: {algorithm}");
+    banner!("Key: <hidden for security>");
+    if let Some(ref t) = text {
+        banner!("Input: {t}");
+        if input_encoding != InputEncoding::Utf8 {
+            banner!("Input encoding: {input_encoding}");
+        }
+    } else if let Some(ref f) = file {
+        banner!("File: {}", f.display());
+        if let Some(ref range) = range {
+            banner!("Range: {}", describe_range(range));
+        }
+    }
+    print_digest(Some("HMAC"), &result, output_format, DigestForm::Plain)?;
+    Ok(true)
+}
 
-            let data = if let Some(ref text_value) = text {
-                text_value.as_bytes().to_vec()
-            } else if let Some(ref file_path) = file {
-                std::fs::read(file_path)?
+/// Run `verify-hmac`
+fn run_verify_hmac(args: VerifyHmacArgs, json: bool) -> Result<bool> {
+    let VerifyHmacArgs {
+        text,
+        input_encoding,
+        file,
+        offset,
+        length,
+        key,
+        expected_hmac,
+        algorithm,
+        quiet,
+    } = args;
+    if !HmacProcessor::supports_hmac(algorithm) {
+        anyhow::bail!("HMAC is not supported for algorithm: {}", algorithm);
+    }
+
+    let start = Instant::now();
+    let range = FileRange::from_options(offset, length);
+    let (computed, input) = if let Some(ref text_value) = text {
+        let data = input_encoding.decode(text_value)?;
+        (
+            HmacProcessor::compute_hmac(&data, key.as_bytes(), algorithm)?,
+            InputDescriptor::encoded_text(text_value, input_encoding, data.len()),
+        )
+    } else if let Some(ref file_path) = file {
+        let (reader, _) = open_range(file_path, &range.unwrap_or_default())?;
+        (
+            HmacProcessor::compute_hmac_reader(reader, key.as_bytes(), algorithm)?,
+            InputDescriptor::file_range(file_path, range),
+        )
+    } else {
+        anyhow::bail!("Either --text or --file must be specified for HMAC verification");
+    };
+
+    // Parse expected HMAC from hex
+    let expected_bytes = hex::decode(&expected_hmac)
+        .map_err(|_| anyhow::anyhow!("Invalid hex format for expected HMAC"))?;
+    let is_valid = HmacProcessor::matches(&computed, &expected_bytes);
+
+    if json {
+        print_json(&VerifyReport {
+            schema_version: SCHEMA_VERSION,
+            command: "verify-hmac",
+            input,
+            algorithm: Some(algorithm),
+            detected: false,
+            candidates: Vec::new(),
+            expected: expected_hmac,
+            computed: Some(computed.to_hex()),
+            verdict: if is_valid {
+                Verdict::Pass
             } else {
-                anyhow::bail!("Either --text or --file must be specified for HMAC");
+                Verdict::Fail
+            },
+            normalization: None,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(is_valid);
+    }
+    if quiet {
+        return Ok(is_valid);
+    }
+
+    banner!("🔧 Hash Forge - HMAC Verification");
+    banner!("Algorithm: {algorithm}");
+    banner!("Expected: {expected_hmac}");
+    banner!("Computed: {}", computed.to_hex());
+
+    if is_valid {
+        println!("✅ HMAC verification PASSED");
+    } else {
+        println!("❌ HMAC verification FAILED");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Describe a `--offset`/`--length` range for banners
+fn describe_range(range: &FileRange) -> String {
+    match range.length {
+        Some(length) => format!("{length} bytes at offset {}", range.offset),
+        None => format!("offset {} to the end of the file", range.offset),
+    }
+}
+
+/// Apply `normalization` to `data` when any of its options is set
+fn normalize(normalization: &Normalization, data: Vec<u8>) -> Result<Vec<u8>> {
+    if normalization.is_enabled() {
+        normalization.apply(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Normalization works on whole files, so it can't be combined with a byte range
+fn check_normalization(normalization: &Normalization, range: &Option<FileRange>) -> Result<()> {
+    if normalization.is_enabled() && range.is_some() {
+        anyhow::bail!("Normalization options cannot be combined with --offset or --length");
+    }
+    Ok(())
+}
+
+/// Digest form selected by the `--tagged`, `--multihash` and `--cid` flags
+fn digest_form(tagged: bool, multihash: bool, cid: bool) -> DigestForm {
+    match (tagged, multihash, cid) {
+        (true, _, _) => DigestForm::Tagged,
+        (_, true, _) => DigestForm::Multihash,
+        (_, _, true) => DigestForm::Cid,
+        _ => DigestForm::Plain,
+    }
+}
+
+/// Digest cache for the commands that use it, loaded on first use and saved
+/// once the command has finished
+struct CliCache {
+    enabled: bool,
+    refresh: bool,
+    cache: OnceCell<Option<SharedCache>>,
+}
+
+impl CliCache {
+    fn new(enabled: bool, refresh: bool) -> Self {
+        Self {
+            enabled,
+            refresh,
+            cache: OnceCell::new(),
+        }
+    }
+
+    /// The cache, or `None` if disabled or unusable (with a warning)
+    fn get(&self) -> Option<SharedCache> {
+        self.cache
+            .get_or_init(|| {
+                if !self.enabled {
+                    return None;
+                }
+                let path = HashCache::default_path()?;
+                match HashCache::load(&path) {
+                    Ok(cache) => Some(cache.refreshing(self.refresh).shared()),
+                    Err(e) => {
+                        eprintln!("hash-forge: not using the cache: {e:#}");
+                        eprintln!("hash-forge: run 'hash-forge cache clear' to reset it");
+                        None
+                    }
+                }
+            })
+            .clone()
+    }
+
+    fn save(&self) {
+        if let Some(Some(cache)) = self.cache.get() {
+            let mut cache = cache
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Err(e) = cache.save() {
+                eprintln!("hash-forge: {e:#}");
+            }
+        }
+    }
+}
+
+/// Open a checksum manifest, or standard input for `-`, and name it for messages
+fn open_manifest(path: &std::path::Path) -> Result<(Box<dyn BufRead>, String)> {
+    if path.as_os_str() == "-" {
+        return Ok((Box::new(std::io::stdin().lock()), "standard input".into()));
+    }
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open manifest: {}", path.display()))?;
+    Ok((
+        Box::new(std::io::BufReader::new(file)),
+        path.display().to_string(),
+    ))
+}
+
+/// The reason a file could not be opened, worded like coreutils
+/// ("No such file or directory")
+fn open_error_message(error: &anyhow::Error) -> String {
+    match error.root_cause().downcast_ref::<std::io::Error>() {
+        Some(io_error) => {
+            let message = io_error.to_string();
+            match message.split_once(" (os error") {
+                Some((reason, _)) => reason.to_string(),
+                None => message,
+            }
+        }
+        None => format!("{error:#}"),
+    }
+}
+
+/// Run an `archive` action
+fn run_archive(action: ArchiveAction, json: bool) -> Result<bool> {
+    let forge = HashForge::new().with_progress(!json);
+    let start = Instant::now();
+
+    match action {
+        ArchiveAction::Manifest {
+            archive,
+            algorithm,
+            format,
+            binary,
+            output_file,
+        } => {
+            let mut writer: Box<dyn Write> = match output_file {
+                Some(ref path) => Box::new(std::io::BufWriter::new(
+                    std::fs::File::create(path)
+                        .with_context(|| format!("Failed to create file: {}", path.display()))?,
+                )),
+                None if json => Box::new(std::io::sink()),
+                None => Box::new(std::io::stdout().lock()),
             };
 
-            let result = HmacProcessor::compute_hmac(&data, key.as_bytes(), algorithm)?;
-            let formatted = match output_format {
-                OutputFormat::Hex => result.to_hex(),
-                OutputFormat::Base64 => result.to_base64(),
+            let mut members = Vec::new();
+            let archive_format = forge.hash_archive(&archive, algorithm, |member| {
+                let digest = member.digest.to_hex();
+                let line = ManifestEntry::new(member.path.as_str(), digest.as_str(), binary);
+                writeln!(writer, "{}", line.to_line(algorithm, format))?;
+                members.push(ArchiveMemberRecord {
+                    path: member.path,
+                    size: member.size,
+                    digest,
+                });
+                Ok(())
+            })?;
+            writer.flush()?;
+
+            if json {
+                print_json(&ArchiveManifestReport {
+                    schema_version: SCHEMA_VERSION,
+                    command: "archive",
+                    action: "manifest",
+                    archive,
+                    archive_format,
+                    algorithm,
+                    format,
+                    output_file,
+                    members,
+                    elapsed_ms: millis(start.elapsed()),
+                })?;
+            }
+            Ok(true)
+        }
+
+        ArchiveAction::Check {
+            archive,
+            manifest,
+            algorithm,
+            quiet,
+            status,
+            strict,
+            warn,
+            ignore_missing,
+        } => {
+            let options = CheckOptions {
+                quiet,
+                status,
+                strict,
+                warn,
+                ignore_missing,
             };
+            let (reader, source) = open_manifest(&manifest)?;
+
+            let mut results = Vec::new();
+            let check = forge.check_archive(&archive, reader, algorithm, &options, |event| {
+                if json {
+                    results.push(event.clone());
+                } else {
+                    print_check_event(event, &source, algorithm, &options);
+                }
+            })?;
+            let success = check.summary.is_success(&options);
 
-            println!("🔧 Hash Forge - HMAC Computation");
-            println!("Algorithm: {algorithm}");
-            println!("Key: <hidden for security>");
-            if let Some(ref t) = text {
-                println!("Input: {t}");
-            } else if let Some(ref f) = file {
-                println!("File: {}", f.display());
+            if json {
+                print_json(&ArchiveCheckReport {
+                    schema_version: SCHEMA_VERSION,
+                    command: "archive",
+                    action: "check",
+                    archive,
+                    archive_format: check.format,
+                    manifest: source,
+                    results,
+                    summary: check.summary,
+                    unlisted: check.unlisted,
+                    verdict: if success {
+                        Verdict::Pass
+                    } else {
+                        Verdict::Fail
+                    },
+                    elapsed_ms: millis(start.elapsed()),
+                })?;
+                return Ok(success);
             }
-            println!("HMAC ({output_format}): {formatted}");
+
+            print_check_summary(&source, &check.summary, &options);
+            if !check.unlisted.is_empty() && !options.status {
+                let count = check.unlisted.len();
+                eprintln!(
+                    "hash-forge: WARNING: {count} archive {} not listed in {source}",
+                    if count == 1 {
+                        "member is"
+                    } else {
+                        "members are"
+                    }
+                );
+            }
+            Ok(success)
         }
+    }
+}
 
-        Commands::VerifyHmac {
-            text,
+/// Run a `blocks` action, returning whether the file matched its list
+fn run_blocks(action: BlockAction, json: bool) -> Result<bool> {
+    let forge = HashForge::new().with_progress(!json);
+    let start = Instant::now();
+
+    match action {
+        BlockAction::Create {
             file,
-            key,
-            expected_hmac,
             algorithm,
+            block_size,
+            output_file,
         } => {
-            if !HmacProcessor::supports_hmac(algorithm) {
-                anyhow::bail!("HMAC is not supported for algorithm: {}", algorithm);
+            let list = forge.block_list(&file, algorithm, block_size)?;
+            if let Some(ref path) = output_file {
+                let out = std::fs::File::create(path)
+                    .with_context(|| format!("Failed to create file: {}", path.display()))?;
+                list.write(std::io::BufWriter::new(out))?;
+            } else if !json {
+                list.write(std::io::stdout().lock())?;
+                return Ok(true);
+            }
+
+            if json {
+                print_json(&BlocksReport {
+                    schema_version: SCHEMA_VERSION,
+                    command: "blocks",
+                    action: "create",
+                    file,
+                    algorithm,
+                    block_size,
+                    file_size: list.file_size,
+                    top: list.top.to_hex(),
+                    blocks: list.blocks.iter().map(hex::encode).collect(),
+                    output_file,
+                    elapsed_ms: millis(start.elapsed()),
+                })?;
+                return Ok(true);
+            }
+
+            banner!("🔧 Hash Forge - Block List");
+            banner!(
+                "File: {} ({})",
+                file.display(),
+                format_file_size(list.file_size)
+            );
+            banner!(
+                "Algorithm: {algorithm}, {} blocks",
+                format_file_size(block_size)
+            );
+            banner!("Blocks: {}", list.blocks.len());
+            banner!("Top digest: {}", list.top.to_hex());
+            if let Some(path) = output_file {
+                banner!("Written to {}", path.display());
+            }
+            Ok(true)
+        }
+
+        BlockAction::Compare { file, list } => {
+            let reader = std::fs::File::open(&list)
+                .with_context(|| format!("Failed to open block list: {}", list.display()))?;
+            let expected = BlockList::parse(std::io::BufReader::new(reader), &list)?;
+            let comparison = forge.compare_blocks(&file, &expected)?;
+            let identical = comparison.is_identical();
+
+            if json {
+                print_json(&BlockCompareReport {
+                    schema_version: SCHEMA_VERSION,
+                    command: "blocks",
+                    action: "compare",
+                    file,
+                    list,
+                    algorithm: expected.algorithm,
+                    block_size: expected.block_size,
+                    file_size: comparison.current.file_size,
+                    expected_file_size: expected.file_size,
+                    top: comparison.current.top.to_hex(),
+                    expected_top: expected.top.to_hex(),
+                    blocks: comparison.blocks,
+                    differing_blocks: comparison.differing,
+                    ranges: comparison.ranges,
+                    verdict: if identical {
+                        Verdict::Pass
+                    } else {
+                        Verdict::Fail
+                    },
+                    elapsed_ms: millis(start.elapsed()),
+                })?;
+                return Ok(identical);
             }
 
-            let data = if let Some(ref text_value) = text {
-                text_value.as_bytes().to_vec()
-            } else if let Some(ref file_path) = file {
-                std::fs::read(file_path)?
+            banner!("🔧 Hash Forge - Block Comparison");
+            banner!("File: {}", file.display());
+            banner!("Block list: {}", list.display());
+            banner!(
+                "Algorithm: {}, {} blocks",
+                expected.algorithm,
+                format_file_size(expected.block_size)
+            );
+            if comparison.current.file_size != expected.file_size {
+                banner!(
+                    "Size: {} bytes, list expects {} bytes",
+                    comparison.current.file_size,
+                    expected.file_size
+                );
+            }
+            banner!();
+            for range in &comparison.ranges {
+                let blocks = if range.first_block == range.last_block {
+                    format!("block {}", range.first_block)
+                } else {
+                    format!("blocks {}-{}", range.first_block, range.last_block)
+                };
+                println!(
+                    "bytes {}-{} ({}, {blocks})",
+                    range.start,
+                    range.end - 1,
+                    format_file_size(range.len())
+                );
+            }
+            if identical {
+                println!("✅ All {} blocks match", comparison.blocks);
             } else {
-                anyhow::bail!("Either --text or --file must be specified for HMAC verification");
-            };
+                println!(
+                    "❌ {} of {} blocks differ",
+                    comparison.differing, comparison.blocks
+                );
+            }
+            Ok(identical)
+        }
+    }
+}
+
+/// Run a `cache` action
+fn run_cache(action: CacheAction, json: bool) -> Result<bool> {
+    let start = Instant::now();
+    let path =
+        HashCache::default_path().context("No cache location: set HASH_FORGE_CACHE or HOME")?;
+
+    let (name, entries, removed) = match action {
+        CacheAction::Info => ("info", HashCache::load(&path)?.len(), None),
+        CacheAction::Prune => {
+            let mut cache = HashCache::load(&path)?;
+            let summary = cache.prune();
+            cache.save()?;
+            ("prune", summary.kept, Some(summary.removed))
+        }
+        CacheAction::Clear => {
+            // Even an unreadable cache can be cleared
+            let count = HashCache::load(&path).map(|cache| cache.len()).unwrap_or(0);
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to remove cache: {}", path.display()))
+                }
+                _ => {}
+            }
+            ("clear", 0, Some(count))
+        }
+    };
+
+    if json {
+        print_json(&CacheReport {
+            schema_version: SCHEMA_VERSION,
+            command: "cache",
+            action: name,
+            path,
+            entries,
+            removed,
+            elapsed_ms: millis(start.elapsed()),
+        })?;
+        return Ok(true);
+    }
+
+    println!("Cache: {}", path.display());
+    if let Some(removed) = removed {
+        println!("Removed: {removed} entries");
+    }
+    println!("Entries: {entries}");
+    Ok(true)
+}
 
-            // Parse expected HMAC from hex
-            let expected_bytes = hex::decode(&expected_hmac)
-                .map_err(|_| anyhow::anyhow!("Invalid hex format for expected HMAC"))?;
+/// Run an `sri` action, returning whether it succeeded
+fn run_sri(action: SriAction, json: bool) -> Result<bool> {
+    let forge = HashForge::new().with_progress(false);
+    let start = Instant::now();
 
-            let is_valid =
-                HmacProcessor::verify_hmac(&data, key.as_bytes(), &expected_bytes, algorithm)?;
+    match action {
+        SriAction::Generate { files, algorithms } => {
+            let mut records = Vec::new();
+            for path in files {
+                let hashes = forge.sri_hashes(&path, &algorithms)?;
+                records.push(SriRecord {
+                    path,
+                    integrity: format_integrity(&hashes),
+                });
+            }
 
-            println!("🔧 Hash Forge - HMAC Verification");
-            println!("Algorithm: {algorithm}");
-            println!("Expected: {expected_hmac}");
+            if json {
+                print_json(&SriReport {
+                    schema_version: SCHEMA_VERSION,
+                    command: "sri",
+                    action: "generate",
+                    files: records,
+                    elapsed_ms: millis(start.elapsed()),
+                })?;
+            } else if let [record] = records.as_slice() {
+                println!("{}", record.integrity);
+            } else {
+                for record in &records {
+                    println!("{}  {}", record.integrity, record.path.display());
+                }
+            }
+            Ok(true)
+        }
 
-            let computed = HmacProcessor::compute_hmac(&data, key.as_bytes(), algorithm)?;
-            println!("Computed: {}", computed.to_hex());
+        SriAction::Verify { file, integrity } => {
+            let parsed = Integrity::parse(&integrity);
+            let check = forge.verify_integrity(&file, &parsed)?;
 
-            if is_valid {
-                println!("✅ HMAC verification PASSED");
+            if json {
+                print_json(&VerifyReport {
+                    schema_version: SCHEMA_VERSION,
+                    command: "sri",
+                    input: InputDescriptor::file(&file),
+                    algorithm: Some(check.computed.algorithm),
+                    detected: false,
+                    candidates: Vec::new(),
+                    expected: integrity,
+                    computed: Some(check.computed.to_hex()),
+                    verdict: if check.matched {
+                        Verdict::Pass
+                    } else {
+                        Verdict::Fail
+                    },
+                    normalization: None,
+                    elapsed_ms: millis(start.elapsed()),
+                })?;
+                return Ok(check.matched);
+            }
+
+            banner!("🔧 Hash Forge - Subresource Integrity");
+            banner!("File: {}", file.display());
+            banner!("Expected: {integrity}");
+            banner!("Computed: {}", check.computed.to_sri());
+            if check.matched {
+                println!("✅ Integrity verification PASSED");
             } else {
-                println!("❌ HMAC verification FAILED");
-                std::process::exit(1);
+                println!("❌ Integrity verification FAILED");
+            }
+            Ok(check.matched)
+        }
+
+        SriAction::Html {
+            html,
+            root,
+            write,
+            algorithms,
+        } => {
+            let result = forge.check_html_integrity(&html, root.as_deref(), write, &algorithms)?;
+            if let Some(ref rewritten) = result.rewritten {
+                std::fs::write(&html, rewritten)
+                    .with_context(|| format!("Failed to write HTML file: {}", html.display()))?;
+            }
+            let failures = result.assets.iter().filter(|a| a.is_failure()).count();
+
+            if json {
+                print_json(&SriHtmlReport {
+                    schema_version: SCHEMA_VERSION,
+                    command: "sri",
+                    action: "html",
+                    html,
+                    rewritten: result.rewritten.is_some(),
+                    assets: result.assets,
+                    verdict: if failures == 0 {
+                        Verdict::Pass
+                    } else {
+                        Verdict::Fail
+                    },
+                    elapsed_ms: millis(start.elapsed()),
+                })?;
+                return Ok(failures == 0);
+            }
+
+            let source = html.display();
+            for asset in &result.assets {
+                let status = match asset.status {
+                    AssetStatus::Ok => "OK".to_string(),
+                    AssetStatus::Failed => "FAILED".to_string(),
+                    AssetStatus::Updated => "UPDATED".to_string(),
+                    AssetStatus::Invalid => "FAILED (no usable integrity hash)".to_string(),
+                    AssetStatus::Remote => "SKIPPED (not a local file)".to_string(),
+                    AssetStatus::Unreadable { ref error } => {
+                        eprintln!("hash-forge: {}: {error}", asset.url);
+                        "FAILED open or read".to_string()
+                    }
+                };
+                println!("{source}:{}: {}: {status}", asset.line, asset.url);
+            }
+
+            if result.assets.is_empty() {
+                eprintln!("hash-forge: {source}: no integrity attributes found");
+            } else if failures > 0 {
+                eprintln!(
+                    "hash-forge: WARNING: {failures} of {} integrity values did NOT match",
+                    result.assets.len()
+                );
+            }
+            Ok(failures == 0)
+        }
+    }
+}
+
+/// Read a snapshot for `diff`: a directory is hashed, anything else is read
+/// as a checksum manifest ("-" for standard input)
+fn load_snapshot(
+    forge: &HashForge,
+    path: &std::path::Path,
+    algorithm: HashAlgorithm,
+    walk: &WalkOptions,
+) -> Result<Snapshot> {
+    if path.as_os_str() == "-" {
+        Snapshot::from_manifest(std::io::stdin().lock(), path, algorithm)
+    } else if path.is_dir() {
+        forge.snapshot_directory(path, algorithm, walk)
+    } else {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open manifest: {}", path.display()))?;
+        Snapshot::from_manifest(std::io::BufReader::new(file), path, algorithm)
+    }
+}
+
+/// Print a digest, labelled unless `label` is `None`; raw output is written
+/// as bytes without a label
+fn print_digest(
+    label: Option<&str>,
+    result: &HashResult,
+    format: OutputFormat,
+    form: DigestForm,
+) -> Result<()> {
+    if format.is_binary() {
+        match form {
+            DigestForm::Plain => return Ok(result.write_encoded(format, std::io::stdout().lock())?),
+            DigestForm::Multihash => {
+                let multihash = HashResult::new(result.to_multihash()?, result.algorithm);
+                return Ok(multihash.write_encoded(format, std::io::stdout().lock())?);
             }
+            DigestForm::Tagged | DigestForm::Cid => {}
         }
     }
 
+    let format = format.text_form();
+    let digest = result.render(form, format)?;
+    let description = match form {
+        DigestForm::Plain | DigestForm::Tagged => format.to_string(),
+        DigestForm::Multihash => format!("multihash, {format}"),
+        DigestForm::Cid => "CIDv1".to_string(),
+    };
+    match label {
+        Some(label) => println!("{label} ({description}): {digest}"),
+        None => println!("{digest}"),
+    }
     Ok(())
 }
+
+fn join_algorithms(algorithms: &[HashAlgorithm]) -> String {
+    algorithms
+        .iter()
+        .map(|alg| alg.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,234 @@
+// Multihash and CID encodings (https://multiformats.io) for content addressing
+use crate::{
+    algorithms::HashAlgorithm,
+    output::{decode_expected_hash, HashResult},
+};
+use anyhow::{Context, Result};
+
+/// Multicodec codes of the algorithms that have one, with their table names.
+///
+/// Codes are from the multicodec table
+/// (https://github.com/multiformats/multicodec/blob/master/table.csv). Password
+/// hashes have no multihash representation.
+pub const MULTIHASH_CODES: [(HashAlgorithm, u64, &str); 17] = [
+    (HashAlgorithm::Md5, 0xd5, "md5"),
+    (HashAlgorithm::Sha1, 0x11, "sha1"),
+    (HashAlgorithm::Sha256, 0x12, "sha2-256"),
+    (HashAlgorithm::Sha384, 0x20, "sha2-384"),
+    (HashAlgorithm::Sha512, 0x13, "sha2-512"),
+    (HashAlgorithm::Sha3_224, 0x17, "sha3-224"),
+    (HashAlgorithm::Sha3_256, 0x16, "sha3-256"),
+    (HashAlgorithm::Sha3_384, 0x15, "sha3-384"),
+    (HashAlgorithm::Sha3_512, 0x14, "sha3-512"),
+    (HashAlgorithm::Shake128, 0x18, "shake-128"),
+    (HashAlgorithm::Shake256, 0x19, "shake-256"),
+    (HashAlgorithm::Blake2b, 0xb240, "blake2b-512"),
+    (HashAlgorithm::Blake2s, 0xb260, "blake2s-256"),
+    (HashAlgorithm::Blake3, 0x1e, "blake3"),
+    (HashAlgorithm::XxHash32, 0xb3e1, "xxh-32"),
+    (HashAlgorithm::XxHash64, 0xb3e2, "xxh-64"),
+    (HashAlgorithm::XxHash3, 0xb3e3, "xxh3-64"),
+];
+
+/// Multicodec code of raw binary content, used in CIDs
+pub const RAW_CODEC: u64 = 0x55;
+
+impl HashAlgorithm {
+    /// Multicodec code identifying this algorithm in a multihash
+    pub fn multicodec(&self) -> Option<u64> {
+        MULTIHASH_CODES
+            .iter()
+            .find(|(alg, _, _)| alg == self)
+            .map(|&(_, code, _)| code)
+    }
+
+    /// Looks up an algorithm by its multicodec code
+    pub fn from_multicodec(code: u64) -> Option<HashAlgorithm> {
+        MULTIHASH_CODES
+            .iter()
+            .find(|&&(_, c, _)| c == code)
+            .map(|&(alg, _, _)| alg)
+    }
+}
+
+/// Append `value` as an unsigned varint (LEB128, as used by multiformats)
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read an unsigned varint from the front of `bytes`, returning the value and
+/// the remaining bytes. Multiformats limit varints to 9 bytes.
+fn read_varint(bytes: &[u8]) -> Result<(u64, &[u8])> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate().take(9) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, &bytes[i + 1..]));
+        }
+    }
+    anyhow::bail!("Truncated or oversized varint")
+}
+
+impl HashResult {
+    /// Encode as a multihash: varint code, varint digest length, digest
+    pub fn to_multihash(&self) -> Result<Vec<u8>> {
+        let code = self
+            .algorithm
+            .multicodec()
+            .with_context(|| format!("{} has no multihash code", self.algorithm.name()))?;
+        let mut out = Vec::with_capacity(self.bytes.len() + 4);
+        write_varint(code, &mut out);
+        write_varint(self.bytes.len() as u64, &mut out);
+        out.extend_from_slice(&self.bytes);
+        Ok(out)
+    }
+
+    /// Decode a binary multihash
+    pub fn from_multihash(bytes: &[u8]) -> Result<Self> {
+        let (code, rest) = read_varint(bytes)?;
+        let algorithm = HashAlgorithm::from_multicodec(code)
+            .with_context(|| format!("Unsupported multihash code 0x{code:x}"))?;
+        let (length, digest) = read_varint(rest)?;
+        if digest.len() as u64 != length {
+            anyhow::bail!(
+                "Multihash declares {length} digest bytes but contains {}",
+                digest.len()
+            );
+        }
+        Ok(Self::new(digest.to_vec(), algorithm))
+    }
+
+    /// Encode as a CIDv1 for raw content, in the default base32 multibase
+    /// (`bafkrei...` for SHA-256)
+    pub fn to_cid(&self) -> Result<String> {
+        let mut cid = Vec::new();
+        write_varint(1, &mut cid);
+        write_varint(RAW_CODEC, &mut cid);
+        cid.extend(self.to_multihash()?);
+        let encoded = data_encoding::BASE32_NOPAD.encode(&cid);
+        Ok(format!("b{}", encoded.to_ascii_lowercase()))
+    }
+
+    /// Decode a CID string.
+    ///
+    /// CIDv1 with the raw codec is accepted in the base32 (`b`/`B`),
+    /// base58btc (`z`), hex (`f`), base64 (`m`) and base64url (`u`)
+    /// multibases. A CIDv0 (`Qm...`) is accepted as a bare SHA-256 multihash.
+    pub fn from_cid(cid: &str) -> Result<Self> {
+        use base64::Engine;
+
+        let cid = cid.trim();
+        if cid.len() == 46 && cid.starts_with("Qm") {
+            let multihash = bs58::decode(cid).into_vec().context("Invalid CIDv0")?;
+            return Self::from_multihash(&multihash);
+        }
+
+        let mut chars = cid.chars();
+        let prefix = chars.next().context("Empty CID")?;
+        let body = chars.as_str();
+        let bytes = match prefix {
+            'b' | 'B' => data_encoding::BASE32_NOPAD
+                .decode(body.to_ascii_uppercase().as_bytes())
+                .ok(),
+            'z' => bs58::decode(body).into_vec().ok(),
+            'f' | 'F' => hex::decode(body).ok(),
+            'm' => base64::engine::general_purpose::STANDARD_NO_PAD
+                .decode(body)
+                .ok(),
+            'u' => base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .decode(body)
+                .ok(),
+            _ => anyhow::bail!("Unsupported multibase prefix '{prefix}'"),
+        }
+        .context("Invalid multibase encoding in CID")?;
+
+        let (version, rest) = read_varint(&bytes)?;
+        if version != 1 {
+            anyhow::bail!("Unsupported CID version {version}");
+        }
+        let (codec, multihash) = read_varint(rest)?;
+        if codec != RAW_CODEC {
+            anyhow::bail!("CID codec 0x{codec:x} is not raw (0x55)");
+        }
+        Self::from_multihash(multihash)
+    }
+}
+
+/// Decode an expected hash written as a CID or as a multihash in any
+/// supported text encoding; both name their algorithm.
+///
+/// A multihash is only accepted if its digest has the algorithm's full
+/// length, so that a plain digest is not mistaken for one.
+pub fn decode_content_address(expected: &str) -> Option<HashResult> {
+    let expected = expected.trim();
+    if let Ok(result) = HashResult::from_cid(expected) {
+        return Some(result);
+    }
+    decode_expected_hash(expected)
+        .iter()
+        .filter_map(|bytes| HashResult::from_multihash(bytes).ok())
+        .find(|result| result.bytes.len() == result.algorithm.output_size())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256_hello() -> HashResult {
+        let digest =
+            hex::decode("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824")
+                .unwrap();
+        HashResult::new(digest, HashAlgorithm::Sha256)
+    }
+
+    #[test]
+    fn test_multihash_round_trip() {
+        let result = sha256_hello();
+        let multihash = result.to_multihash().unwrap();
+        assert_eq!(&multihash[..2], &[0x12, 0x20]);
+        assert_eq!(
+            bs58::encode(&multihash).into_string(),
+            "QmRN6wdp1S2A5EtjW9A3M1vKSBuQQGcgvuhoMUoEz4iiT5"
+        );
+        let parsed = HashResult::from_multihash(&multihash).unwrap();
+        assert_eq!(parsed.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(parsed.bytes, result.bytes);
+
+        // Two-byte varint code
+        let blake2b = HashResult::new(vec![0; 64], HashAlgorithm::Blake2b);
+        assert_eq!(
+            &blake2b.to_multihash().unwrap()[..4],
+            &[0xc0, 0xe4, 0x02, 0x40]
+        );
+        assert!(HashResult::from_multihash(&[0x12, 0x20, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_cid() {
+        let result = sha256_hello();
+        let cid = result.to_cid().unwrap();
+        assert_eq!(
+            cid,
+            "bafkreibm6jg3ux5qumhcn2b3flc3tyu6dmlb4xa7u5bf44yegnrjhc4yeq"
+        );
+        assert_eq!(HashResult::from_cid(&cid).unwrap().bytes, result.bytes);
+
+        let v0 = HashResult::from_cid("QmRN6wdp1S2A5EtjW9A3M1vKSBuQQGcgvuhoMUoEz4iiT5").unwrap();
+        assert_eq!(v0.bytes, result.bytes);
+        assert!(HashResult::new(vec![0; 32], HashAlgorithm::Argon2)
+            .to_cid()
+            .is_err());
+
+        let multihash = hex::encode(result.to_multihash().unwrap());
+        for expected in [cid.as_str(), &multihash] {
+            let decoded = decode_content_address(expected).unwrap();
+            assert_eq!(decoded.algorithm, HashAlgorithm::Sha256);
+            assert_eq!(decoded.bytes, result.bytes);
+        }
+        assert!(decode_content_address(&result.to_hex()).is_none());
+    }
+}
@@ -0,0 +1,265 @@
+// Opt-in text normalization, so that the same text hashes the same on every platform
+use crate::{
+    algorithms::HashAlgorithm, core::HashForge, decompress::Compression, output::HashResult,
+};
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::{fmt, fs, io::Read, path::Path};
+use unicode_normalization::UnicodeNormalization;
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Line break written by `--line-endings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// "\n", as on Linux and macOS
+    Lf,
+    /// "\r\n", as on Windows
+    Crlf,
+}
+
+impl LineEnding {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+}
+
+/// Unicode normalization form applied by `--unicode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeForm {
+    /// Canonical composition ("é" as one code point)
+    Nfc,
+    /// Canonical decomposition ("é" as "e" and a combining accent)
+    Nfd,
+    /// Compatibility composition (also folds ligatures, full-width forms, ...)
+    Nfkc,
+    /// Compatibility decomposition
+    Nfkd,
+}
+
+/// Changes made to text before it is hashed; all off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Args, Serialize)]
+pub struct Normalization {
+    /// Convert every line break (CRLF, CR or LF) to this style
+    #[arg(long, value_name = "STYLE")]
+    pub line_endings: Option<LineEnding>,
+
+    /// Remove a UTF-8 byte order mark at the start
+    #[arg(long)]
+    pub strip_bom: bool,
+
+    /// Remove spaces and tabs at the end of every line
+    #[arg(long)]
+    pub trim_trailing_whitespace: bool,
+
+    /// Remove line breaks at the end of the text
+    #[arg(long)]
+    pub trim_trailing_newlines: bool,
+
+    /// Normalize Unicode to this form; the input must be UTF-8
+    #[arg(long, value_name = "FORM")]
+    pub unicode: Option<UnicodeForm>,
+}
+
+impl Normalization {
+    /// Whether any option is set
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// Apply the selected changes to `data`.
+    ///
+    /// The BOM is removed first and Unicode normalized next, then lines are
+    /// processed, so each option sees the result of the ones before it.
+    pub fn apply(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut data = data;
+        if self.strip_bom {
+            data = data.strip_prefix(BOM).unwrap_or(data);
+        }
+
+        let normalized;
+        if let Some(form) = self.unicode {
+            let text =
+                std::str::from_utf8(data).context("Unicode normalization needs UTF-8 input")?;
+            normalized = match form {
+                UnicodeForm::Nfc => text.nfc().collect::<String>(),
+                UnicodeForm::Nfd => text.nfd().collect(),
+                UnicodeForm::Nfkc => text.nfkc().collect(),
+                UnicodeForm::Nfkd => text.nfkd().collect(),
+            };
+            data = normalized.as_bytes();
+        }
+
+        if self.line_endings.is_none() && !self.trim_trailing_whitespace {
+            let mut output = data.to_vec();
+            if self.trim_trailing_newlines {
+                output.truncate(trimmed_len(&output, b"\r\n"));
+            }
+            return Ok(output);
+        }
+
+        let mut output = Vec::with_capacity(data.len());
+        let mut rest = data;
+        while !rest.is_empty() {
+            let end = rest
+                .iter()
+                .position(|&b| b == b'\n' || b == b'\r')
+                .unwrap_or(rest.len());
+            let break_len = match rest.get(end..) {
+                Some([b'\r', b'\n', ..]) => 2,
+                Some([_, ..]) => 1,
+                _ => 0,
+            };
+            let line = &rest[..end];
+            if self.trim_trailing_whitespace {
+                output.extend_from_slice(&line[..trimmed_len(line, b" \t")]);
+            } else {
+                output.extend_from_slice(line);
+            }
+            let line_break = &rest[end..end + break_len];
+            match self.line_endings {
+                Some(style) if break_len > 0 => output.extend_from_slice(style.as_bytes()),
+                _ => output.extend_from_slice(line_break),
+            }
+            rest = &rest[end + break_len..];
+        }
+        if self.trim_trailing_newlines {
+            output.truncate(trimmed_len(&output, b"\r\n"));
+        }
+        Ok(output)
+    }
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut steps = Vec::new();
+        if self.strip_bom {
+            steps.push("strip BOM".to_string());
+        }
+        if let Some(form) = self.unicode {
+            steps.push(format!(
+                "Unicode {}",
+                form.to_possible_value().unwrap().get_name().to_uppercase()
+            ));
+        }
+        if self.trim_trailing_whitespace {
+            steps.push("trim trailing whitespace".to_string());
+        }
+        if let Some(style) = self.line_endings {
+            steps.push(format!(
+                "{} line endings",
+                style.to_possible_value().unwrap().get_name().to_uppercase()
+            ));
+        }
+        if self.trim_trailing_newlines {
+            steps.push("trim trailing newlines".to_string());
+        }
+        write!(f, "{}", steps.join(", "))
+    }
+}
+
+/// Length of `data` without trailing bytes from `trim`
+fn trimmed_len(data: &[u8], trim: &[u8]) -> usize {
+    data.iter()
+        .rposition(|b| !trim.contains(b))
+        .map_or(0, |last| last + 1)
+}
+
+impl HashForge {
+    /// Hash the contents of `path` after applying `normalization`, undoing
+    /// `compression` first if given.
+    ///
+    /// The file is read into memory, and the digest cache is not used.
+    pub fn hash_file_normalized(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        compression: Option<Compression>,
+        normalization: &Normalization,
+    ) -> Result<HashResult> {
+        Ok(self
+            .hash_file_normalized_many(path, &[algorithm], compression, normalization)?
+            .remove(0))
+    }
+
+    /// Like [`HashForge::hash_file_normalized`], with each of `algorithms`
+    /// over a single read of the file
+    pub fn hash_file_normalized_many(
+        &self,
+        path: &Path,
+        algorithms: &[HashAlgorithm],
+        compression: Option<Compression>,
+        normalization: &Normalization,
+    ) -> Result<Vec<HashResult>> {
+        let file = fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let mut reader: Box<dyn Read> = match compression {
+            Some(compression) => compression.decoder(file)?,
+            None => Box::new(file),
+        };
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+
+        let normalized = normalization
+            .apply(&data)
+            .with_context(|| format!("Failed to normalize {}", path.display()))?;
+        algorithms
+            .iter()
+            .map(|&algorithm| self.hash_bytes(&normalized, algorithm))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization() {
+        let windows = b"\xef\xbb\xbfline one  \r\nline two\t\r\n\r\n";
+        let unix = b"line one\nline two";
+        let options = Normalization {
+            line_endings: Some(LineEnding::Lf),
+            strip_bom: true,
+            trim_trailing_whitespace: true,
+            trim_trailing_newlines: true,
+            unicode: None,
+        };
+        assert!(options.is_enabled());
+        assert!(!Normalization::default().is_enabled());
+        assert_eq!(options.apply(windows).unwrap(), unix);
+        assert_eq!(options.apply(unix).unwrap(), unix);
+
+        let crlf = Normalization {
+            line_endings: Some(LineEnding::Crlf),
+            ..Default::default()
+        };
+        assert_eq!(crlf.apply(b"a\nb\rc\r\nd").unwrap(), b"a\r\nb\r\nc\r\nd");
+
+        let newlines = Normalization {
+            trim_trailing_newlines: true,
+            ..Default::default()
+        };
+        assert_eq!(newlines.apply(b"text \n\r\n").unwrap(), b"text ");
+
+        let nfc = Normalization {
+            unicode: Some(UnicodeForm::Nfc),
+            ..Default::default()
+        };
+        assert_eq!(nfc.apply("e\u{301}".as_bytes()).unwrap(), "é".as_bytes());
+        let nfkc = Normalization {
+            unicode: Some(UnicodeForm::Nfkc),
+            ..Default::default()
+        };
+        assert_eq!(nfkc.apply("ﬁ".as_bytes()).unwrap(), b"fi");
+        assert!(nfc.apply(b"\xff").is_err());
+    }
+}
@@ -1,20 +1,121 @@
+use crate::{algorithms::HashAlgorithm, sri::SRI_ALGORITHMS};
 use clap::ValueEnum;
-use std::fmt;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::{
+    fmt,
+    io::{self, Write},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
 pub enum OutputFormat {
+    /// Lowercase hexadecimal
     #[default]
     #[value(name = "hex")]
     Hex,
+    /// Uppercase hexadecimal
+    #[value(name = "hex-upper")]
+    HexUpper,
+    /// Standard base64 with padding
     #[value(name = "base64")]
     Base64,
+    /// URL-safe base64 without padding (JWT, webhooks)
+    #[value(name = "base64url")]
+    Base64Url,
+    /// RFC 4648 base32 with padding (TOTP secrets)
+    #[value(name = "base32")]
+    Base32,
+    /// Base58 with the Bitcoin alphabet (base58btc)
+    #[value(name = "base58")]
+    Base58,
+    /// Raw digest bytes, for piping into other tools
+    #[value(name = "raw")]
+    Raw,
+}
+
+impl OutputFormat {
+    /// Whether the format produces bytes rather than text
+    pub fn is_binary(self) -> bool {
+        self == OutputFormat::Raw
+    }
+
+    /// The format actually used where only text can be stored (JSON, CSV, GUI).
+    /// Raw bytes fall back to hex.
+    pub fn text_form(self) -> OutputFormat {
+        if self.is_binary() {
+            OutputFormat::Hex
+        } else {
+            self
+        }
+    }
 }
 
 impl fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             OutputFormat::Hex => write!(f, "hex"),
+            OutputFormat::HexUpper => write!(f, "hex-upper"),
             OutputFormat::Base64 => write!(f, "base64"),
+            OutputFormat::Base64Url => write!(f, "base64url"),
+            OutputFormat::Base32 => write!(f, "base32"),
+            OutputFormat::Base58 => write!(f, "base58"),
+            OutputFormat::Raw => write!(f, "raw"),
+        }
+    }
+}
+
+impl Serialize for OutputFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// How command results are reported on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum OutputMode {
+    /// Human-readable text
+    #[default]
+    #[value(name = "human")]
+    Human,
+    /// One JSON document per command (see docs-dev/JSON_OUTPUT.md)
+    #[value(name = "json")]
+    Json,
+}
+
+/// How a digest is presented, independently of its [`OutputFormat`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DigestForm {
+    /// The bare digest
+    #[default]
+    Plain,
+    /// `algorithm:digest`
+    Tagged,
+    /// Multihash bytes (code, length, digest) in the output format
+    Multihash,
+    /// CIDv1 for raw content; always base32 multibase
+    Cid,
+}
+
+/// Record format for `batch` results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum BatchFormat {
+    /// `digest path` lines
+    #[default]
+    #[value(name = "text")]
+    Text,
+    /// Comma-separated values with a header row
+    #[value(name = "csv")]
+    Csv,
+    /// One JSON object per line
+    #[value(name = "ndjson")]
+    Ndjson,
+}
+
+impl fmt::Display for BatchFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchFormat::Text => write!(f, "text"),
+            BatchFormat::Csv => write!(f, "csv"),
+            BatchFormat::Ndjson => write!(f, "ndjson"),
         }
     }
 }
@@ -23,11 +124,11 @@ impl fmt::Display for OutputFormat {
 #[derive(Debug, Clone)]
 pub struct HashResult {
     pub bytes: Vec<u8>,
-    pub algorithm: crate::algorithms::HashAlgorithm,
+    pub algorithm: HashAlgorithm,
 }
 
 impl HashResult {
-    pub fn new(bytes: Vec<u8>, algorithm: crate::algorithms::HashAlgorithm) -> Self {
+    pub fn new(bytes: Vec<u8>, algorithm: HashAlgorithm) -> Self {
         Self { bytes, algorithm }
     }
 
@@ -36,12 +137,102 @@ impl HashResult {
         hex::encode(&self.bytes)
     }
 
+    /// Convert hash to uppercase hexadecimal string
+    pub fn to_hex_upper(&self) -> String {
+        hex::encode_upper(&self.bytes)
+    }
+
     /// Convert hash to base64 string
     pub fn to_base64(&self) -> String {
         use base64::Engine;
         base64::engine::general_purpose::STANDARD.encode(&self.bytes)
     }
 
+    /// Convert hash to unpadded URL-safe base64 string
+    pub fn to_base64url(&self) -> String {
+        use base64::Engine;
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&self.bytes)
+    }
+
+    /// Convert hash to padded RFC 4648 base32 string
+    pub fn to_base32(&self) -> String {
+        data_encoding::BASE32.encode(&self.bytes)
+    }
+
+    /// Convert hash to base58btc string
+    pub fn to_base58(&self) -> String {
+        bs58::encode(&self.bytes).into_string()
+    }
+
+    /// Encode the hash in the given output format.
+    ///
+    /// [`OutputFormat::Raw`] has no text form and is rendered as hex; use
+    /// [`HashResult::write_encoded`] to emit the bytes themselves.
+    pub fn encode(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Hex | OutputFormat::Raw => self.to_hex(),
+            OutputFormat::HexUpper => self.to_hex_upper(),
+            OutputFormat::Base64 => self.to_base64(),
+            OutputFormat::Base64Url => self.to_base64url(),
+            OutputFormat::Base32 => self.to_base32(),
+            OutputFormat::Base58 => self.to_base58(),
+        }
+    }
+
+    /// Format as an OCI-style tagged digest, e.g. `sha256:2cf24d...`
+    pub fn to_tagged(&self, format: OutputFormat) -> String {
+        format!("{}:{}", self.algorithm.name(), self.encode(format))
+    }
+
+    /// Format as a Subresource Integrity value, e.g. `sha512-z4PhNX...`
+    pub fn to_sri(&self) -> String {
+        format!("{}-{}", self.algorithm.name(), self.to_base64())
+    }
+
+    /// Parse a tagged digest (`algorithm:encoded` or SRI `algorithm-base64`).
+    ///
+    /// The digest may use any text encoding accepted by
+    /// [`decode_expected_hash`]; when several interpretations decode, the one
+    /// with the algorithm's digest length is chosen.
+    pub fn parse_tagged(tagged: &str) -> anyhow::Result<Self> {
+        let (Some(algorithm), encoded) = split_algorithm_tag(tagged) else {
+            anyhow::bail!("Digest has no algorithm tag: {tagged}");
+        };
+        let decoded = decode_expected_hash(encoded);
+        let bytes = decoded
+            .iter()
+            .find(|bytes| bytes.len() == algorithm.output_size())
+            .or_else(|| decoded.first().filter(|_| algorithm.is_extendable()))
+            .ok_or_else(|| anyhow::anyhow!("Invalid {} digest: {encoded}", algorithm.name()))?;
+        Ok(Self::new(bytes.clone(), algorithm))
+    }
+
+    /// Render the digest in `form` as text.
+    ///
+    /// Fails for multihash and CID forms when the algorithm has no multicodec
+    /// code.
+    pub fn render(&self, form: DigestForm, format: OutputFormat) -> anyhow::Result<String> {
+        Ok(match form {
+            DigestForm::Plain => self.encode(format),
+            DigestForm::Tagged => self.to_tagged(format),
+            DigestForm::Multihash => {
+                HashResult::new(self.to_multihash()?, self.algorithm).encode(format)
+            }
+            DigestForm::Cid => self.to_cid()?,
+        })
+    }
+
+    /// Write the encoded hash to `writer`: raw bytes as-is, text formats
+    /// followed by a newline
+    pub fn write_encoded<W: Write>(&self, format: OutputFormat, mut writer: W) -> io::Result<()> {
+        if format.is_binary() {
+            writer.write_all(&self.bytes)?;
+        } else {
+            writeln!(writer, "{}", self.encode(format))?;
+        }
+        writer.flush()
+    }
+
     /// Get raw bytes
     pub fn to_bytes(&self) -> &[u8] {
         &self.bytes
@@ -61,8 +252,131 @@ impl HashResult {
     }
 }
 
+impl Serialize for HashResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HashResult", 3)?;
+        state.serialize_field("algorithm", &self.algorithm)?;
+        state.serialize_field("digest", &self.to_hex())?;
+        state.serialize_field("length", &self.bytes.len())?;
+        state.end()
+    }
+}
+
 impl fmt::Display for HashResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.to_hex())
     }
 }
+
+/// Split an optional algorithm tag from an expected hash.
+///
+/// Accepts the OCI-style `algorithm:` prefix for any algorithm (`sha256:...`,
+/// `blake3:...`, `sha3-256:...`) and the SRI-style `algorithm-` prefix for the
+/// algorithms SRI defines (`sha384-...`).
+pub fn split_algorithm_tag(expected: &str) -> (Option<HashAlgorithm>, &str) {
+    let expected = expected.trim();
+    if let Some((tag, rest)) = expected.split_once(':') {
+        if let Ok(algorithm) = HashAlgorithm::from_str(tag, true) {
+            return (Some(algorithm), rest);
+        }
+    }
+    if let Some((tag, rest)) = expected.split_once('-') {
+        if let Some(&algorithm) = SRI_ALGORITHMS.iter().find(|alg| alg.name() == tag) {
+            return (Some(algorithm), rest);
+        }
+    }
+    (None, expected)
+}
+
+/// Decode an expected hash string into every byte sequence it could represent.
+///
+/// A string such as `deadbeef` is valid as hex, base64 and base58 alike, so
+/// all successful interpretations are returned, in order: hex (either case),
+/// base64, base64url, base32 and base58.
+pub fn decode_expected_hash(expected: &str) -> Vec<Vec<u8>> {
+    use base64::{
+        engine::{general_purpose, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+        Engine,
+    };
+
+    // Accept base64url with or without padding
+    const URL_SAFE_ANY_PAD: GeneralPurpose = GeneralPurpose::new(
+        &base64::alphabet::URL_SAFE,
+        GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+    );
+
+    let expected = expected.trim();
+    let base32 = expected.to_ascii_uppercase();
+    let attempts = [
+        hex::decode(expected).ok(),
+        general_purpose::STANDARD.decode(expected).ok(),
+        URL_SAFE_ANY_PAD.decode(expected).ok(),
+        data_encoding::BASE32.decode(base32.as_bytes()).ok(),
+        data_encoding::BASE32_NOPAD.decode(base32.as_bytes()).ok(),
+        bs58::decode(expected).into_vec().ok(),
+    ];
+
+    let mut decoded = Vec::new();
+    for bytes in attempts.into_iter().flatten() {
+        if !bytes.is_empty() && !decoded.contains(&bytes) {
+            decoded.push(bytes);
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encodings() {
+        let result = HashResult::new(vec![0x00, 0xfb, 0xff, 0x10], HashAlgorithm::Sha256);
+        assert_eq!(result.encode(OutputFormat::Hex), "00fbff10");
+        assert_eq!(result.encode(OutputFormat::HexUpper), "00FBFF10");
+        assert_eq!(result.encode(OutputFormat::Base64), "APv/EA==");
+        assert_eq!(result.encode(OutputFormat::Base64Url), "APv_EA");
+        assert_eq!(result.encode(OutputFormat::Base32), "AD576EA=");
+        assert_eq!(result.encode(OutputFormat::Base58), "12TeHV");
+        assert_eq!(result.encode(OutputFormat::Raw), "00fbff10");
+    }
+
+    #[test]
+    fn test_decode_expected_hash() {
+        let bytes = vec![0x00, 0xfb, 0xff, 0x10];
+        for encoded in [
+            "00FBFF10", "APv/EA==", "APv_EA", "AD576EA=", "ad576ea", "12TeHV",
+        ] {
+            assert!(
+                decode_expected_hash(encoded).contains(&bytes),
+                "{encoded} should decode"
+            );
+        }
+        assert!(decode_expected_hash("not a hash!").is_empty());
+    }
+
+    #[test]
+    fn test_tagged_digests() {
+        let result = HashResult::new(vec![0xab; 32], HashAlgorithm::Sha256);
+        let tagged = result.to_tagged(OutputFormat::Hex);
+        assert_eq!(tagged, format!("sha256:{}", "ab".repeat(32)));
+        assert_eq!(
+            HashResult::parse_tagged(&tagged).unwrap().bytes,
+            result.bytes
+        );
+
+        let sri = result.to_sri();
+        assert!(sri.starts_with("sha256-q6ur"));
+        let parsed = HashResult::parse_tagged(&sri).unwrap();
+        assert_eq!(parsed.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(parsed.bytes, result.bytes);
+
+        let (tag, rest) = split_algorithm_tag("sha3-256:abcd");
+        assert_eq!(tag, Some(HashAlgorithm::Sha3_256));
+        assert_eq!(rest, "abcd");
+        // Only SRI algorithms take the dash form
+        assert_eq!(split_algorithm_tag("blake3-abcd").0, None);
+        assert!(HashResult::parse_tagged("abcd").is_err());
+        assert!(HashResult::parse_tagged("sha256:abcd").is_err());
+    }
+}
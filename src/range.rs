@@ -0,0 +1,147 @@
+// Hashing a byte range of a file in place
+use crate::{algorithms::HashAlgorithm, core::HashForge, output::HashResult};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    io::{BufReader, Read, Seek, SeekFrom, Take},
+    path::Path,
+};
+
+/// Bytes `offset..offset + length` of a file; without a length, the range
+/// runs to the end of the file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileRange {
+    pub offset: u64,
+    pub length: Option<u64>,
+}
+
+impl FileRange {
+    /// The range selected by `--offset` and `--length`, or `None` for the
+    /// whole file
+    pub fn from_options(offset: Option<u64>, length: Option<u64>) -> Option<Self> {
+        if offset.is_none() && length.is_none() {
+            return None;
+        }
+        Some(Self {
+            offset: offset.unwrap_or(0),
+            length,
+        })
+    }
+
+    /// Length of the range within a file of `file_size` bytes, or an error if
+    /// the range does not fit
+    pub fn resolve(&self, file_size: u64) -> Result<u64> {
+        if self.offset > file_size {
+            anyhow::bail!(
+                "Offset {} is past the end of the file ({file_size} bytes)",
+                self.offset
+            );
+        }
+        let available = file_size - self.offset;
+        match self.length {
+            Some(length) if length > available => anyhow::bail!(
+                "Range of {length} bytes at offset {} runs past the end of the file ({file_size} bytes)",
+                self.offset
+            ),
+            Some(length) => Ok(length),
+            None => Ok(available),
+        }
+    }
+}
+
+/// Open `path` positioned at the start of `range`.
+///
+/// Returns a reader that stops at the end of the range, and the range's
+/// length.
+pub fn open_range(path: &Path, range: &FileRange) -> Result<(Take<BufReader<fs::File>>, u64)> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open file: {}", path.display()))?;
+    let length = range
+        .resolve(file.metadata()?.len())
+        .with_context(|| format!("Invalid range for {}", path.display()))?;
+    file.seek(SeekFrom::Start(range.offset))?;
+    Ok((BufReader::new(file).take(length), length))
+}
+
+impl HashForge {
+    /// Hash bytes `range` of `path`, reading only that range.
+    ///
+    /// The digest cache is not used: it holds digests of whole files.
+    pub fn hash_file_range(
+        &self,
+        path: &Path,
+        algorithm: HashAlgorithm,
+        range: &FileRange,
+    ) -> Result<HashResult> {
+        Ok(self
+            .hash_file_range_many(path, &[algorithm], range)?
+            .remove(0))
+    }
+
+    /// Like [`HashForge::hash_file_range`], with each of `algorithms` in a
+    /// single read of the range
+    pub fn hash_file_range_many(
+        &self,
+        path: &Path,
+        algorithms: &[HashAlgorithm],
+        range: &FileRange,
+    ) -> Result<Vec<HashResult>> {
+        let (mut reader, length) = open_range(path, range)?;
+        let results = self.hash_reader_many(&mut reader, length, algorithms)?;
+        if reader.limit() != 0 {
+            anyhow::bail!("{} shrank while being read", path.display());
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_file_range() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("data");
+        fs::write(&path, "header:hello:trailer").unwrap();
+        let forge = HashForge::new().with_progress(false);
+
+        let range = FileRange {
+            offset: 7,
+            length: Some(5),
+        };
+        let expected = forge.hash_bytes(b"hello", HashAlgorithm::Sha256).unwrap();
+        let result = forge
+            .hash_file_range(&path, HashAlgorithm::Sha256, &range)
+            .unwrap();
+        assert_eq!(result.bytes, expected.bytes);
+
+        let tail = FileRange {
+            offset: 13,
+            length: None,
+        };
+        assert_eq!(tail.resolve(20).unwrap(), 7);
+        assert_eq!(
+            FileRange {
+                offset: 20,
+                length: Some(0)
+            }
+            .resolve(20)
+            .unwrap(),
+            0
+        );
+        assert!(FileRange {
+            offset: 21,
+            length: None
+        }
+        .resolve(20)
+        .is_err());
+        assert!(FileRange {
+            offset: 16,
+            length: Some(5)
+        }
+        .resolve(20)
+        .is_err());
+        assert!(FileRange::from_options(None, None).is_none());
+    }
+}
@@ -0,0 +1,613 @@
+// Structured command reports for `--output json`
+//
+// Every command emits exactly one of these documents. The schema is described
+// in docs-dev/JSON_OUTPUT.md; bump SCHEMA_VERSION on incompatible changes.
+use crate::{
+    algorithms::HashAlgorithm,
+    archive::ArchiveFormat,
+    blocks::ByteRange,
+    checksum::{CheckEvent, CheckSummary, ManifestFormat},
+    chunks::ChunkedFile,
+    decompress::Compression,
+    diff::{SnapshotDiff, SnapshotSource},
+    dupes::{DuplicateSet, FileError},
+    input::InputEncoding,
+    normalize::Normalization,
+    output::{HashResult, OutputFormat},
+    range::FileRange,
+    sri::AssetResult,
+    utils::format_timestamp,
+    walk::{SymlinkPolicy, WalkEntry},
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::{path::PathBuf, time::Duration};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Description of what was hashed
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputDescriptor {
+    Text {
+        value: String,
+        /// How `value` was turned into the hashed bytes
+        encoding: InputEncoding,
+        /// Number of hashed bytes
+        size: u64,
+    },
+    File {
+        path: PathBuf,
+        size: Option<u64>,
+        /// Part of the file that was hashed, or `null` for all of it
+        range: Option<RangeDescriptor>,
+        /// Compression undone before hashing, or `null` if the file was hashed as stored
+        decompressed: Option<Compression>,
+    },
+}
+
+/// Byte range of a file given by `--offset` and `--length`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct RangeDescriptor {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl InputDescriptor {
+    pub fn text(value: &str) -> Self {
+        Self::encoded_text(value, InputEncoding::Utf8, value.len())
+    }
+
+    /// Text given in `encoding`, standing for `size` bytes
+    pub fn encoded_text(value: &str, encoding: InputEncoding, size: usize) -> Self {
+        InputDescriptor::Text {
+            value: value.to_string(),
+            encoding,
+            size: size as u64,
+        }
+    }
+
+    pub fn file(path: &std::path::Path) -> Self {
+        Self::file_range(path, None)
+    }
+
+    /// A file, or the part of it selected by `range`
+    pub fn file_range(path: &std::path::Path, range: Option<FileRange>) -> Self {
+        let size = std::fs::metadata(path).ok().map(|m| m.len());
+        InputDescriptor::File {
+            path: path.to_path_buf(),
+            size,
+            range: range.and_then(|range| {
+                Some(RangeDescriptor {
+                    offset: range.offset,
+                    length: range.resolve(size?).ok()?,
+                })
+            }),
+            decompressed: None,
+        }
+    }
+
+    /// Record that the contents were hashed after undoing `compression`
+    pub fn with_decompressed(mut self, compression: Option<Compression>) -> Self {
+        if let InputDescriptor::File {
+            ref mut decompressed,
+            ..
+        } = self
+        {
+            *decompressed = compression;
+        }
+        self
+    }
+}
+
+/// Result of `text`, `file` and `hmac`
+#[derive(Debug, Clone, Serialize)]
+pub struct HashReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub input: InputDescriptor,
+    pub algorithm: HashAlgorithm,
+    pub encoding: OutputFormat,
+    pub digest: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<u32>,
+    /// Normalization applied to the input before hashing, when enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalization: Option<Normalization>,
+    pub elapsed_ms: f64,
+}
+
+impl HashReport {
+    pub fn new(
+        command: &'static str,
+        input: InputDescriptor,
+        result: &HashResult,
+        encoding: OutputFormat,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            command,
+            input,
+            algorithm: result.algorithm,
+            encoding: encoding.text_form(),
+            digest: result.encode(encoding),
+            salt: None,
+            iterations: None,
+            normalization: None,
+            elapsed_ms: millis(elapsed),
+        }
+    }
+}
+
+/// Outcome of a verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Pass,
+    Fail,
+    Ambiguous,
+}
+
+/// Result of `verify` and `verify-hmac`
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub input: InputDescriptor,
+    /// Algorithm used, or `null` when detection found no single match
+    pub algorithm: Option<HashAlgorithm>,
+    /// Whether the algorithm was detected automatically
+    pub detected: bool,
+    /// Algorithms tried (auto detection) or found matching (ambiguous)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<HashAlgorithm>,
+    pub expected: String,
+    /// Computed digest in hex, when a single algorithm was used
+    pub computed: Option<String>,
+    pub verdict: Verdict,
+    /// Normalization applied to the input before hashing, when enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalization: Option<Normalization>,
+    pub elapsed_ms: f64,
+}
+
+/// One file processed by a directory operation.
+///
+/// This is also the row format of `batch --format csv|ndjson`, so the fields
+/// stay flat and in column order.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileRecord {
+    pub path: String,
+    pub size: Option<u64>,
+    /// Modification time, RFC 3339 in UTC
+    pub mtime: Option<String>,
+    pub algorithm: HashAlgorithm,
+    pub digest: Option<String>,
+    pub error: Option<String>,
+}
+
+impl FileRecord {
+    pub fn new(
+        entry: &WalkEntry,
+        algorithm: HashAlgorithm,
+        result: &Result<HashResult>,
+        encoding: OutputFormat,
+    ) -> Self {
+        let (digest, error) = match result {
+            Ok(hash) => (Some(hash.encode(encoding)), None),
+            Err(e) => (None, Some(format!("{e:#}"))),
+        };
+        Self {
+            path: entry.display_path(),
+            size: entry.size(),
+            mtime: entry.modified().map(format_timestamp),
+            algorithm,
+            digest,
+            error,
+        }
+    }
+}
+
+/// Result of `batch`
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub directory: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub encoding: OutputFormat,
+    pub files: Vec<FileRecord>,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `manifest`
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub directory: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub format: ManifestFormat,
+    pub output_file: Option<PathBuf>,
+    pub files: Vec<FileRecord>,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `diff`
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub old: SnapshotSource,
+    pub new: SnapshotSource,
+    pub algorithm: HashAlgorithm,
+    #[serde(flatten)]
+    pub diff: SnapshotDiff,
+    pub identical: bool,
+    pub elapsed_ms: f64,
+}
+
+/// One set of identical files in a `dupes` report
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateRecord {
+    pub size: u64,
+    pub digest: String,
+    pub wasted: u64,
+    /// File kept by `--hardlink`
+    pub keep: String,
+    /// Files `--hardlink` replaces with links to `keep`
+    pub duplicates: Vec<String>,
+}
+
+impl DuplicateRecord {
+    pub fn new(set: &DuplicateSet) -> Self {
+        let mut paths = set.files.iter().map(WalkEntry::display_path);
+        Self {
+            size: set.size,
+            digest: set.digest.to_hex(),
+            wasted: set.wasted(),
+            keep: paths.next().unwrap_or_default(),
+            duplicates: paths.collect(),
+        }
+    }
+}
+
+/// Result of `dupes`
+#[derive(Debug, Clone, Serialize)]
+pub struct DupesReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub directory: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub scanned: usize,
+    pub hashed: usize,
+    pub wasted: u64,
+    pub sets: Vec<DuplicateRecord>,
+    pub skipped: Vec<FileError>,
+    pub hardlinked: bool,
+    /// Duplicates `--hardlink` could not replace
+    pub link_errors: Vec<FileError>,
+    pub elapsed_ms: f64,
+}
+
+/// One line of `watch` output in JSON mode
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchRecord {
+    pub time: String,
+    pub path: String,
+    /// `hashed`, `removed` or `failed`
+    pub event: &'static str,
+    pub initial: bool,
+    pub algorithm: HashAlgorithm,
+    pub digest: Option<String>,
+    /// Result against `--manifest`: `ok`, `failed`, `missing` or `unlisted`
+    pub verdict: Option<&'static str>,
+    pub error: Option<String>,
+}
+
+/// Result of `cache info`, `cache prune` and `cache clear`
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub action: &'static str,
+    pub path: PathBuf,
+    /// Entries left in the cache
+    pub entries: usize,
+    /// Entries removed by `prune` or `clear`
+    pub removed: Option<usize>,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `tree`
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub directory: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub encoding: OutputFormat,
+    pub digest: String,
+    pub modes: bool,
+    pub empty_dirs: bool,
+    pub symlinks: SymlinkPolicy,
+    pub files: usize,
+    pub directories: usize,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `blocks create`
+#[derive(Debug, Clone, Serialize)]
+pub struct BlocksReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub action: &'static str,
+    pub file: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub block_size: u64,
+    pub file_size: u64,
+    /// Hex digest of the concatenated block digests
+    pub top: String,
+    /// Hex digest of each block, in file order
+    pub blocks: Vec<String>,
+    /// Where the block list was written, if not only in this report
+    pub output_file: Option<PathBuf>,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `blocks compare`
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockCompareReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub action: &'static str,
+    pub file: PathBuf,
+    pub list: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub block_size: u64,
+    pub file_size: u64,
+    pub expected_file_size: u64,
+    pub top: String,
+    pub expected_top: String,
+    /// Blocks compared (the larger of the two block counts)
+    pub blocks: u64,
+    pub differing_blocks: u64,
+    pub ranges: Vec<ByteRange>,
+    pub verdict: Verdict,
+    pub elapsed_ms: f64,
+}
+
+/// One chunk listed by `chunks --list`
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkRecord {
+    pub offset: u64,
+    pub length: u64,
+    pub digest: String,
+}
+
+/// One file analyzed by `chunks`
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkedFileRecord {
+    pub path: PathBuf,
+    pub size: u64,
+    pub chunk_count: usize,
+    /// Every chunk, with `--list` only
+    pub chunks: Option<Vec<ChunkRecord>>,
+}
+
+impl ChunkedFileRecord {
+    pub fn new(file: &ChunkedFile, list: bool) -> Self {
+        Self {
+            path: file.path.clone(),
+            size: file.size,
+            chunk_count: file.chunks.len(),
+            chunks: list.then(|| {
+                file.chunks
+                    .iter()
+                    .map(|chunk| ChunkRecord {
+                        offset: chunk.offset,
+                        length: chunk.length,
+                        digest: hex::encode(&chunk.digest),
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Result of `chunks`
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunksReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub algorithm: HashAlgorithm,
+    pub min_size: u64,
+    pub avg_size: u64,
+    pub max_size: u64,
+    pub files: Vec<ChunkedFileRecord>,
+    pub skipped: Vec<FileError>,
+    pub total_bytes: u64,
+    pub total_chunks: usize,
+    pub unique_bytes: u64,
+    pub unique_chunks: usize,
+    pub dedup_ratio: f64,
+    /// Fraction of `total_bytes` that deduplication would not store
+    pub savings: f64,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `signature`
+#[derive(Debug, Clone, Serialize)]
+pub struct SignatureReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub file: PathBuf,
+    pub signature: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub block_size: u32,
+    pub file_size: u64,
+    pub blocks: usize,
+    /// Size of the signature file in bytes
+    pub signature_size: u64,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `delta`
+#[derive(Debug, Clone, Serialize)]
+pub struct DeltaReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub signature: PathBuf,
+    pub file: PathBuf,
+    pub delta: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub block_size: u32,
+    pub file_size: u64,
+    pub copied_bytes: u64,
+    pub literal_bytes: u64,
+    pub copy_commands: usize,
+    pub literal_commands: usize,
+    /// Size of the delta file in bytes
+    pub delta_size: u64,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `patch`
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub basis: PathBuf,
+    pub delta: PathBuf,
+    pub output: PathBuf,
+    pub algorithm: HashAlgorithm,
+    pub size: u64,
+    /// Hex digest of the result, equal to the one recorded in the delta
+    pub digest: String,
+    pub elapsed_ms: f64,
+}
+
+/// A member hashed by `archive manifest`
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveMemberRecord {
+    pub path: String,
+    pub size: u64,
+    pub digest: String,
+}
+
+/// Result of `archive manifest`
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveManifestReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub action: &'static str,
+    pub archive: PathBuf,
+    pub archive_format: ArchiveFormat,
+    pub algorithm: HashAlgorithm,
+    pub format: ManifestFormat,
+    pub output_file: Option<PathBuf>,
+    pub members: Vec<ArchiveMemberRecord>,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `archive check`
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveCheckReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub action: &'static str,
+    pub archive: PathBuf,
+    pub archive_format: ArchiveFormat,
+    pub manifest: String,
+    pub results: Vec<CheckEvent>,
+    pub summary: CheckSummary,
+    /// Members the manifest does not list
+    pub unlisted: Vec<String>,
+    pub verdict: Verdict,
+    pub elapsed_ms: f64,
+}
+
+/// Results for one manifest read by `check`
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckedManifest {
+    pub source: String,
+    pub results: Vec<CheckEvent>,
+    pub summary: CheckSummary,
+    pub ok: bool,
+    /// Why the manifest could not be read, in which case it has no results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of `check`
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub manifests: Vec<CheckedManifest>,
+    pub verdict: Verdict,
+    pub elapsed_ms: f64,
+}
+
+/// Integrity value generated for one file by `sri generate`
+#[derive(Debug, Clone, Serialize)]
+pub struct SriRecord {
+    pub path: PathBuf,
+    pub integrity: String,
+}
+
+/// Result of `sri generate`
+#[derive(Debug, Clone, Serialize)]
+pub struct SriReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub action: &'static str,
+    pub files: Vec<SriRecord>,
+    pub elapsed_ms: f64,
+}
+
+/// Result of `sri html`
+#[derive(Debug, Clone, Serialize)]
+pub struct SriHtmlReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub action: &'static str,
+    pub html: PathBuf,
+    pub assets: Vec<AssetResult>,
+    /// Whether the HTML file was rewritten
+    pub rewritten: bool,
+    pub verdict: Verdict,
+    pub elapsed_ms: f64,
+}
+
+/// Emitted instead of a command report when a command fails
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub error: String,
+}
+
+impl ErrorReport {
+    pub fn new(command: &'static str, error: &anyhow::Error) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            command,
+            error: format!("{error:#}"),
+        }
+    }
+}
+
+/// Print a report as a single pretty-printed JSON document on stdout
+pub fn print_json<T: Serialize>(report: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(report)?);
+    Ok(())
+}
+
+/// Elapsed time in fractional milliseconds
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,574 @@
+// Subresource Integrity (https://www.w3.org/TR/SRI/) generation and checking
+use crate::{algorithms::HashAlgorithm, core::HashForge, output::HashResult};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Algorithms defined by SRI, weakest first
+pub const SRI_ALGORITHMS: [HashAlgorithm; 3] = [
+    HashAlgorithm::Sha256,
+    HashAlgorithm::Sha384,
+    HashAlgorithm::Sha512,
+];
+
+/// Algorithm used when none is requested, as recommended by the SRI spec
+pub const DEFAULT_SRI_ALGORITHM: HashAlgorithm = HashAlgorithm::Sha384;
+
+/// Reject algorithms that browsers do not accept in integrity metadata
+pub fn ensure_sri_algorithms(algorithms: &[HashAlgorithm]) -> Result<()> {
+    for algorithm in algorithms {
+        if !SRI_ALGORITHMS.contains(algorithm) {
+            anyhow::bail!(
+                "{} is not a Subresource Integrity algorithm (use sha256, sha384 or sha512)",
+                algorithm.name()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Parsed value of an `integrity` attribute
+#[derive(Debug, Clone, Default)]
+pub struct Integrity {
+    pub hashes: Vec<HashResult>,
+}
+
+impl Integrity {
+    /// Parse whitespace-separated `algorithm-base64[?options]` tokens.
+    ///
+    /// As in browsers, tokens with an unknown algorithm or a digest that is
+    /// not standard base64 are ignored rather than rejected.
+    pub fn parse(value: &str) -> Self {
+        use base64::Engine;
+
+        let hashes = value
+            .split_whitespace()
+            .filter_map(|token| {
+                let token = token.split_once('?').map_or(token, |(hash, _)| hash);
+                let (name, digest) = token.split_once('-')?;
+                let algorithm = *SRI_ALGORITHMS.iter().find(|alg| alg.name() == name)?;
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(digest)
+                    .ok()
+                    .filter(|bytes| bytes.len() == algorithm.output_size())?;
+                Some(HashResult::new(bytes, algorithm))
+            })
+            .collect();
+        Self { hashes }
+    }
+
+    /// The strongest algorithm present; only its digests are checked
+    pub fn strongest(&self) -> Option<HashAlgorithm> {
+        SRI_ALGORITHMS
+            .iter()
+            .rev()
+            .copied()
+            .find(|&alg| self.hashes.iter().any(|hash| hash.algorithm == alg))
+    }
+
+    /// Algorithms present, in order of first appearance
+    pub fn algorithms(&self) -> Vec<HashAlgorithm> {
+        let mut algorithms = Vec::new();
+        for hash in &self.hashes {
+            if !algorithms.contains(&hash.algorithm) {
+                algorithms.push(hash.algorithm);
+            }
+        }
+        algorithms
+    }
+
+    /// Whether `computed` (made with the strongest algorithm) matches any of
+    /// the digests listed for that algorithm
+    pub fn matches(&self, computed: &HashResult) -> bool {
+        self.hashes
+            .iter()
+            .filter(|hash| hash.algorithm == computed.algorithm)
+            .any(|hash| computed.constant_time_eq(&hash.bytes))
+    }
+}
+
+/// Join SRI strings into an integrity value (`sha384-... sha512-...`)
+pub fn format_integrity(hashes: &[HashResult]) -> String {
+    hashes
+        .iter()
+        .map(HashResult::to_sri)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Outcome of checking one file against integrity metadata
+#[derive(Debug, Clone)]
+pub struct IntegrityCheck {
+    /// Digest computed with the strongest algorithm in the metadata
+    pub computed: HashResult,
+    pub matched: bool,
+}
+
+/// What happened to one `<script>` or `<link>` integrity value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AssetStatus {
+    Ok,
+    Failed,
+    /// The value did not match and was rewritten
+    Updated,
+    /// The value names no SRI algorithm this tool can check
+    Invalid,
+    /// The asset is not a local file
+    Remote,
+    Unreadable {
+        error: String,
+    },
+}
+
+/// An integrity-protected asset referenced from an HTML file
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetResult {
+    pub line: usize,
+    pub tag: String,
+    pub url: String,
+    /// Local file the URL resolved to
+    pub path: Option<PathBuf>,
+    pub expected: String,
+    /// Integrity value computed for the local file
+    pub computed: Option<String>,
+    #[serde(flatten)]
+    pub status: AssetStatus,
+}
+
+impl AssetResult {
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self.status,
+            AssetStatus::Failed | AssetStatus::Invalid | AssetStatus::Unreadable { .. }
+        )
+    }
+}
+
+/// Result of scanning an HTML file
+#[derive(Debug, Clone)]
+pub struct HtmlIntegrity {
+    pub assets: Vec<AssetResult>,
+    /// Rewritten document, when rewriting was requested and something changed
+    pub rewritten: Option<String>,
+}
+
+impl HashForge {
+    /// Hash `path` with each of `algorithms`
+    pub fn sri_hashes(&self, path: &Path, algorithms: &[HashAlgorithm]) -> Result<Vec<HashResult>> {
+        ensure_sri_algorithms(algorithms)?;
+        algorithms
+            .iter()
+            .map(|&algorithm| self.hash_file(path, algorithm))
+            .collect()
+    }
+
+    /// Check `path` against integrity metadata, using its strongest algorithm
+    pub fn verify_integrity(&self, path: &Path, integrity: &Integrity) -> Result<IntegrityCheck> {
+        let algorithm = integrity
+            .strongest()
+            .context("Integrity value contains no sha256, sha384 or sha512 hash")?;
+        let computed = self.hash_file(path, algorithm)?;
+        Ok(IntegrityCheck {
+            matched: integrity.matches(&computed),
+            computed,
+        })
+    }
+
+    /// Check every `<script>` and `<link>` integrity value in an HTML file
+    /// against the local files they reference.
+    ///
+    /// URLs starting with `/` are resolved against `root` (default: the HTML
+    /// file's directory), other relative URLs against the HTML file's
+    /// directory. With `rewrite`, mismatching values are replaced by digests
+    /// made with the algorithms already in the value, and every value is
+    /// converted to `algorithms` when that is non-empty.
+    pub fn check_html_integrity(
+        &self,
+        html_path: &Path,
+        root: Option<&Path>,
+        rewrite: bool,
+        algorithms: &[HashAlgorithm],
+    ) -> Result<HtmlIntegrity> {
+        ensure_sri_algorithms(algorithms)?;
+        let html = std::fs::read_to_string(html_path)
+            .with_context(|| format!("Failed to read HTML file: {}", html_path.display()))?;
+        let base = html_path.parent().unwrap_or(Path::new(""));
+        let root = root.unwrap_or(base);
+
+        let mut assets = Vec::new();
+        let mut replacements = Vec::new();
+        for tag in find_integrity_tags(&html) {
+            let mut result = AssetResult {
+                line: tag.line,
+                tag: tag.name.clone(),
+                url: tag.url.clone(),
+                path: None,
+                expected: tag.integrity.clone(),
+                computed: None,
+                status: AssetStatus::Ok,
+            };
+
+            let Some(path) = local_asset_path(&tag.url, base, root) else {
+                result.status = AssetStatus::Remote;
+                assets.push(result);
+                continue;
+            };
+            result.path = Some(path.clone());
+
+            let integrity = Integrity::parse(&tag.integrity);
+            let outcome = match integrity.strongest() {
+                None if !rewrite => {
+                    result.status = AssetStatus::Invalid;
+                    assets.push(result);
+                    continue;
+                }
+                None => None,
+                Some(_) => match self.verify_integrity(&path, &integrity) {
+                    Ok(check) => Some(check),
+                    Err(e) => {
+                        result.status = AssetStatus::Unreadable {
+                            error: format!("{e:#}"),
+                        };
+                        assets.push(result);
+                        continue;
+                    }
+                },
+            };
+
+            let matched = outcome.as_ref().is_some_and(|check| check.matched);
+            // Explicitly requested algorithms replace matching values too
+            let convert = rewrite && !algorithms.is_empty() && integrity.algorithms() != algorithms;
+            let computed = match outcome {
+                Some(check) if !rewrite => check.computed.to_sri(),
+                // Report the value the file should have after rewriting
+                _ => {
+                    let wanted = if !algorithms.is_empty() {
+                        algorithms.to_vec()
+                    } else if integrity.hashes.is_empty() {
+                        vec![DEFAULT_SRI_ALGORITHM]
+                    } else {
+                        integrity.algorithms()
+                    };
+                    match self.sri_hashes(&path, &wanted) {
+                        Ok(hashes) => format_integrity(&hashes),
+                        Err(e) => {
+                            result.status = AssetStatus::Unreadable {
+                                error: format!("{e:#}"),
+                            };
+                            assets.push(result);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            result.status = match (matched && !convert, rewrite) {
+                (true, _) => AssetStatus::Ok,
+                (false, true) => {
+                    replacements.push((tag.value_range.clone(), tag.quoted, computed.clone()));
+                    AssetStatus::Updated
+                }
+                (false, false) => AssetStatus::Failed,
+            };
+            result.computed = Some(computed);
+            assets.push(result);
+        }
+
+        let rewritten = (!replacements.is_empty()).then(|| {
+            let mut output = String::with_capacity(html.len());
+            let mut last = 0;
+            for (range, quoted, value) in replacements {
+                output.push_str(&html[last..range.start]);
+                if quoted {
+                    output.push_str(&value);
+                } else {
+                    // Several space-separated hashes need quoting
+                    output.push('"');
+                    output.push_str(&value);
+                    output.push('"');
+                }
+                last = range.end;
+            }
+            output.push_str(&html[last..]);
+            output
+        });
+
+        Ok(HtmlIntegrity { assets, rewritten })
+    }
+}
+
+/// Map an asset URL to a local file, or `None` for remote and data URLs
+fn local_asset_path(url: &str, base: &Path, root: &Path) -> Option<PathBuf> {
+    if url.starts_with("//") || url.contains(':') {
+        return None;
+    }
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let decoded = percent_decode(url);
+    Some(match decoded.strip_prefix('/') {
+        Some(absolute) => root.join(absolute),
+        None => base.join(decoded),
+    })
+}
+
+/// Decode `%XX` escapes; malformed escapes are kept as written
+fn percent_decode(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(|b| b.is_ascii_hexdigit()));
+        if let Some(hex) = escape {
+            let hex = std::str::from_utf8(hex).expect("hex digits are ASCII");
+            decoded.push(u8::from_str_radix(hex, 16).expect("validated hex digits"));
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// A `<script>` or `<link>` tag carrying an integrity attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IntegrityTag {
+    name: String,
+    line: usize,
+    /// `src` of scripts, `href` of links
+    url: String,
+    integrity: String,
+    /// Byte range of the integrity value (inside the quotes, if any)
+    value_range: Range<usize>,
+    quoted: bool,
+}
+
+/// An attribute of an HTML start tag
+struct Attribute {
+    name: String,
+    value: String,
+    range: Range<usize>,
+    quoted: bool,
+}
+
+/// Find integrity-carrying `<script>` and `<link>` tags.
+///
+/// This is a small tokenizer rather than a full HTML parser: it understands
+/// comments, quoted and unquoted attribute values, and skips script bodies so
+/// markup inside JavaScript strings is not mistaken for tags.
+fn find_integrity_tags(html: &str) -> Vec<IntegrityTag> {
+    let bytes = html.as_bytes();
+    let mut tags = Vec::new();
+    let mut pos = 0;
+    // Line number of `line_start`, advanced as tags are found
+    let (mut line, mut line_start) = (1, 0);
+
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &html[start..];
+        if rest.starts_with("<!--") {
+            pos = rest.find("-->").map_or(html.len(), |end| start + end + 3);
+            continue;
+        }
+
+        let name_len = rest[1..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len() - 1);
+        let name = rest[1..1 + name_len].to_ascii_lowercase();
+        if name != "script" && name != "link" {
+            pos = start + 1;
+            continue;
+        }
+
+        let (attributes, end) = parse_attributes(html, start + 1 + name_len);
+        let url_attribute = if name == "script" { "src" } else { "href" };
+        let find = |wanted: &str| attributes.iter().find(|attr| attr.name == wanted);
+        if let (Some(url), Some(integrity)) = (find(url_attribute), find("integrity")) {
+            line += bytes[line_start..start]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count();
+            line_start = start;
+            tags.push(IntegrityTag {
+                name: name.clone(),
+                line,
+                url: url.value.clone(),
+                integrity: integrity.value.clone(),
+                value_range: integrity.range.clone(),
+                quoted: integrity.quoted,
+            });
+        }
+
+        pos = end;
+        if name == "script" {
+            // Skip the script body up to its end tag
+            pos = find_ignore_case(&bytes[pos..], b"</script")
+                .map_or(html.len(), |close| pos + close);
+        }
+    }
+    tags
+}
+
+/// Position of the first ASCII case-insensitive match of `needle`
+fn find_ignore_case(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+/// Parse attributes from `pos` up to the closing `>`; returns them and the
+/// position just after the tag
+fn parse_attributes(html: &str, mut pos: usize) -> (Vec<Attribute>, usize) {
+    let bytes = html.as_bytes();
+    let mut attributes = Vec::new();
+
+    loop {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+            pos += 1;
+        }
+        if pos >= bytes.len() {
+            return (attributes, pos);
+        }
+        if bytes[pos] == b'>' {
+            return (attributes, pos + 1);
+        }
+
+        let name_start = pos;
+        while pos < bytes.len()
+            && !bytes[pos].is_ascii_whitespace()
+            && !matches!(bytes[pos], b'=' | b'>' | b'/')
+        {
+            pos += 1;
+        }
+        let name = html[name_start..pos].to_ascii_lowercase();
+
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos >= bytes.len() || bytes[pos] != b'=' {
+            attributes.push(Attribute {
+                name,
+                value: String::new(),
+                range: pos..pos,
+                quoted: false,
+            });
+            continue;
+        }
+        pos += 1;
+        while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+
+        let (range, quoted) = match bytes.get(pos) {
+            Some(&quote @ (b'"' | b'\'')) => {
+                let value_start = pos + 1;
+                let value_end = html[value_start..]
+                    .find(quote as char)
+                    .map_or(html.len(), |end| value_start + end);
+                pos = (value_end + 1).min(html.len());
+                (value_start..value_end, true)
+            }
+            _ => {
+                let value_start = pos;
+                while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+                    pos += 1;
+                }
+                (value_start..pos, false)
+            }
+        };
+        attributes.push(Attribute {
+            name,
+            value: decode_entities(&html[range.clone()]),
+            range,
+            quoted,
+        });
+    }
+}
+
+/// Decode the character references that commonly appear in URLs
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&amp;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_integrity() {
+        let sha384 = HashResult::new(vec![7; 48], HashAlgorithm::Sha384);
+        let sha512 = HashResult::new(vec![9; 64], HashAlgorithm::Sha512);
+        let value = format!(
+            "{} md5-AAAA sha512-bogus {}?opt",
+            sha384.to_sri(),
+            sha512.to_sri()
+        );
+
+        let integrity = Integrity::parse(&value);
+        assert_eq!(integrity.hashes.len(), 2);
+        assert_eq!(integrity.strongest(), Some(HashAlgorithm::Sha512));
+        assert!(integrity.matches(&sha512));
+        assert!(!integrity.matches(&HashResult::new(vec![0; 64], HashAlgorithm::Sha512)));
+        assert_eq!(Integrity::parse("md5-AAAA").strongest(), None);
+
+        // Browsers reject the URL-safe alphabet, so its tokens are ignored
+        let digest = HashResult::new(vec![0xfb; 32], HashAlgorithm::Sha256);
+        let standard = digest.to_sri();
+        assert!(standard.contains('+') || standard.contains('/'));
+        let url_safe = standard.replace('+', "-").replace('/', "_");
+        assert!(Integrity::parse(&standard).matches(&digest));
+        assert!(Integrity::parse(&url_safe).hashes.is_empty());
+    }
+
+    #[test]
+    fn test_find_integrity_tags() {
+        let html = r#"<!doctype html>
+<!-- <script src="commented.js" integrity="sha256-x"></script> -->
+<link rel=stylesheet href="css/site.css?v=2" integrity=sha384-abc>
+<script src='app.js' INTEGRITY="sha256-def" crossorigin></script>
+<script>document.write('<script src="x.js" integrity="sha256-y">')</script>
+<script src="plain.js"></script>
+<SCRIPT>var s = "<link href=a.css integrity=sha256-z>"</Script>
+<script src="late.js" integrity="sha256-ghi"></script>
+"#;
+        let tags = find_integrity_tags(html);
+        assert_eq!(tags.len(), 3);
+        assert_eq!(tags[0].name, "link");
+        assert_eq!(tags[0].line, 3);
+        assert_eq!(tags[0].url, "css/site.css?v=2");
+        assert_eq!(&html[tags[0].value_range.clone()], "sha384-abc");
+        assert!(!tags[0].quoted);
+        assert_eq!(tags[1].name, "script");
+        assert_eq!(tags[1].line, 4);
+        assert_eq!(tags[1].integrity, "sha256-def");
+        assert!(tags[1].quoted);
+        assert_eq!((tags[2].line, tags[2].url.as_str()), (8, "late.js"));
+    }
+
+    #[test]
+    fn test_local_asset_path() {
+        let base = Path::new("site/docs");
+        let root = Path::new("site");
+        assert_eq!(
+            local_asset_path("app%20v2.js?x=1#top", base, root),
+            Some(PathBuf::from("site/docs/app v2.js"))
+        );
+        assert_eq!(
+            local_asset_path("/css/a.css", base, root),
+            Some(PathBuf::from("site/css/a.css"))
+        );
+        assert_eq!(
+            local_asset_path("https://cdn.example/a.js", base, root),
+            None
+        );
+        assert_eq!(local_asset_path("//cdn.example/a.js", base, root), None);
+    }
+}
@@ -0,0 +1,277 @@
+// Deterministic Merkle digest of a directory tree.
+//
+// The format is specified in docs-dev/TREE_DIGEST.md; any change to the bytes
+// hashed here must be reflected there, as other implementations rely on it.
+use crate::{
+    algorithms::HashAlgorithm,
+    core::HashForge,
+    output::HashResult,
+    walk::{collect_tree, WalkEntry, WalkOptions},
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path},
+};
+
+/// What a tree digest covers beyond file names and contents
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TreeOptions {
+    /// Include permission bits of files and directories
+    pub modes: bool,
+    /// Include directories that contain no selected files
+    pub empty_dirs: bool,
+}
+
+/// Digest of a whole directory
+#[derive(Debug, Clone)]
+pub struct TreeDigest {
+    pub digest: HashResult,
+    /// Files (and links, with `hash-target-path`) covered by the digest
+    pub files: usize,
+    /// Subdirectories covered by the digest
+    pub directories: usize,
+}
+
+enum Node {
+    File {
+        kind: &'static str,
+        mode: u32,
+        digest: Vec<u8>,
+    },
+    Dir {
+        mode: u32,
+        children: BTreeMap<Vec<u8>, Node>,
+    },
+}
+
+impl Node {
+    fn empty_dir(mode: u32) -> Self {
+        Node::Dir {
+            mode,
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+impl HashForge {
+    /// Compute the Merkle digest of the files selected in `directory`.
+    ///
+    /// The walk is always recursive unless `walk.max_depth` limits it. Any
+    /// file that cannot be read makes the whole digest fail, since a partial
+    /// tree digest would be indistinguishable from a different tree.
+    pub fn tree_digest(
+        &self,
+        directory: &Path,
+        algorithm: HashAlgorithm,
+        walk: &WalkOptions,
+        options: &TreeOptions,
+    ) -> Result<TreeDigest> {
+        if algorithm.is_password_hash() {
+            anyhow::bail!(
+                "Tree digests need a fast hash; {} is a password hash",
+                algorithm.name()
+            );
+        }
+        let walk = WalkOptions {
+            recursive: true,
+            ..walk.clone()
+        };
+        let tree = collect_tree(directory, &walk)?;
+
+        let mut root = Node::empty_dir(mode_of(directory, false, options)?);
+        if options.empty_dirs {
+            for dir in &tree.dirs {
+                let mode = mode_of(&directory.join(dir), false, options)?;
+                insert(&mut root, dir, Node::empty_dir(mode), directory, options)?;
+            }
+        }
+        for entry in &tree.files {
+            let digest = self
+                .hash_entry(entry, algorithm)
+                .with_context(|| format!("Failed to hash {}", entry.path.display()))?;
+            let node = file_node(entry, digest.bytes, options)?;
+            insert(&mut root, &entry.relative, node, directory, options)?;
+        }
+
+        let mut counts = (0, 0);
+        let digest = self.node_digest(&root, algorithm, options, &mut counts)?;
+        Ok(TreeDigest {
+            digest: HashResult::new(digest, algorithm),
+            files: counts.0,
+            directories: counts.1,
+        })
+    }
+
+    /// Digest of a node: file digests as computed, directories hashed over
+    /// their listing. `counts` accumulates (files, directories) below `node`.
+    fn node_digest(
+        &self,
+        node: &Node,
+        algorithm: HashAlgorithm,
+        options: &TreeOptions,
+        counts: &mut (usize, usize),
+    ) -> Result<Vec<u8>> {
+        let Node::Dir { children, .. } = node else {
+            unreachable!("only directories are hashed recursively")
+        };
+
+        let mut listing = Vec::new();
+        for (name, child) in children {
+            let (kind, mode, digest) = match child {
+                Node::File { kind, mode, digest } => {
+                    counts.0 += 1;
+                    (*kind, *mode, digest.clone())
+                }
+                Node::Dir { mode, .. } => {
+                    counts.1 += 1;
+                    (
+                        "dir",
+                        *mode,
+                        self.node_digest(child, algorithm, options, counts)?,
+                    )
+                }
+            };
+            listing.extend_from_slice(kind.as_bytes());
+            listing.push(b' ');
+            if options.modes {
+                listing.extend_from_slice(format!("{mode:04o} ").as_bytes());
+            }
+            listing.extend_from_slice(hex::encode(digest).as_bytes());
+            listing.push(b' ');
+            listing.extend_from_slice(name);
+            listing.push(0);
+        }
+
+        Ok(self.hash_bytes(&listing, algorithm)?.bytes)
+    }
+}
+
+/// Leaf node for a walked file or link
+fn file_node(entry: &WalkEntry, digest: Vec<u8>, options: &TreeOptions) -> Result<Node> {
+    let is_link = entry.link_target.is_some();
+    Ok(Node::File {
+        kind: if is_link { "link" } else { "file" },
+        mode: mode_of(&entry.path, is_link, options)?,
+        digest,
+    })
+}
+
+/// Insert `node` at `relative`, creating intermediate directories
+fn insert(
+    root: &mut Node,
+    relative: &Path,
+    node: Node,
+    base: &Path,
+    options: &TreeOptions,
+) -> Result<()> {
+    let names: Vec<_> = relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    let Some((last, parents)) = names.split_last() else {
+        return Ok(());
+    };
+
+    let mut current = root;
+    let mut path = base.to_path_buf();
+    for name in parents {
+        path.push(name);
+        let Node::Dir { children, .. } = current else {
+            anyhow::bail!("{} is both a file and a directory", path.display());
+        };
+        current = match children.entry(name.as_encoded_bytes().to_vec()) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                entry.insert(Node::empty_dir(mode_of(&path, false, options)?))
+            }
+        };
+    }
+    if let Node::Dir { children, .. } = current {
+        children
+            .entry(last.as_encoded_bytes().to_vec())
+            .or_insert(node);
+    }
+    Ok(())
+}
+
+/// Permission bits recorded for `path`, or 0 when modes are not included
+fn mode_of(path: &Path, is_link: bool, options: &TreeOptions) -> Result<u32> {
+    if !options.modes {
+        return Ok(0);
+    }
+    let metadata = if is_link {
+        fs::symlink_metadata(path)
+    } else {
+        fs::metadata(path)
+    }
+    .with_context(|| format!("Failed to read metadata: {}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(metadata.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(
+            match (metadata.is_dir(), metadata.permissions().readonly()) {
+                (true, _) => 0o755,
+                (false, true) => 0o444,
+                (false, false) => 0o644,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_digest() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+        fs::write(root.join("a/x.txt"), "hello\n").unwrap();
+        fs::write(root.join("top"), "z\n").unwrap();
+
+        let forge = HashForge::new();
+        let digest = |options: &TreeOptions| {
+            forge
+                .tree_digest(
+                    root,
+                    HashAlgorithm::Sha256,
+                    &WalkOptions::default(),
+                    options,
+                )
+                .unwrap()
+        };
+
+        // Example from docs-dev/TREE_DIGEST.md
+        let tree = digest(&TreeOptions::default());
+        assert_eq!(
+            tree.digest.to_hex(),
+            "abe32a04ba007c7c1f79351ae107110b81ae359f3f6ca15111905482cf18c93b"
+        );
+        assert_eq!((tree.files, tree.directories), (2, 1));
+
+        let with_empty = digest(&TreeOptions {
+            empty_dirs: true,
+            ..Default::default()
+        });
+        assert_ne!(with_empty.digest.bytes, tree.digest.bytes);
+        assert_eq!(with_empty.directories, 2);
+
+        fs::rename(root.join("top"), root.join("top2")).unwrap();
+        assert_ne!(
+            digest(&TreeOptions::default()).digest.bytes,
+            tree.digest.bytes
+        );
+    }
+}
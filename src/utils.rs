@@ -31,6 +31,46 @@ pub fn format_file_size(size: u64) -> String {
     }
 }
 
+/// Parse a byte count such as `0`, `4096`, `0x1000`, `64K` or `2GiB`.
+///
+/// Suffixes are binary multiples (`K` = 1024) and case-insensitive; a
+/// trailing `B` or `iB` is accepted. Hexadecimal counts take no suffix.
+pub fn parse_byte_count(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).map_err(|_| format!("invalid byte count '{s}'"));
+    }
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(digits);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid byte count '{s}'"))?;
+    let suffix = suffix.to_ascii_lowercase();
+    let suffix = suffix
+        .strip_suffix("ib")
+        .or_else(|| suffix.strip_suffix('b'))
+        .unwrap_or(&suffix);
+    let shift = match suffix {
+        "" => 0,
+        "k" => 10,
+        "m" => 20,
+        "g" => 30,
+        "t" => 40,
+        _ => return Err(format!("invalid byte count '{s}': unknown unit")),
+    };
+    number
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("byte count '{s}' is too large"))
+}
+
+/// Parse a size with [`parse_byte_count`], rejecting zero
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    match parse_byte_count(s)? {
+        0 => Err("size must not be zero".to_string()),
+        size => Ok(size),
+    }
+}
+
 /// Format duration in human readable format
 pub fn format_duration(duration: std::time::Duration) -> String {
     let total_seconds = duration.as_secs();
@@ -50,6 +90,40 @@ pub fn format_duration(duration: std::time::Duration) -> String {
     }
 }
 
+/// Format a timestamp as RFC 3339 in UTC (e.g. `2024-05-01T12:30:00Z`)
+pub fn format_timestamp(time: std::time::SystemTime) -> String {
+    let (secs, nanos) = match time.duration_since(std::time::UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+        Err(e) => {
+            let d = e.duration();
+            let nanos = d.subsec_nanos();
+            let secs = -(d.as_secs() as i64) - i64::from(nanos > 0);
+            (secs, if nanos > 0 { 1_000_000_000 - nanos } else { 0 })
+        }
+    };
+
+    let days = secs.div_euclid(86_400);
+    let day_secs = secs.rem_euclid(86_400);
+    let (hour, minute, second) = (day_secs / 3600, (day_secs % 3600) / 60, day_secs % 60);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    if nanos == 0 {
+        format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+    } else {
+        format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{nanos:09}Z")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,4 +145,32 @@ mod tests {
         assert_eq!(format_file_size(1536), "1.50 KB");
         assert_eq!(format_file_size(1024 * 1024), "1.00 MB");
     }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("1MiB"), Ok(1024 * 1024));
+        assert_eq!(parse_size("2gb"), Ok(2 << 30));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("1X").is_err());
+        assert!(parse_size("K").is_err());
+        assert_eq!(parse_byte_count("0"), Ok(0));
+        assert_eq!(parse_byte_count("0x7e00"), Ok(0x7e00));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+            "2024-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(1_500)),
+            "1970-01-01T00:00:01.500000000Z"
+        );
+    }
 }
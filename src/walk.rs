@@ -0,0 +1,542 @@
+// Directory traversal shared by batch and manifest operations
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Serialize, Serializer};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// What to do with symbolic links found while walking a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Default)]
+pub enum SymlinkPolicy {
+    /// Ignore symbolic links entirely
+    #[value(name = "skip")]
+    Skip,
+    /// Hash the file a link points to and descend into linked directories
+    #[default]
+    #[value(name = "follow")]
+    Follow,
+    /// Hash the link target path itself instead of the file it points to
+    #[value(name = "hash-target-path")]
+    HashTargetPath,
+}
+
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymlinkPolicy::Skip => write!(f, "skip"),
+            SymlinkPolicy::Follow => write!(f, "follow"),
+            SymlinkPolicy::HashTargetPath => write!(f, "hash-target-path"),
+        }
+    }
+}
+
+impl Serialize for SymlinkPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Options controlling which files a directory walk yields
+#[derive(Debug, Clone, Default, Args)]
+pub struct WalkOptions {
+    /// Descend into subdirectories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Maximum directory depth to descend (1 = only the directory itself); implies --recursive
+    #[arg(long, value_name = "DEPTH")]
+    pub max_depth: Option<usize>,
+
+    /// Only hash files matching this glob (repeatable, e.g. "*.rs" or "src/**")
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip files and directories matching this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Skip hidden files and directories (names starting with '.')
+    #[arg(long)]
+    pub skip_hidden: bool,
+
+    /// How to treat symbolic links
+    #[arg(long, default_value = "follow")]
+    pub symlinks: SymlinkPolicy,
+
+    /// Honour .gitignore, .ignore and .hashforgeignore files
+    #[arg(long)]
+    pub respect_ignore: bool,
+}
+
+impl WalkOptions {
+    /// Effective maximum depth, taking --recursive into account
+    fn depth_limit(&self) -> usize {
+        match self.max_depth {
+            Some(depth) => depth,
+            None if self.recursive => usize::MAX,
+            None => 1,
+        }
+    }
+}
+
+/// A file found by [`collect_files`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalkEntry {
+    /// Path on disk
+    pub path: PathBuf,
+    /// Path relative to the walked directory
+    pub relative: PathBuf,
+    /// Link target to hash instead of file contents (`hash-target-path` policy)
+    pub link_target: Option<PathBuf>,
+}
+
+impl WalkEntry {
+    /// Relative path rendered with `/` separators on every platform
+    pub fn display_path(&self) -> String {
+        self.relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Number of bytes that will be hashed for this entry, if known
+    pub fn size(&self) -> Option<u64> {
+        match self.link_target {
+            Some(ref target) => Some(target.as_os_str().len() as u64),
+            None => fs::metadata(&self.path).ok().map(|m| m.len()),
+        }
+    }
+
+    /// Last modification time of the file (or of the link itself for link targets)
+    pub fn modified(&self) -> Option<SystemTime> {
+        let metadata = match self.link_target {
+            Some(_) => fs::symlink_metadata(&self.path),
+            None => fs::metadata(&self.path),
+        };
+        metadata.and_then(|m| m.modified()).ok()
+    }
+}
+
+/// Compiled include/exclude globs
+struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    fn new(options: &WalkOptions) -> Result<Self> {
+        let include = if options.include.is_empty() {
+            None
+        } else {
+            Some(build_globset(&options.include)?)
+        };
+        Ok(Self {
+            include,
+            exclude: build_globset(&options.exclude)?,
+        })
+    }
+
+    /// Patterns match either the whole relative path or just the file name
+    fn matches(set: &GlobSet, relative: &Path) -> bool {
+        set.is_match(relative) || relative.file_name().is_some_and(|name| set.is_match(name))
+    }
+
+    fn is_excluded(&self, relative: &Path) -> bool {
+        Self::matches(&self.exclude, relative)
+    }
+
+    fn is_included(&self, relative: &Path) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|set| Self::matches(set, relative))
+    }
+}
+
+/// Ignore files read in each directory, lowest precedence first
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".hashforgeignore"];
+
+/// Build a matcher from `files` (those that exist), anchored at `root`
+fn load_ignore_files(files: &[PathBuf], root: &Path) -> Option<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    let mut found = false;
+
+    for file in files.iter().filter(|file| file.is_file()) {
+        found = true;
+        if let Some(e) = builder.add(file) {
+            eprintln!("⚠️ Ignoring invalid pattern in {}: {e}", file.display());
+        }
+    }
+
+    if !found {
+        return None;
+    }
+    builder
+        .build()
+        .map_err(|e| eprintln!("⚠️ Failed to load ignore files in {}: {e}", root.display()))
+        .ok()
+}
+
+/// Matcher for the ignore files directly inside `dir`
+fn dir_ignore_files(dir: &Path, root: &Path) -> Option<Gitignore> {
+    let files: Vec<_> = IGNORE_FILES.iter().map(|name| dir.join(name)).collect();
+    load_ignore_files(&files, root)
+}
+
+/// Matchers inherited from the enclosing git repository, outermost first.
+///
+/// Like git, the repository's `.git/info/exclude` has the lowest precedence,
+/// followed by ignore files in parent directories down to `root`.
+fn parent_ignore_files(root: &Path) -> Vec<Gitignore> {
+    let Some(repo) = root.ancestors().find(|dir| dir.join(".git").exists()) else {
+        return Vec::new();
+    };
+
+    let exclude = repo.join(".git").join("info").join("exclude");
+    let mut matchers: Vec<_> = load_ignore_files(&[exclude], repo).into_iter().collect();
+    let parents: Vec<_> = root
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(repo))
+        .collect();
+    for dir in parents.into_iter().rev() {
+        matchers.extend(dir_ignore_files(dir, dir));
+    }
+    matchers
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid glob pattern: {pattern}"))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// Files and directories found by [`collect_tree`]
+#[derive(Debug, Clone, Default)]
+pub struct WalkTree {
+    /// Selected files, sorted by relative path
+    pub files: Vec<WalkEntry>,
+    /// Relative paths of the subdirectories descended into, sorted
+    pub dirs: Vec<PathBuf>,
+}
+
+/// Collect the files under `root` selected by `options`, sorted by relative path.
+///
+/// Unreadable subdirectories and broken links are reported on stderr and
+/// skipped; only failing to read `root` itself is an error.
+pub fn collect_files(root: &Path, options: &WalkOptions) -> Result<Vec<WalkEntry>> {
+    Ok(collect_tree(root, options)?.files)
+}
+
+/// Like [`collect_files`], but also report the directories that were walked
+/// (including those that turned out to hold no selected files)
+pub fn collect_tree(root: &Path, options: &WalkOptions) -> Result<WalkTree> {
+    let filter = PathFilter::new(options)?;
+    let entries = fs::read_dir(root)
+        .with_context(|| format!("Failed to read directory: {}", root.display()))?;
+    let canonical_root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+
+    let mut walker = Walker {
+        options,
+        filter,
+        ancestors: vec![canonical_root.clone()],
+        ignores: Vec::new(),
+        inherited: 0,
+        ignore_root: canonical_root,
+        entries: Vec::new(),
+        dirs: Vec::new(),
+    };
+    if options.respect_ignore {
+        walker.ignores = parent_ignore_files(&walker.ignore_root);
+        walker.inherited = walker.ignores.len();
+    }
+    walker.walk_entries(root, entries, Path::new(""), 1);
+
+    let mut files = walker.entries;
+    files.sort_by(|a, b| a.relative.cmp(&b.relative));
+    let mut dirs = walker.dirs;
+    dirs.sort();
+    Ok(WalkTree { files, dirs })
+}
+
+struct Walker<'a> {
+    options: &'a WalkOptions,
+    filter: PathFilter,
+    /// Canonical paths of the directories being walked, to break symlink loops
+    ancestors: Vec<PathBuf>,
+    /// Ignore matchers in scope for the current directory, outermost first
+    ignores: Vec<Gitignore>,
+    /// Number of leading `ignores` read from above the walked directory
+    inherited: usize,
+    /// Absolute path ignore patterns are matched against
+    ignore_root: PathBuf,
+    entries: Vec<WalkEntry>,
+    dirs: Vec<PathBuf>,
+}
+
+impl Walker<'_> {
+    /// Check ignore files from the innermost directory outwards; the first
+    /// matcher with an opinion (ignore or `!` whitelist) decides, as in git
+    fn is_ignored(&self, relative: &Path, is_dir: bool) -> bool {
+        let path = self.ignore_root.join(relative);
+        for (index, matcher) in self.ignores.iter().enumerate().rev() {
+            // Ignored directories inside the walk are never entered, but
+            // inherited patterns may match the walked directory or one of
+            // its parents, which the walk started below
+            let matched = if index < self.inherited {
+                matcher.matched_path_or_any_parents(&path, is_dir)
+            } else {
+                matcher.matched(&path, is_dir)
+            };
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn walk_entries(
+        &mut self,
+        dir: &Path,
+        entries: fs::ReadDir,
+        relative_dir: &Path,
+        depth: usize,
+    ) {
+        let loaded = if self.options.respect_ignore {
+            let root = self.ignore_root.join(relative_dir);
+            dir_ignore_files(dir, &root).map(|matcher| self.ignores.push(matcher))
+        } else {
+            None
+        };
+
+        let mut entries: Vec<_> = entries
+            .filter_map(|entry| {
+                entry
+                    .map_err(|e| eprintln!("⚠️ Skipping entry in {}: {e}", relative_dir.display()))
+                    .ok()
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name();
+            if self.options.skip_hidden && name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            // git never tracks its own metadata directory
+            if self.options.respect_ignore && name == ".git" {
+                continue;
+            }
+
+            let path = entry.path();
+            let relative = relative_dir.join(&name);
+            if self.filter.is_excluded(&relative) {
+                continue;
+            }
+
+            let Ok(mut file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_symlink() {
+                match self.options.symlinks {
+                    SymlinkPolicy::Skip => continue,
+                    SymlinkPolicy::HashTargetPath => {
+                        if self.filter.is_included(&relative) {
+                            match fs::read_link(&path) {
+                                Ok(_)
+                                    if self.options.respect_ignore
+                                        && self.is_ignored(&relative, false) => {}
+                                Ok(target) => self.entries.push(WalkEntry {
+                                    path,
+                                    relative,
+                                    link_target: Some(target),
+                                }),
+                                Err(e) => eprintln!("⚠️ Skipping {}: {e}", path.display()),
+                            }
+                        }
+                        continue;
+                    }
+                    SymlinkPolicy::Follow => match fs::metadata(&path) {
+                        Ok(metadata) => file_type = metadata.file_type(),
+                        Err(e) => {
+                            eprintln!("⚠️ Skipping broken link {}: {e}", path.display());
+                            continue;
+                        }
+                    },
+                }
+            }
+
+            if self.options.respect_ignore && self.is_ignored(&relative, file_type.is_dir()) {
+                continue;
+            }
+
+            if file_type.is_dir() {
+                if depth < self.options.depth_limit() {
+                    self.walk_dir(&path, &relative, depth + 1);
+                }
+            } else if file_type.is_file() && self.filter.is_included(&relative) {
+                self.entries.push(WalkEntry {
+                    path,
+                    relative,
+                    link_target: None,
+                });
+            }
+        }
+
+        if loaded.is_some() {
+            self.ignores.pop();
+        }
+    }
+
+    fn walk_dir(&mut self, path: &Path, relative: &Path, depth: usize) {
+        let canonical = fs::canonicalize(path).ok();
+        if let Some(ref canonical) = canonical {
+            if self.ancestors.contains(canonical) {
+                eprintln!("⚠️ Skipping symlink loop at {}", path.display());
+                return;
+            }
+        }
+
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                self.dirs.push(relative.to_path_buf());
+                let pushed = canonical.map(|c| self.ancestors.push(c)).is_some();
+                self.walk_entries(path, entries, relative, depth);
+                if pushed {
+                    self.ancestors.pop();
+                }
+            }
+            Err(e) => eprintln!("⚠️ Skipping directory {}: {e}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_files_hidden() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join(".config")).unwrap();
+        fs::write(root.join(".hidden"), "a").unwrap();
+        fs::write(root.join(".config/settings"), "b").unwrap();
+        fs::write(root.join("visible"), "c").unwrap();
+
+        let paths = |options: &WalkOptions| -> Vec<String> {
+            collect_files(root, options)
+                .unwrap()
+                .iter()
+                .map(WalkEntry::display_path)
+                .collect()
+        };
+        let recursive = WalkOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            paths(&recursive),
+            [".config/settings", ".hidden", "visible"]
+        );
+        assert_eq!(
+            paths(&WalkOptions {
+                skip_hidden: true,
+                ..recursive
+            }),
+            ["visible"]
+        );
+    }
+
+    /// Write `contents` to `root/relative`, creating parent directories
+    fn write(root: &Path, relative: &str, contents: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn ignored_walk(root: &Path) -> Vec<String> {
+        let options = WalkOptions {
+            recursive: true,
+            skip_hidden: true,
+            respect_ignore: true,
+            ..Default::default()
+        };
+        collect_files(root, &options)
+            .unwrap()
+            .iter()
+            .map(WalkEntry::display_path)
+            .collect()
+    }
+
+    #[test]
+    fn test_ignore_file_precedence() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        // .ignore overrides .gitignore, and .hashforgeignore overrides both
+        write(root, ".gitignore", "*.log\n*.tmp\n");
+        write(root, ".ignore", "!keep.*\n");
+        write(root, ".hashforgeignore", "keep.tmp\nsecret.txt\n");
+        // A nested file overrides its parents
+        write(root, "sub/.gitignore", "!debug.log\n");
+        for name in [
+            "a.log",
+            "keep.log",
+            "b.tmp",
+            "keep.tmp",
+            "notes.txt",
+            "secret.txt",
+            "sub/debug.log",
+            "sub/trace.log",
+        ] {
+            write(root, name, name);
+        }
+
+        assert_eq!(
+            ignored_walk(root),
+            ["keep.log", "notes.txt", "sub/debug.log"]
+        );
+    }
+
+    #[test]
+    fn test_ignore_files_above_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let repo = temp.path();
+        write(repo, ".git/info/exclude", "*.bak\n");
+        write(repo, ".gitignore", "target/\n*.o\n!main.o\n");
+        for name in [
+            "project/lib.rs",
+            "project/lib.bak",
+            "project/lib.o",
+            "project/main.o",
+            "target/debug/app",
+        ] {
+            write(repo, name, name);
+        }
+
+        assert_eq!(ignored_walk(&repo.join("project")), ["lib.rs", "main.o"]);
+        // The walked directory itself is ignored by the repository
+        assert!(ignored_walk(&repo.join("target")).is_empty());
+        assert!(ignored_walk(&repo.join("target/debug")).is_empty());
+        let all = WalkOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        assert_eq!(collect_files(&repo.join("target"), &all).unwrap().len(), 1);
+    }
+}
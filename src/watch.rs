@@ -0,0 +1,500 @@
+// Re-hash files as they change, using native file system notifications
+use crate::{
+    algorithms::HashAlgorithm,
+    core::HashForge,
+    output::HashResult,
+    walk::{collect_files, WalkOptions},
+};
+use anyhow::{Context, Result};
+use notify::{
+    event::{AccessKind, AccessMode},
+    EventKind, RecursiveMode, Watcher,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Options for [`HashForge::watch`]
+#[derive(Debug, Clone, Copy)]
+pub struct WatchOptions {
+    /// How long a file must go without changes before it is hashed
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(500),
+        }
+    }
+}
+
+/// What happened to a watched file
+#[derive(Debug, Clone)]
+pub enum WatchChange {
+    /// The file was hashed, initially or after its contents changed
+    Hashed(HashResult),
+    /// The file was deleted or is no longer selected
+    Removed,
+    /// The file could not be hashed
+    Failed(String),
+}
+
+/// A change reported by [`HashForge::watch`]
+#[derive(Debug, Clone)]
+pub struct WatchEvent {
+    /// Path as reachable from the current directory
+    pub path: PathBuf,
+    /// Path relative to the watched directory (or as given, for watched files)
+    pub relative: String,
+    pub change: WatchChange,
+    /// Whether this is part of the initial pass over existing files
+    pub initial: bool,
+}
+
+/// A file or directory given to [`HashForge::watch`]
+struct WatchRoot {
+    /// As given on the command line
+    path: PathBuf,
+    /// Absolute form, which notification paths are compared against
+    absolute: PathBuf,
+    is_dir: bool,
+}
+
+/// A selected file: where to find it and how to report it
+#[derive(Clone)]
+struct Selected {
+    path: PathBuf,
+    relative: String,
+}
+
+/// A file reported before, with the digest it was reported with
+struct Known {
+    file: Selected,
+    digest: Vec<u8>,
+}
+
+impl WatchRoot {
+    /// Files currently selected under this root, keyed by absolute path
+    fn select(&self, walk: &WalkOptions) -> Result<HashMap<PathBuf, Selected>> {
+        if !self.is_dir {
+            let mut selected = HashMap::new();
+            if self.path.is_file() {
+                selected.insert(
+                    self.absolute.clone(),
+                    Selected {
+                        path: self.path.clone(),
+                        relative: self.path.display().to_string(),
+                    },
+                );
+            }
+            return Ok(selected);
+        }
+        Ok(collect_files(&self.path, walk)?
+            .into_iter()
+            .map(|entry| {
+                let relative = entry.display_path();
+                (
+                    self.absolute.join(&entry.relative),
+                    Selected {
+                        path: entry.path,
+                        relative,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    /// Whether a change at `path` can affect files selected under this root
+    fn is_affected_by(&self, path: &Path) -> bool {
+        if self.is_dir {
+            path.starts_with(&self.absolute)
+        } else {
+            path == self.absolute
+        }
+    }
+
+    /// What to report now that `settled` paths have gone quiet, given the
+    /// files currently `selected` under this root: known files that are gone,
+    /// and files to hash again. Files still pending wait until they settle.
+    fn changes(
+        &self,
+        settled: &[PathBuf],
+        selected: HashMap<PathBuf, Selected>,
+        known: &HashMap<PathBuf, Known>,
+        debouncer: &Debouncer,
+    ) -> (Vec<PathBuf>, Vec<(PathBuf, Selected)>) {
+        let affected = |path: &Path| settled.iter().any(|p| path.starts_with(p));
+        let mut changed: Vec<_> = selected
+            .into_iter()
+            .filter(|(absolute, _)| affected(absolute) && !debouncer.is_pending(absolute))
+            .collect();
+        changed.sort_by(|a, b| a.1.relative.cmp(&b.1.relative));
+
+        let mut removed: Vec<_> = known
+            .keys()
+            .filter(|path| {
+                self.is_affected_by(path)
+                    && affected(path)
+                    && !changed.iter().any(|(absolute, _)| absolute == *path)
+                    && !debouncer.is_pending(path)
+            })
+            .cloned()
+            .collect();
+        removed.sort();
+        (removed, changed)
+    }
+}
+
+fn is_read_only(kind: &EventKind) -> bool {
+    matches!(kind, EventKind::Access(access) if *access != AccessKind::Close(AccessMode::Write))
+}
+
+/// Size and mtime, used to tell whether a file is still being written;
+/// `None` if the file does not exist
+type FileState = Option<(u64, SystemTime)>;
+
+fn file_state(path: &Path) -> FileState {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Paths with recent events, held back until they settle.
+///
+/// A path settles once no event has arrived for it for the debounce interval
+/// and its state has stopped changing. The time and file states are passed
+/// in, so the bookkeeping does not depend on the clock or the file system.
+struct Debouncer {
+    debounce: Duration,
+    /// When the last event arrived, and the state the path was last seen in
+    pending: HashMap<PathBuf, (Instant, FileState)>,
+}
+
+impl Debouncer {
+    fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Record an event for `path`, which was in `state` at `now`
+    fn record(&mut self, path: PathBuf, now: Instant, state: FileState) {
+        self.pending.insert(path, (now, state));
+    }
+
+    fn is_pending(&self, path: &Path) -> bool {
+        self.pending.contains_key(path)
+    }
+
+    /// How long until the next path may settle, or `None` if nothing is pending
+    fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.pending
+            .values()
+            .map(|(last, _)| (*last + self.debounce).saturating_duration_since(now))
+            .min()
+    }
+
+    /// Remove and return the paths that have settled at `now`. A path whose
+    /// state differs from `state_of` is still being written and waits for
+    /// another interval.
+    fn settle(&mut self, now: Instant, state_of: impl Fn(&Path) -> FileState) -> Vec<PathBuf> {
+        let mut settled = Vec::new();
+        self.pending.retain(|path, (last, state)| {
+            if now < *last + self.debounce {
+                return true;
+            }
+            let current = state_of(path);
+            if current != *state {
+                *last = now;
+                *state = current;
+                return true;
+            }
+            settled.push(path.clone());
+            false
+        });
+        settled.sort();
+        settled
+    }
+}
+
+impl HashForge {
+    /// Hash the files selected by `paths`, then keep watching them and hash
+    /// each file again whenever it changes.
+    ///
+    /// Directories select files like `batch` does with `walk`. A file is only
+    /// hashed once no event has arrived for it for `options.debounce` and its
+    /// size and mtime have stopped changing, so files still being written
+    /// are not reported half-done. Files whose digest is unchanged after an
+    /// event are not reported again.
+    ///
+    /// Runs until `on_event` returns an error or the watcher stops.
+    pub fn watch<F>(
+        &self,
+        paths: &[PathBuf],
+        algorithm: HashAlgorithm,
+        walk: &WalkOptions,
+        options: &WatchOptions,
+        mut on_event: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&WatchEvent) -> Result<()>,
+    {
+        if algorithm.is_password_hash() {
+            anyhow::bail!(
+                "Watch mode needs a fast hash; {} is a password hash",
+                algorithm.name()
+            );
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(sender).context("Failed to start file watcher")?;
+        let mut roots = Vec::new();
+        for path in paths {
+            let metadata = fs::metadata(path)
+                .with_context(|| format!("Failed to watch {}", path.display()))?;
+            let absolute = std::path::absolute(path)?;
+            let recursive = walk.recursive || walk.max_depth.is_some_and(|depth| depth > 1);
+            // Watch a file through its directory, so editors that replace the
+            // file by renaming a new one over it don't end the watch
+            let (target, mode) = match (metadata.is_dir(), absolute.parent()) {
+                (true, _) if recursive => (absolute.clone(), RecursiveMode::Recursive),
+                (true, _) => (absolute.clone(), RecursiveMode::NonRecursive),
+                (false, Some(parent)) => (parent.to_path_buf(), RecursiveMode::NonRecursive),
+                (false, None) => (absolute.clone(), RecursiveMode::NonRecursive),
+            };
+            watcher
+                .watch(&target, mode)
+                .with_context(|| format!("Failed to watch {}", path.display()))?;
+            roots.push(WatchRoot {
+                path: path.clone(),
+                absolute,
+                is_dir: metadata.is_dir(),
+            });
+        }
+
+        // Files hashed so far, keyed by absolute path
+        let mut known: HashMap<PathBuf, Known> = HashMap::new();
+        for root in &roots {
+            let mut selected: Vec<_> = root.select(walk)?.into_iter().collect();
+            selected.sort_by(|a, b| a.1.relative.cmp(&b.1.relative));
+            for (absolute, file) in selected {
+                self.report(&mut known, absolute, file, algorithm, true, &mut on_event)?;
+            }
+        }
+
+        let mut debouncer = Debouncer::new(options.debounce);
+        loop {
+            let timeout = debouncer
+                .timeout(Instant::now())
+                .unwrap_or(Duration::from_secs(3600));
+            match receiver.recv_timeout(timeout) {
+                // Opening and reading (including our own hashing) changes nothing
+                Ok(Ok(event)) if is_read_only(&event.kind) => {}
+                Ok(Ok(event)) => {
+                    for path in event.paths {
+                        let state = file_state(&path);
+                        debouncer.record(path, Instant::now(), state);
+                    }
+                }
+                Ok(Err(e)) => eprintln!("hash-forge: watch error: {e}"),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            let settled = debouncer.settle(Instant::now(), file_state);
+            if settled.is_empty() {
+                continue;
+            }
+
+            for root in roots
+                .iter()
+                .filter(|root| settled.iter().any(|p| root.is_affected_by(p)))
+            {
+                let (removed, changed) =
+                    root.changes(&settled, root.select(walk)?, &known, &debouncer);
+                for path in removed {
+                    if let Some(Known { file, .. }) = known.remove(&path) {
+                        on_event(&WatchEvent {
+                            path: file.path,
+                            relative: file.relative,
+                            change: WatchChange::Removed,
+                            initial: false,
+                        })?;
+                    }
+                }
+
+                for (absolute, file) in changed {
+                    self.report(&mut known, absolute, file, algorithm, false, &mut on_event)?;
+                }
+            }
+        }
+    }
+
+    /// Hash a selected file and report it if its digest changed
+    fn report<F>(
+        &self,
+        known: &mut HashMap<PathBuf, Known>,
+        absolute: PathBuf,
+        file: Selected,
+        algorithm: HashAlgorithm,
+        initial: bool,
+        on_event: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(&WatchEvent) -> Result<()>,
+    {
+        let change = match self.hash_file(&file.path, algorithm) {
+            Ok(result) => {
+                if known
+                    .get(&absolute)
+                    .is_some_and(|known| known.digest == result.bytes)
+                {
+                    return Ok(());
+                }
+                known.insert(
+                    absolute,
+                    Known {
+                        file: file.clone(),
+                        digest: result.bytes.clone(),
+                    },
+                );
+                WatchChange::Hashed(result)
+            }
+            Err(e) => WatchChange::Failed(format!("{e:#}")),
+        };
+        on_event(&WatchEvent {
+            path: file.path,
+            relative: file.relative,
+            change,
+            initial,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBOUNCE: Duration = Duration::from_millis(500);
+
+    fn size(bytes: u64) -> FileState {
+        Some((bytes, SystemTime::UNIX_EPOCH))
+    }
+
+    fn selected(absolute: &str, relative: &str) -> (PathBuf, Selected) {
+        (
+            PathBuf::from(absolute),
+            Selected {
+                path: PathBuf::from(absolute),
+                relative: relative.to_string(),
+            },
+        )
+    }
+
+    fn known(paths: &[&str]) -> HashMap<PathBuf, Known> {
+        paths
+            .iter()
+            .map(|path| {
+                let (absolute, file) = selected(path, path);
+                (
+                    absolute,
+                    Known {
+                        file,
+                        digest: vec![0],
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn paths(changed: &[(PathBuf, Selected)]) -> Vec<&Path> {
+        changed.iter().map(|(path, _)| path.as_path()).collect()
+    }
+
+    #[test]
+    fn test_file_still_being_written() {
+        let start = Instant::now();
+        let log = PathBuf::from("/w/log");
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        debouncer.record(log.clone(), start, size(10));
+        assert_eq!(debouncer.timeout(start), Some(DEBOUNCE));
+        assert!(debouncer
+            .settle(start + DEBOUNCE / 2, |_| size(10))
+            .is_empty());
+
+        // Quiet for the interval, but the file grew without an event yet
+        let later = start + DEBOUNCE;
+        assert!(debouncer.settle(later, |_| size(20)).is_empty());
+        assert!(debouncer.is_pending(&log));
+        assert!(debouncer
+            .settle(later + DEBOUNCE / 2, |_| size(20))
+            .is_empty());
+        assert_eq!(debouncer.settle(later + DEBOUNCE, |_| size(20)), [log]);
+        assert_eq!(debouncer.timeout(later + DEBOUNCE), None);
+
+        // A file that is still pending is not hashed when a parent settles
+        let root = WatchRoot {
+            path: "w".into(),
+            absolute: "/w".into(),
+            is_dir: true,
+        };
+        debouncer.record("/w/b".into(), later, size(1));
+        let selected = HashMap::from([selected("/w/a", "a"), selected("/w/b", "b")]);
+        let (removed, changed) =
+            root.changes(&["/w".into()], selected, &known(&["/w/b"]), &debouncer);
+        assert!(removed.is_empty());
+        assert_eq!(paths(&changed), [Path::new("/w/a")]);
+    }
+
+    #[test]
+    fn test_removed_file() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        debouncer.record("/w/a".into(), start, None);
+        let settled = debouncer.settle(start + DEBOUNCE, |_| None);
+        assert_eq!(settled, [PathBuf::from("/w/a")]);
+
+        let root = WatchRoot {
+            path: "w".into(),
+            absolute: "/w".into(),
+            is_dir: true,
+        };
+        let selected = HashMap::from([selected("/w/b", "b")]);
+        let (removed, changed) =
+            root.changes(&settled, selected, &known(&["/w/a", "/w/b"]), &debouncer);
+        assert_eq!(removed, [PathBuf::from("/w/a")]);
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn test_rename_over_watched_file() {
+        // An editor writes a temporary file and renames it over the original;
+        // the rename event names both paths
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DEBOUNCE);
+        debouncer.record("/w/.f.txt.swp".into(), start, None);
+        debouncer.record("/w/f.txt".into(), start, size(5));
+        let settled = debouncer.settle(start + DEBOUNCE, |path| {
+            (path == Path::new("/w/f.txt")).then_some((5, SystemTime::UNIX_EPOCH))
+        });
+        assert_eq!(settled.len(), 2);
+
+        let root = WatchRoot {
+            path: "f.txt".into(),
+            absolute: "/w/f.txt".into(),
+            is_dir: false,
+        };
+        assert!(!root.is_affected_by(Path::new("/w/.f.txt.swp")));
+        let selected = HashMap::from([selected("/w/f.txt", "f.txt")]);
+        let (removed, changed) =
+            root.changes(&settled, selected, &known(&["/w/f.txt"]), &debouncer);
+        assert!(removed.is_empty());
+        assert_eq!(paths(&changed), [Path::new("/w/f.txt")]);
+    }
+}
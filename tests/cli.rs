@@ -0,0 +1,169 @@
+// End-to-end checks running the hash-forge binary
+use std::{fs, path::Path, process::Command};
+
+fn hash_forge(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_hash-forge"))
+        .args(args)
+        .output()
+        .expect("failed to run hash-forge");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (output.status.success(), stdout.trim().to_string())
+}
+
+fn path_arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn test_verify_multiformats_round_trip() {
+    let temp = tempfile::tempdir().unwrap();
+    let file = temp.path().join("f.txt");
+    fs::write(&file, "hello\n").unwrap();
+    let file = path_arg(&file);
+
+    for algorithm in ["sha256", "blake3", "sha3-512"] {
+        for form in ["--cid", "--multihash"] {
+            let (ok, expected) = hash_forge(&["file", "-p", file, "-a", algorithm, form, "-q"]);
+            assert!(ok, "{algorithm} {form}");
+
+            // The algorithm comes from the multicodec code
+            let (ok, _) = hash_forge(&["verify", "-f", file, "-e", &expected, "-q"]);
+            assert!(ok, "verify {algorithm} {form}: {expected}");
+            let (ok, _) = hash_forge(&["verify", "-f", file, "-e", &expected, "-a", "auto", "-q"]);
+            assert!(ok, "verify auto {algorithm} {form}: {expected}");
+        }
+    }
+
+    let (_, cid) = hash_forge(&["text", "-i", "other", "--cid", "-q"]);
+    let (ok, _) = hash_forge(&["verify", "-f", file, "-e", &cid, "-q"]);
+    assert!(!ok);
+}
+
+#[test]
+fn test_diff_exit_status() {
+    let temp = tempfile::tempdir().unwrap();
+    let dir = temp.path().join("site");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("index.html"), "v1").unwrap();
+    let manifest = temp.path().join("SUMS");
+    let (ok, sums) = hash_forge(&["manifest", "-d", path_arg(&dir)]);
+    assert!(ok);
+    fs::write(&manifest, sums + "\n").unwrap();
+
+    let status = |new: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_hash-forge"))
+            .args(["diff", "--status", path_arg(&manifest), path_arg(new)])
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+    assert_eq!(status(&dir), Some(0));
+    fs::write(dir.join("index.html"), "v2").unwrap();
+    assert_eq!(status(&dir), Some(1));
+    assert_eq!(status(&temp.path().join("missing.sums")), Some(2));
+}
+
+/// Run a command with `--output json` and parse its report
+fn json_report(args: &[&str]) -> serde_json::Value {
+    let (_, stdout) = hash_forge(&[&["--output", "json"], args].concat());
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{args:?}: {e}\n{stdout}"))
+}
+
+/// Field names of a JSON object, sorted
+fn fields(value: &serde_json::Value) -> Vec<&str> {
+    let mut names: Vec<_> = value
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_json_reports() {
+    let temp = tempfile::tempdir().unwrap();
+    let file = temp.path().join("f.txt");
+    fs::write(&file, "hello\n").unwrap();
+    let file = path_arg(&file);
+
+    let text = json_report(&["text", "-i", "hello"]);
+    assert_eq!(text["schema_version"], 1);
+    assert_eq!(text["command"], "text");
+    assert_eq!(
+        fields(&text),
+        [
+            "algorithm",
+            "command",
+            "digest",
+            "elapsed_ms",
+            "encoding",
+            "input",
+            "schema_version"
+        ]
+    );
+    assert_eq!(
+        fields(&text["input"]),
+        ["encoding", "size", "type", "value"]
+    );
+    assert_eq!(
+        text["digest"],
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+
+    let (_, digest) = hash_forge(&["file", "-p", file, "-q"]);
+    let verify = json_report(&["verify", "-f", file, "-e", &digest]);
+    assert_eq!(verify["schema_version"], 1);
+    assert_eq!(verify["command"], "verify");
+    assert_eq!(
+        fields(&verify),
+        [
+            "algorithm",
+            "command",
+            "computed",
+            "detected",
+            "elapsed_ms",
+            "expected",
+            "input",
+            "schema_version",
+            "verdict"
+        ]
+    );
+    assert_eq!(
+        fields(&verify["input"]),
+        ["decompressed", "path", "range", "size", "type"]
+    );
+    assert_eq!(verify["verdict"], "pass");
+
+    let manifest = temp.path().join("SUMS");
+    fs::write(&manifest, format!("{digest}  {file}\n")).unwrap();
+    let check = json_report(&["check", path_arg(&manifest)]);
+    assert_eq!(check["schema_version"], 1);
+    assert_eq!(check["command"], "check");
+    assert_eq!(
+        fields(&check),
+        [
+            "command",
+            "elapsed_ms",
+            "manifests",
+            "schema_version",
+            "verdict"
+        ]
+    );
+    let checked = &check["manifests"][0];
+    assert_eq!(fields(checked), ["ok", "results", "source", "summary"]);
+    assert_eq!(fields(&checked["results"][0]), ["line", "path", "status"]);
+    assert_eq!(
+        fields(&checked["summary"]),
+        [
+            "improperly_formatted",
+            "mismatched",
+            "properly_formatted",
+            "unreadable",
+            "verified"
+        ]
+    );
+    assert_eq!(check["verdict"], "pass");
+}